@@ -0,0 +1,595 @@
+pub mod significance;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::fmt;
+use std::io::BufReader;
+use std::io::prelude::*;
+use format::compression;
+use format::csv::{self, CsvFormat};
+use format::feature_names::FeatureNames;
+use metric;
+use train::dataset::DataSet;
+use train::{self, model, Evaluate};
+use util::{exit_on_error, Error, ParseResult, Result, Value};
+use self::significance::*;
+
+struct CompareParameter<'a> {
+    test_file_path: &'a str,
+    a_file_path: &'a str,
+    b_file_path: &'a str,
+    // Whether the files of A and B are score files instead of models.
+    scores: bool,
+    csv_format: CsvFormat,
+    regroup_queries: bool,
+    metrics: Vec<&'a str>,
+    metric_k: usize,
+    trials: usize,
+    samples: usize,
+    confidence: f64,
+    seed: usize,
+    per_query: bool,
+}
+
+impl<'a> CompareParameter<'a> {
+    pub fn parse(matches: &'a ArgMatches<'a>) -> CompareParameter<'a> {
+        let metric_k = value_t!(matches.value_of("metric-k"), usize)
+            .unwrap_or_else(|e| e.exit());
+        let metrics: Vec<&str> = matches.values_of("metric").unwrap().collect();
+        for &spec in metrics.iter() {
+            if let Err(e) = metric::parse(spec, metric_k) {
+                exit_on_error(e);
+            }
+        }
+        let trials = value_t!(matches.value_of("trials"), usize)
+            .unwrap_or_else(|e| e.exit());
+        let samples = value_t!(matches.value_of("samples"), usize)
+            .unwrap_or_else(|e| e.exit());
+        let confidence = value_t!(matches.value_of("confidence"), f64)
+            .unwrap_or_else(|e| e.exit());
+        let seed = value_t!(matches.value_of("seed"), usize)
+            .unwrap_or_else(|e| e.exit());
+
+        if confidence <= 0.0 || confidence >= 1.0 {
+            exit_on_error(Error::Config(
+                format!("Confidence should be in (0, 1): {}", confidence),
+            ));
+        }
+        if samples == 0 {
+            exit_on_error(Error::Config(
+                "Samples should be positive".to_string(),
+            ));
+        }
+
+        CompareParameter {
+            test_file_path: matches.value_of("test-file").unwrap(),
+            a_file_path: matches.value_of("a-file").unwrap(),
+            b_file_path: matches.value_of("b-file").unwrap(),
+            scores: matches.is_present("scores"),
+            csv_format: CsvFormat::from_matches(matches),
+            regroup_queries: matches.is_present("regroup-queries"),
+            metrics: metrics,
+            metric_k: metric_k,
+            trials: trials,
+            samples: samples,
+            confidence: confidence,
+            seed: seed,
+            per_query: matches.is_present("per-query"),
+        }
+    }
+
+    /// Loads the test set. The columns of CSV files are mapped to the
+    /// features of `feature_names` by name, if any.
+    fn load_test_set(&self, feature_names: FeatureNames) -> Result<DataSet> {
+        let csv_format =
+            self.csv_format.clone().with_feature_names(feature_names);
+        let test_set = DataSet::load_file_with(
+            self.test_file_path,
+            &csv_format,
+            self.regroup_queries,
+        )?;
+        Ok(test_set)
+    }
+
+    /// Returns the scores of the instances of the test set in the
+    /// score file.
+    fn score_file(
+        &self,
+        path: &str,
+        test_set: &DataSet,
+    ) -> Result<Vec<Value>> {
+        let scores = compression::open(path)
+            .and_then(load_scores)
+            .and_then(|lines| match_scores(lines, test_set))
+            .map_err(|e| e.in_file(path))?;
+        Ok(scores)
+    }
+
+    /// Returns the test set and the scores of its instances by A and
+    /// B.
+    fn load(&self) -> Result<(DataSet, Vec<Value>, Vec<Value>)> {
+        if self.scores {
+            let test_set = self.load_test_set(FeatureNames::new())?;
+            let scores_a = self.score_file(self.a_file_path, &test_set)?;
+            let scores_b = self.score_file(self.b_file_path, &test_set)?;
+            return Ok((test_set, scores_a, scores_b));
+        }
+
+        let model_a = model::load_file(self.a_file_path)?;
+        let model_b = model::load_file(self.b_file_path)?;
+        let names_a = model_a.feature_names().cloned().unwrap_or_default();
+        let names_b = model_b.feature_names().cloned().unwrap_or_default();
+        if !names_a.is_empty() && !names_b.is_empty() && names_a != names_b {
+            Err(Error::Config(
+                "The models have different features, \
+                 compare their score files instead"
+                    .to_string(),
+            ))?;
+        }
+        let names = if names_a.is_empty() { names_b } else { names_a };
+
+        let test_set = self.load_test_set(names)?;
+        let scores_a = scores(&*model_a, &test_set);
+        let scores_b = scores(&*model_b, &test_set);
+        Ok((test_set, scores_a, scores_b))
+    }
+
+    pub fn execute(&self) -> Result<()> {
+        let (test_set, scores_a, scores_b) = self.load()?;
+
+        for spec in self.metrics.iter() {
+            // The specs are valid.
+            let metric = metric::parse(spec, self.metric_k).unwrap();
+            let a = test_set.measure_queries(&scores_a, &metric);
+            let b = test_set.measure_queries(&scores_b, &metric);
+
+            if self.per_query {
+                let qids = test_set.query_iter().map(|(qid, _)| qid);
+                for ((qid, a), b) in qids.zip(a.iter()).zip(b.iter()) {
+                    println!(
+                        "{}\t{}\t{:.4}\t{:.4}\t{:.4}",
+                        metric.name(),
+                        test_set.qid_name(qid),
+                        a,
+                        b,
+                        b - a
+                    );
+                }
+            }
+
+            let comparison = Comparison::new(
+                &metric.name(),
+                &a,
+                &b,
+                self.trials,
+                self.samples,
+                self.confidence,
+                self.seed,
+            );
+            println!("{}", comparison);
+        }
+        Ok(())
+    }
+}
+
+/// Returns the scores of the instances of the data set by the model.
+fn scores(model: &dyn Evaluate, dataset: &DataSet) -> Vec<Value> {
+    dataset
+        .iter()
+        .map(|instance| model.evaluate(instance))
+        .collect()
+}
+
+/// A line of a score file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoreLine {
+    /// 1-based line number.
+    pub line: usize,
+    /// The qid and the document id, if written with `--with-ids`.
+    pub ids: Option<(String, String)>,
+    pub score: Value,
+}
+
+/// Loads the scores written by the predict command, one score per
+/// line, which may be prefixed by the qid and the document id
+/// separated by tabs. Empty lines are skipped.
+///
+/// # Examples
+///
+/// ```
+/// use rforests::compare::load_scores;
+///
+/// let s = "0.5\n-1.25\n\n1\tGX000-00-0000000\t3\n";
+/// let lines = load_scores(s.as_bytes()).unwrap();
+/// let scores: Vec<f64> = lines.iter().map(|line| line.score).collect();
+/// assert_eq!(scores, vec![0.5, -1.25, 3.0]);
+/// assert_eq!(lines[2].line, 4);
+/// assert_eq!(
+///     lines[2].ids,
+///     Some(("1".to_string(), "GX000-00-0000000".to_string()))
+/// );
+/// ```
+pub fn load_scores<R: Read>(reader: R) -> ParseResult<Vec<ScoreLine>> {
+    fn parse(line: &str) -> ParseResult<(Option<(String, String)>, Value)> {
+        let fields: Vec<&str> = line.trim().split('\t').collect();
+        let (ids, field) = match fields.len() {
+            1 => (None, fields[0]),
+            3 => {
+                let ids = (fields[0].to_string(), fields[1].to_string());
+                (Some(ids), fields[2])
+            }
+            _ => {
+                return Err(Error::parse(
+                    line,
+                    "Expect \"<score>\" or \"<qid>\t<docid>\t<score>\"",
+                ))
+            }
+        };
+        let score = field
+            .parse::<Value>()
+            .map_err(|_| Error::parse(field, "Invalid score"))?;
+        Ok((ids, score))
+    }
+
+    let mut lines = Vec::new();
+    for (index, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (ids, score) = parse(&line).map_err(|e| e.at_line(index + 1))?;
+        lines.push(ScoreLine {
+            line: index + 1,
+            ids: ids,
+            score: score,
+        });
+    }
+    Ok(lines)
+}
+
+/// Returns the scores of the lines of a score file for the instances
+/// of the data set. The lines are in the order of the data file, see
+/// `DataSet::file_order`, and the scores in the order of the data set.
+/// It is an error if the numbers differ, or if the ids of a line are
+/// not those of its instance, see `DataSet::id_iter`.
+pub fn match_scores(
+    lines: Vec<ScoreLine>,
+    dataset: &DataSet,
+) -> ParseResult<Vec<Value>> {
+    if lines.len() != dataset.len() {
+        return Err(Error::Parse {
+            file: None,
+            line: None,
+            token: None,
+            message: format!(
+                "{} scores for {} instances",
+                lines.len(),
+                dataset.len()
+            ),
+        });
+    }
+
+    let ids: Vec<(String, String)> = dataset.id_iter().collect();
+    let mut scores = vec![0.0; lines.len()];
+    for (line, index) in lines.into_iter().zip(dataset.file_order()) {
+        let ids = &ids[index];
+        if let Some((qid, docid)) = line.ids {
+            if (qid.as_str(), docid.as_str()) != (&ids.0, &ids.1) {
+                let message = format!(
+                    "Expect qid {} and document id {} of the instance",
+                    ids.0,
+                    ids.1
+                );
+                let token = format!("{}\t{}", qid, docid);
+                return Err(Error::parse(&token, message).at_line(line.line));
+            }
+        }
+        scores[index] = line.score;
+    }
+    Ok(scores)
+}
+
+/// The comparison of the scores of B with the scores of A on the same
+/// queries with a metric.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    name: String,
+    nqueries: usize,
+    mean_a: f64,
+    mean_b: f64,
+    // (wins, losses, ties) of B.
+    counts: (usize, usize, usize),
+    t_test: Option<TTest>,
+    trials: usize,
+    randomization_p_value: f64,
+    samples: usize,
+    confidence: f64,
+    interval: (f64, f64),
+}
+
+impl Comparison {
+    /// Compares the per-query scores `b` with `a` by the differences
+    /// b - a. The randomization test runs `trials` trials, and the
+    /// bootstrap confidence interval draws `samples` samples, both
+    /// with the seed.
+    pub fn new(
+        name: &str,
+        a: &[f64],
+        b: &[f64],
+        trials: usize,
+        samples: usize,
+        confidence: f64,
+        seed: usize,
+    ) -> Comparison {
+        assert_eq!(a.len(), b.len());
+        let deltas: Vec<f64> =
+            a.iter().zip(b.iter()).map(|(a, b)| b - a).collect();
+        let mean = |values: &[f64]| if values.is_empty() {
+            0.0
+        } else {
+            values.iter().sum::<f64>() / values.len() as f64
+        };
+
+        Comparison {
+            name: name.to_string(),
+            nqueries: a.len(),
+            mean_a: mean(a),
+            mean_b: mean(b),
+            counts: win_loss_tie(a, b),
+            t_test: paired_t_test(&deltas),
+            trials: trials,
+            randomization_p_value: randomization_test(&deltas, trials, seed),
+            samples: samples,
+            confidence: confidence,
+            interval: bootstrap_ci(&deltas, confidence, samples, seed),
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn write_param<T: fmt::Display>(
+            f: &mut fmt::Formatter,
+            name: &str,
+            value: T,
+        ) -> fmt::Result {
+            writeln!(f, "{:<20}: {}", name, value)
+        }
+
+        write_param(f, "Metric", &self.name)?;
+        write_param(f, "Queries", self.nqueries)?;
+        write_param(f, "Mean of A", format!("{:.4}", self.mean_a))?;
+        write_param(f, "Mean of B", format!("{:.4}", self.mean_b))?;
+        write_param(
+            f,
+            "Mean of B - A",
+            format!("{:.4}", self.mean_b - self.mean_a),
+        )?;
+        write_param(
+            f,
+            "Wins/losses/ties",
+            format!("{}/{}/{}", self.counts.0, self.counts.1, self.counts.2),
+        )?;
+        match self.t_test {
+            Some(test) => write_param(
+                f,
+                "Paired t-test",
+                format!(
+                    "t = {:.4}, df = {}, p = {:.4}",
+                    test.t,
+                    test.df,
+                    test.p_value
+                ),
+            )?,
+            None => write_param(f, "Paired t-test", "too few queries")?,
+        }
+        write_param(
+            f,
+            "Randomization test",
+            format!(
+                "p = {:.4}, {} trials",
+                self.randomization_p_value,
+                self.trials
+            ),
+        )?;
+        write_param(
+            f,
+            // Rounds off the error of the percentage, as 0.9 * 100.
+            &format!(
+                "Bootstrap {}% CI",
+                (self.confidence * 1000.0).round() / 10.0
+            ),
+            format!(
+                "[{:.4}, {:.4}], {} samples",
+                self.interval.0,
+                self.interval.1,
+                self.samples
+            ),
+        )
+    }
+}
+
+pub fn main<'a>(matches: &ArgMatches<'a>) {
+    let param = CompareParameter::parse(matches);
+    if let Err(e) = param.execute() {
+        exit_on_error(e);
+    }
+}
+
+/// Returns the compare command.
+pub fn clap_command<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("compare")
+        .about("Compare two models with paired significance tests")
+        .arg(
+            Arg::with_name("test-file")
+                .short("T")
+                .long("test")
+                .value_name("FILE")
+                .takes_value(true)
+                .empty_values(false)
+                .required(true)
+                .display_order(1)
+                .help("Testing file"),
+        )
+        .arg(
+            Arg::with_name("a-file")
+                .short("a")
+                .long("model-a")
+                .value_name("FILE")
+                .takes_value(true)
+                .empty_values(false)
+                .required(true)
+                .display_order(2)
+                .help("Model file of A, the baseline"),
+        )
+        .arg(
+            Arg::with_name("b-file")
+                .short("b")
+                .long("model-b")
+                .value_name("FILE")
+                .takes_value(true)
+                .empty_values(false)
+                .required(true)
+                .display_order(3)
+                .help("Model file of B, compared with A"),
+        )
+        .arg(
+            Arg::with_name("scores")
+                .long("scores")
+                .display_order(4)
+                .help("Read the files of A and B as score files written by the predict command, instead of models. The ids of the lines written with --with-ids must match the test set"),
+        )
+        .arg(
+            Arg::with_name("metric")
+                .short("m")
+                .long("metric")
+                .value_name("METRICS")
+                .takes_value(true)
+                .multiple(true)
+                .require_delimiter(true)
+                .default_value("NDCG")
+                .display_order(5)
+                .help("Comma separated metrics, each as NAME[@k][:gain=exp|linear][:max=LABEL], for example NDCG@10,MAP"),
+        )
+        .arg(
+            Arg::with_name("metric-k")
+                .short("k")
+                .long("metric-k")
+                .value_name("NUM")
+                .default_value("10")
+                .display_order(6)
+                .help("K value for the metrics without @k"),
+        )
+        .arg(
+            Arg::with_name("trials")
+                .long("trials")
+                .value_name("NUM")
+                .default_value("10000")
+                .display_order(7)
+                .help("Number of trials of the randomization test"),
+        )
+        .arg(
+            Arg::with_name("samples")
+                .long("samples")
+                .value_name("NUM")
+                .default_value("10000")
+                .display_order(8)
+                .help("Number of bootstrap samples"),
+        )
+        .arg(
+            Arg::with_name("confidence")
+                .long("confidence")
+                .value_name("LEVEL")
+                .default_value("0.95")
+                .display_order(9)
+                .help("Confidence level of the bootstrap interval"),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .value_name("NUM")
+                .default_value("1")
+                .display_order(10)
+                .help("Seed of the randomization test and the bootstrap"),
+        )
+        .arg(
+            Arg::with_name("per-query")
+                .long("per-query")
+                .display_order(11)
+                .help("Output \"<metric>\\t<qid>\\t<A>\\t<B>\\t<B - A>\" for each query"),
+        )
+        .args(&csv::clap_args(12))
+        .arg(train::regroup_queries_arg().display_order(16))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_comparison() {
+        let a = [0.5, 0.2, 0.3, 0.4];
+        let b = [0.6, 0.3, 0.3, 0.6];
+        let comparison =
+            Comparison::new("NDCG@10", &a, &b, 1000, 1000, 0.9, 1);
+        assert_eq!(comparison.counts, (3, 0, 1));
+        assert_eq!(comparison.t_test.unwrap().df, 3);
+        assert!(comparison.interval.0 <= 0.1 && 0.1 <= comparison.interval.1);
+
+        let s = comparison.to_string();
+        assert!(s.starts_with("Metric              : NDCG@10\n"));
+        assert!(s.contains("Mean of B - A       : 0.1000\n"));
+        assert!(s.contains("Wins/losses/ties    : 3/0/1\n"));
+        assert!(s.contains("Bootstrap 90% CI    : ["));
+
+        let comparison =
+            Comparison::new("NDCG@10", &[0.5], &[0.5], 10, 10, 0.9, 1);
+        assert!(comparison.to_string().contains("too few queries"));
+    }
+
+    #[test]
+    fn test_match_scores() {
+        let s = "3.0 qid:1 1:5.0 # 1A
+2.0 qid:1 1:7.0
+1.0 qid:2 1:3.0";
+        let dataset = DataSet::load(::std::io::Cursor::new(s)).unwrap();
+
+        let lines = load_scores("0.5\n1\t1\t0.25\n2\t0\t1".as_bytes());
+        let scores = match_scores(lines.unwrap(), &dataset).unwrap();
+        assert_eq!(scores, vec![0.5, 0.25, 1.0]);
+
+        let lines = load_scores("0.5\n0.25\n".as_bytes()).unwrap();
+        match match_scores(lines, &dataset) {
+            Err(Error::Parse { line, message, .. }) => {
+                assert_eq!(line, None);
+                assert_eq!(message, "2 scores for 3 instances");
+            }
+            _ => panic!("Expected a parse error"),
+        }
+
+        // The scores of another order of the documents.
+        let s = "1\t1\t0.25\n1\t1A\t0.5\n2\t0\t1\n";
+        let lines = load_scores(s.as_bytes()).unwrap();
+        match match_scores(lines, &dataset) {
+            Err(Error::Parse { line, token, .. }) => {
+                assert_eq!(line, Some(1));
+                assert_eq!(token, Some("1\t1".to_string()));
+            }
+            _ => panic!("Expected a parse error"),
+        }
+
+        match load_scores("0.5\n1\t0.25\n".as_bytes()) {
+            Err(Error::Parse { line, .. }) => assert_eq!(line, Some(2)),
+            _ => panic!("Expected a parse error"),
+        }
+
+        // The lines follow the data file if the queries are regrouped.
+        let s = "3.0 qid:1 1:5.0
+1.0 qid:2 1:3.0
+2.0 qid:1 1:7.0";
+        let dataset = DataSet::load_with(s.as_bytes(), true).unwrap();
+        let lines = load_scores("0.5\n2\t0\t1\n0.25".as_bytes());
+        let scores = match_scores(lines.unwrap(), &dataset).unwrap();
+        assert_eq!(scores, vec![0.5, 0.25, 1.0]);
+    }
+}
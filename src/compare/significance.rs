@@ -0,0 +1,276 @@
+use rand::{Rng, SeedableRng, StdRng};
+
+/// The result of a paired t-test.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TTest {
+    pub t: f64,
+    pub df: usize,
+    // Two-sided p-value.
+    pub p_value: f64,
+}
+
+/// Runs a paired t-test on the differences of the pairs, which tests
+/// whether their mean is 0. Returns None if there are fewer than two
+/// differences.
+///
+/// # Examples
+///
+/// ```
+/// use rforests::compare::significance::paired_t_test;
+///
+/// let test = paired_t_test(&[0.1, 0.2, 0.3, 0.2]).unwrap();
+/// assert_eq!(test.df, 3);
+/// assert!((test.t - 4.8990).abs() < 1e-4);
+/// assert!((test.p_value - 0.0163).abs() < 1e-4);
+/// ```
+pub fn paired_t_test(deltas: &[f64]) -> Option<TTest> {
+    let n = deltas.len();
+    if n < 2 {
+        return None;
+    }
+
+    let mean = mean(deltas);
+    let variance = deltas
+        .iter()
+        .map(|delta| (delta - mean) * (delta - mean))
+        .sum::<f64>() / (n - 1) as f64;
+    let df = n - 1;
+    if variance == 0.0 {
+        // Every difference is the mean.
+        let (t, p_value) = if mean == 0.0 {
+            (0.0, 1.0)
+        } else {
+            (mean.signum() * ::std::f64::INFINITY, 0.0)
+        };
+        return Some(TTest {
+            t: t,
+            df: df,
+            p_value: p_value,
+        });
+    }
+
+    let t = mean / (variance / n as f64).sqrt();
+    let df_f = df as f64;
+    // P(|T| > |t|) of Student's t distribution.
+    let p_value = incomplete_beta(df_f / 2.0, 0.5, df_f / (df_f + t * t));
+    Some(TTest {
+        t: t,
+        df: df,
+        p_value: p_value,
+    })
+}
+
+/// Runs a paired randomization test on the differences of the pairs.
+/// Each trial flips the sign of every difference with probability
+/// 1/2, and the two-sided p-value is the fraction of the trials whose
+/// mean is at least as far from 0 as the observed mean, counting the
+/// observation itself.
+///
+/// # Examples
+///
+/// ```
+/// use rforests::compare::significance::randomization_test;
+///
+/// let p = randomization_test(&[0.1, 0.2, 0.3, 0.2], 10000, 1);
+/// // 2 of the 16 sign flips are as extreme.
+/// assert!((p - 0.125).abs() < 0.01);
+/// ```
+pub fn randomization_test(deltas: &[f64], trials: usize, seed: usize) -> f64 {
+    if deltas.is_empty() {
+        return 1.0;
+    }
+
+    let mut rng: StdRng = SeedableRng::from_seed(&[seed][..]);
+    let observed = mean(deltas).abs();
+    // Tolerates the rounding errors of the sums.
+    let threshold = observed - 1e-12;
+    let mut extreme = 0;
+    for _ in 0..trials {
+        let sum: f64 = deltas
+            .iter()
+            .map(|&delta| if rng.gen() { delta } else { -delta })
+            .sum();
+        if (sum / deltas.len() as f64).abs() >= threshold {
+            extreme += 1;
+        }
+    }
+    (extreme + 1) as f64 / (trials + 1) as f64
+}
+
+/// Returns the bootstrap percentile confidence interval of the mean of
+/// the differences at the `confidence` level, for example 0.95. Each
+/// sample draws as many differences as there are with replacement.
+///
+/// # Examples
+///
+/// ```
+/// use rforests::compare::significance::bootstrap_ci;
+///
+/// let deltas = [0.1, 0.2, 0.3, 0.2];
+/// let (lower, upper) = bootstrap_ci(&deltas, 0.95, 10000, 1);
+/// assert!(0.1 <= lower && lower < 0.2);
+/// assert!(0.2 < upper && upper <= 0.3);
+/// ```
+pub fn bootstrap_ci(
+    deltas: &[f64],
+    confidence: f64,
+    samples: usize,
+    seed: usize,
+) -> (f64, f64) {
+    assert!(confidence > 0.0 && confidence < 1.0);
+    if deltas.is_empty() || samples == 0 {
+        return (0.0, 0.0);
+    }
+
+    let mut rng: StdRng = SeedableRng::from_seed(&[seed][..]);
+    let mut means: Vec<f64> = (0..samples)
+        .map(|_| {
+            let sum: f64 = (0..deltas.len())
+                .map(|_| deltas[rng.gen_range(0, deltas.len())])
+                .sum();
+            sum / deltas.len() as f64
+        })
+        .collect();
+    means.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let alpha = (1.0 - confidence) / 2.0;
+    let lower = (alpha * samples as f64).floor() as usize;
+    let upper = ((1.0 - alpha) * samples as f64).ceil() as usize;
+    (means[lower.min(samples - 1)], means[upper.max(1) - 1])
+}
+
+/// Counts the pairs where the second score is greater (wins), less
+/// (losses) and equal (ties).
+///
+/// # Examples
+///
+/// ```
+/// use rforests::compare::significance::win_loss_tie;
+///
+/// let a = [0.5, 0.2, 0.3];
+/// let b = [0.6, 0.1, 0.3];
+/// assert_eq!(win_loss_tie(&a, &b), (1, 1, 1));
+/// ```
+pub fn win_loss_tie(a: &[f64], b: &[f64]) -> (usize, usize, usize) {
+    assert_eq!(a.len(), b.len());
+    let mut counts = (0, 0, 0);
+    for (score_a, score_b) in a.iter().zip(b.iter()) {
+        if score_b > score_a {
+            counts.0 += 1;
+        } else if score_b < score_a {
+            counts.1 += 1;
+        } else {
+            counts.2 += 1;
+        }
+    }
+    counts
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// The natural logarithm of the gamma function, by the Lanczos
+/// approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let mut series = 1.000000000190015;
+    for (i, coefficient) in COEFFICIENTS.iter().enumerate() {
+        series += coefficient / (x + 1.0 + i as f64);
+    }
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+/// The regularized incomplete beta function I_x(a, b).
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() +
+        b * (1.0 - x).ln())
+        .exp();
+    // The continued fraction converges quickly on this side.
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_fraction(a, b, x) / a
+    } else {
+        1.0 - front * beta_fraction(b, a, 1.0 - x) / b
+    }
+}
+
+/// The continued fraction of the incomplete beta function, by the
+/// modified Lentz's method.
+fn beta_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const EPSILON: f64 = 1e-14;
+    const TINY: f64 = 1e-300;
+
+    let fix = |value: f64| if value.abs() < TINY { TINY } else { value };
+    let mut c = 1.0;
+    let mut d = 1.0 / fix(1.0 - (a + b) * x / (a + 1.0));
+    let mut result = d;
+    for m in 1..1000 {
+        let m = m as f64;
+        let m2 = 2.0 * m;
+
+        // The even step.
+        let numerator = m * (b - m) * x / ((a + m2 - 1.0) * (a + m2));
+        d = 1.0 / fix(1.0 + numerator * d);
+        c = fix(1.0 + numerator / c);
+        result *= d * c;
+
+        // The odd step.
+        let numerator =
+            -(a + m) * (a + b + m) * x / ((a + m2) * (a + m2 + 1.0));
+        d = 1.0 / fix(1.0 + numerator * d);
+        c = fix(1.0 + numerator / c);
+        let delta = d * c;
+        result *= delta;
+
+        if (delta - 1.0).abs() < EPSILON {
+            break;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paired_t_test() {
+        let a = [0.42, 0.35, 0.61, 0.27, 0.55, 0.48, 0.39, 0.52];
+        let b = [0.45, 0.33, 0.66, 0.31, 0.55, 0.52, 0.44, 0.50];
+        let deltas: Vec<f64> =
+            a.iter().zip(b.iter()).map(|(a, b)| b - a).collect();
+        let test = paired_t_test(&deltas).unwrap();
+        assert_eq!(test.df, 7);
+        assert!((test.t - 2.0055).abs() < 1e-4);
+        assert!((test.p_value - 0.0849).abs() < 1e-4);
+
+        assert_eq!(paired_t_test(&[0.1]), None);
+        assert_eq!(paired_t_test(&[0.0, 0.0]).unwrap().p_value, 1.0);
+        assert_eq!(paired_t_test(&[0.1, 0.1]).unwrap().p_value, 0.0);
+    }
+
+    #[test]
+    fn test_incomplete_beta() {
+        assert!((incomplete_beta(2.0, 3.0, 0.4) - 0.5248).abs() < 1e-8);
+        // The t distribution with 1 degree of freedom is Cauchy, where
+        // P(|T| > 1) = 0.5.
+        assert!((incomplete_beta(0.5, 0.5, 0.5) - 0.5).abs() < 1e-8);
+        assert!((incomplete_beta(1.0, 1.0, 0.3) - 0.3).abs() < 1e-8);
+    }
+}
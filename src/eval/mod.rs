@@ -0,0 +1,405 @@
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use format::trec::{Qrels, Run};
+use metric::{self, Measure};
+use util::{exit_on_error, Error, ParseResult, Result};
+
+struct EvalParameter<'a> {
+    run_file_path: &'a str,
+    qrels_file_path: &'a str,
+    output_file_path: Option<&'a str>,
+    metrics: Vec<&'a str>,
+    metric_k: usize,
+    per_query: bool,
+}
+
+impl<'a> EvalParameter<'a> {
+    pub fn parse(matches: &'a ArgMatches<'a>) -> EvalParameter<'a> {
+        let metric_k = value_t!(matches.value_of("metric-k"), usize)
+            .unwrap_or_else(|e| e.exit());
+        let metrics: Vec<&str> = matches.values_of("metric").unwrap().collect();
+        for &spec in metrics.iter() {
+            if let Err(e) = Metric::parse(spec, metric_k) {
+                exit_on_error(e);
+            }
+        }
+
+        EvalParameter {
+            run_file_path: matches.value_of("run-file").unwrap(),
+            qrels_file_path: matches.value_of("qrels-file").unwrap(),
+            output_file_path: matches.value_of("output-file"),
+            metrics: metrics,
+            metric_k: metric_k,
+            per_query: matches.is_present("per-query"),
+        }
+    }
+
+    pub fn execute(&self) -> Result<()> {
+        let run = Run::load_file(self.run_file_path)?;
+        let qrels = Qrels::load_file(self.qrels_file_path)?;
+
+        // The specs are valid.
+        let metrics: Vec<Metric> = self.metrics
+            .iter()
+            .map(|spec| Metric::parse(spec, self.metric_k).unwrap())
+            .collect();
+        let evaluation = evaluate(&run, &qrels, &metrics);
+
+        match self.output_file_path {
+            Some(path) => {
+                let output = File::create(path)
+                    .map_err(|e| Error::from(e).in_file(path))?;
+                evaluation.write(output, self.per_query)
+            }
+            None => {
+                let stdout = ::std::io::stdout();
+                evaluation.write(stdout.lock(), self.per_query)
+            }
+        }
+    }
+}
+
+/// A metric of a run. MAP and RR without @k have no cutoff, as `map`
+/// and `recip_rank` of trec_eval, so they are measured at the number
+/// of the retrieved documents of each query.
+pub enum Metric {
+    Cut(Box<dyn Measure>),
+    /// The name of MAP or RR.
+    Uncut(String),
+}
+
+impl Metric {
+    /// Parses a metric spec, see `metric::parse`. k defaults to
+    /// `default_k`, except for MAP and RR.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rforests::eval::Metric;
+    ///
+    /// assert_eq!(Metric::parse("map", 10).unwrap().name(), "MAP");
+    /// assert_eq!(Metric::parse("RR@5", 10).unwrap().name(), "RR@5");
+    /// assert_eq!(Metric::parse("NDCG", 10).unwrap().name(), "NDCG@10");
+    /// ```
+    pub fn parse(spec: &str, default_k: usize) -> ParseResult<Metric> {
+        let metric = metric::parse(spec, default_k)?;
+        let head = spec.split(':').next().unwrap();
+        let name = head.to_uppercase();
+        if name == "MAP" || name == "RR" {
+            Ok(Metric::Uncut(name))
+        } else {
+            Ok(Metric::Cut(metric))
+        }
+    }
+
+    /// Name of the metric. For display.
+    pub fn name(&self) -> String {
+        match *self {
+            Metric::Cut(ref metric) => metric.name(),
+            Metric::Uncut(ref name) => name.clone(),
+        }
+    }
+
+    /// Measures the labels of a query, of which the first `nretrieved`
+    /// are of the retrieved documents.
+    fn measure(&self, labels: &[f64], nretrieved: usize) -> f64 {
+        match *self {
+            Metric::Cut(ref metric) => metric.measure(labels),
+            Metric::Uncut(ref name) => {
+                // The name is valid.
+                metric::new(name, nretrieved).unwrap().measure(labels)
+            }
+        }
+    }
+}
+
+/// The scores of the metrics on each query of a run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Evaluation {
+    names: Vec<String>,
+    queries: Vec<(String, Vec<f64>)>,
+}
+
+impl Evaluation {
+    /// Returns the names of the metrics.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Returns the evaluated queries in ascending order of the qids,
+    /// each with the scores in the order of the metrics.
+    pub fn queries(&self) -> &[(String, Vec<f64>)] {
+        &self.queries
+    }
+
+    /// Returns the average scores over the queries, which are 0 if no
+    /// query is evaluated.
+    pub fn mean(&self) -> Vec<f64> {
+        let mut means = vec![0.0; self.names.len()];
+        for &(_, ref scores) in self.queries.iter() {
+            for (mean, score) in means.iter_mut().zip(scores.iter()) {
+                *mean += score;
+            }
+        }
+        if !self.queries.is_empty() {
+            for mean in means.iter_mut() {
+                *mean /= self.queries.len() as f64;
+            }
+        }
+        means
+    }
+
+    /// Writes a line "<metric>\t<qid>\t<score>" for each metric of
+    /// each query if `per_query` is true, then the averages with the
+    /// qid "all", and the number of queries, as trec_eval does.
+    pub fn write<W: Write>(&self, writer: W, per_query: bool) -> Result<()> {
+        let mut writer = BufWriter::new(writer);
+        if per_query {
+            for &(ref qid, ref scores) in self.queries.iter() {
+                for (name, score) in self.names.iter().zip(scores.iter()) {
+                    writeln!(writer, "{:<22}\t{}\t{:.4}", name, qid, score)?;
+                }
+            }
+        }
+        writeln!(writer, "{:<22}\tall\t{}", "num_q", self.queries.len())?;
+        for (name, mean) in self.names.iter().zip(self.mean().iter()) {
+            writeln!(writer, "{:<22}\tall\t{:.4}", name, mean)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Measures the ranking of each query of the run with the metrics.
+/// Only the queries judged in the qrels are evaluated, and the
+/// documents not judged are not relevant.
+///
+/// The labels of the ranking are padded by zeros to the largest k of
+/// the metrics, and followed by the labels of the judged documents not
+/// retrieved. So the ideal DCG of NDCG and the relevant documents of
+/// MAP count every judged document, and a metric at k divides by k
+/// even if fewer documents are retrieved. The metrics without cutoff
+/// only rank the retrieved documents.
+///
+/// # Examples
+///
+/// ```
+/// use rforests::eval::{evaluate, Metric};
+/// use rforests::format::trec::{Qrels, Run};
+///
+/// let run = "1 Q0 a 1 3.0 bm25
+/// 1 Q0 b 2 2.0 bm25
+/// 2 Q0 c 1 1.0 bm25
+/// 3 Q0 d 1 1.0 bm25";
+/// let qrels = "1 0 b 1
+/// 1 0 e 1
+/// 2 0 c 2";
+/// let run = Run::load(run.as_bytes()).unwrap();
+/// let qrels = Qrels::load(qrels.as_bytes()).unwrap();
+///
+/// let metrics = vec![
+///     Metric::parse("MAP", 10).unwrap(),
+///     Metric::parse("P@2", 10).unwrap(),
+/// ];
+/// let evaluation = evaluate(&run, &qrels, &metrics);
+/// assert_eq!(
+///     evaluation.queries(),
+///     &[
+///         ("1".to_string(), vec![0.25, 0.5]),
+///         ("2".to_string(), vec![1.0, 0.5]),
+///     ]
+/// );
+/// assert_eq!(evaluation.mean(), vec![0.625, 0.5]);
+/// ```
+pub fn evaluate(
+    run: &Run,
+    qrels: &Qrels,
+    metrics: &[Metric],
+) -> Evaluation {
+    let max_k = metrics
+        .iter()
+        .filter_map(|metric| match *metric {
+            Metric::Cut(ref metric) => Some(metric.get_k()),
+            Metric::Uncut(_) => None,
+        })
+        .max()
+        .unwrap_or(0);
+
+    let mut queries = Vec::new();
+    for (qid, docnos) in run.rankings() {
+        let judged = match qrels.get(qid) {
+            Some(judged) => judged,
+            None => continue,
+        };
+
+        let mut labels: Vec<f64> = docnos
+            .iter()
+            .map(|&docno| judged.get(docno).cloned().unwrap_or(0.0))
+            .collect();
+        while labels.len() < max_k {
+            labels.push(0.0);
+        }
+        let retrieved: HashSet<&str> = docnos.iter().cloned().collect();
+        let mut missed: Vec<f64> = judged
+            .iter()
+            .filter(|&(docno, _)| !retrieved.contains(docno.as_str()))
+            .map(|(_, &label)| label)
+            .collect();
+        // The order of a hash map is random.
+        missed.sort_by(|a, b| b.partial_cmp(a).unwrap());
+        labels.extend(missed);
+
+        let scores = metrics
+            .iter()
+            .map(|metric| metric.measure(&labels, docnos.len()))
+            .collect();
+        queries.push((qid.to_string(), scores));
+    }
+
+    Evaluation {
+        names: metrics.iter().map(|metric| metric.name()).collect(),
+        queries: queries,
+    }
+}
+
+pub fn main<'a>(matches: &ArgMatches<'a>) {
+    let param = EvalParameter::parse(matches);
+    if let Err(e) = param.execute() {
+        exit_on_error(e);
+    }
+}
+
+/// Returns the eval command.
+pub fn clap_command<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("eval")
+        .about("Evaluate a TREC run file with a qrels file")
+        .arg(
+            Arg::with_name("run-file")
+                .short("r")
+                .long("run")
+                .value_name("FILE")
+                .takes_value(true)
+                .empty_values(false)
+                .required(true)
+                .display_order(1)
+                .help("Run file, with a line \"<qid> Q0 <docno> <rank> <score> <tag>\" for each retrieved document"),
+        )
+        .arg(
+            Arg::with_name("qrels-file")
+                .short("q")
+                .long("qrels")
+                .value_name("FILE")
+                .takes_value(true)
+                .empty_values(false)
+                .required(true)
+                .display_order(2)
+                .help("Qrels file, with a line \"<qid> <iter> <docno> <label>\" for each judged document"),
+        )
+        .arg(
+            Arg::with_name("metric")
+                .short("m")
+                .long("metric")
+                .value_name("METRICS")
+                .takes_value(true)
+                .multiple(true)
+                .require_delimiter(true)
+                .default_value("NDCG")
+                .display_order(3)
+                .help("Comma separated metrics, each as NAME[@k][:gain=exp|linear][:max=LABEL], for example NDCG@10,MAP,P@5. MAP and RR without @k are not cut"),
+        )
+        .arg(
+            Arg::with_name("metric-k")
+                .short("k")
+                .long("metric-k")
+                .value_name("NUM")
+                .default_value("10")
+                .display_order(4)
+                .help("K value for the metrics without @k, except MAP and RR"),
+        )
+        .arg(
+            Arg::with_name("per-query")
+                .long("per-query")
+                .display_order(5)
+                .help("Output the scores of each query besides the averages"),
+        )
+        .arg(
+            Arg::with_name("output-file")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .takes_value(true)
+                .empty_values(false)
+                .display_order(6)
+                .help("Output file for the scores, defaults to stdout"),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate_missed() {
+        let run = Run::load("1 Q0 a 1 1.0 bm25\n".as_bytes()).unwrap();
+        let qrels = Qrels::load("1 0 a 1\n1 0 b 2\n".as_bytes()).unwrap();
+        let metrics = vec![
+            Metric::parse("NDCG@3", 10).unwrap(),
+            Metric::parse("RR", 10).unwrap(),
+        ];
+
+        // The unretrieved b is in the ideal DCG.
+        let evaluation = evaluate(&run, &qrels, &metrics);
+        let ideal = 3.0 + 1.0 / 3.0_f64.log2();
+        assert_eq!(evaluation.names(), &["NDCG@3", "RR"]);
+        assert_eq!(evaluation.mean(), vec![1.0 / ideal, 1.0]);
+
+        let mut output = Vec::new();
+        evaluation.write(&mut output, true).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "NDCG@3                \t1\t{:.4}
+RR                    \t1\t1.0000
+num_q                 \tall\t1
+NDCG@3                \tall\t{:.4}
+RR                    \tall\t1.0000
+",
+                1.0 / ideal,
+                1.0 / ideal
+            )
+        );
+    }
+
+    #[test]
+    fn test_evaluate_uncut() {
+        // The relevant a is retrieved at rank 12, and b is not
+        // retrieved.
+        let mut run = String::new();
+        for rank in 1..13 {
+            let docno = if rank == 12 {
+                "a".to_string()
+            } else {
+                rank.to_string()
+            };
+            let line = format!("1 Q0 {} {} {} bm25\n", docno, rank, -rank);
+            run.push_str(&line);
+        }
+        let run = Run::load(run.as_bytes()).unwrap();
+        let qrels = Qrels::load("1 0 a 1\n1 0 b 1\n".as_bytes()).unwrap();
+        let metrics = vec![
+            Metric::parse("MAP", 10).unwrap(),
+            Metric::parse("RR", 10).unwrap(),
+            Metric::parse("MAP@10", 10).unwrap(),
+            Metric::parse("RR@10", 10).unwrap(),
+        ];
+
+        let evaluation = evaluate(&run, &qrels, &metrics);
+        assert_eq!(
+            evaluation.mean(),
+            vec![1.0 / 12.0 / 2.0, 1.0 / 12.0, 0.0, 0.0]
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use flate2::read::MultiGzDecoder;
+use ruzstd::{BlockDecodingStrategy, FrameDecoder};
+use ruzstd::frame::ReadFrameHeaderError;
+use ruzstd::frame_decoder::FrameDecoderError;
+use std::fs::File;
+use std::io::{self, BufReader, Cursor};
+use std::io::prelude::*;
+use std::path::Path;
+use util::*;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+// Skippable frames have the magic numbers 0x184D2A50 to 0x184D2A5F.
+const SKIPPABLE_MAGIC: [u8; 3] = [0x2a, 0x4d, 0x18];
+
+/// Extensions of the compressed files.
+const EXTENSIONS: [&str; 3] = ["gz", "zst", "zstd"];
+
+/// Wraps a reader so that gzip and zstd compressed data, as told by
+/// the magic bytes at the start, is decompressed while being read.
+/// Other data is read as it is. The frames of a zstd stream are
+/// decoded one after another, as written by `pzstd` or by
+/// concatenating files.
+///
+/// # Examples
+///
+/// ```
+/// use rforests::format::compression::decompress;
+/// use std::io::Read;
+///
+/// let gzip: &[u8] = &[
+///     0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x33,
+///     0x56, 0x28, 0xcc, 0x4c, 0xb1, 0x32, 0x54, 0x30, 0xb4, 0x32, 0xd0,
+///     0x33, 0xe5, 0x02, 0x00, 0x8e, 0x7e, 0x66, 0x60, 0x0e, 0x00, 0x00,
+///     0x00,
+/// ];
+/// let mut s = String::new();
+/// decompress(gzip).unwrap().read_to_string(&mut s).unwrap();
+/// assert_eq!(s, "3 qid:1 1:0.5\n");
+///
+/// let mut s = String::new();
+/// decompress("3 qid:1 1:0.5\n".as_bytes())
+///     .unwrap()
+///     .read_to_string(&mut s)
+///     .unwrap();
+/// assert_eq!(s, "3 qid:1 1:0.5\n");
+/// ```
+pub fn decompress<'a, R>(mut reader: R) -> io::Result<Box<dyn Read + 'a>>
+where
+    R: Read + 'a,
+{
+    // Read the magic bytes, and put them back in front of the rest.
+    let mut magic = Vec::with_capacity(ZSTD_MAGIC.len());
+    (&mut reader)
+        .take(ZSTD_MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+    let is_gzip = magic.starts_with(&GZIP_MAGIC);
+    let is_zstd = magic.starts_with(&ZSTD_MAGIC) ||
+        (magic.len() == 4 && magic[0] & 0xf0 == 0x50 &&
+             magic[1..] == SKIPPABLE_MAGIC);
+    let reader = Cursor::new(magic).chain(reader);
+
+    if is_gzip {
+        Ok(Box::new(MultiGzDecoder::new(reader)))
+    } else if is_zstd {
+        Ok(Box::new(ZstdDecoder::new(reader)?))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Decodes the frames of a zstd stream one after another. Skippable
+/// frames, such as the ones `pzstd` writes, are skipped.
+struct ZstdDecoder<R: Read> {
+    source: BufReader<R>,
+    decoder: FrameDecoder,
+    // Whether the end of the source is reached.
+    done: bool,
+}
+
+impl<R: Read> ZstdDecoder<R> {
+    fn new(reader: R) -> io::Result<ZstdDecoder<R>> {
+        let mut decoder = ZstdDecoder {
+            source: BufReader::new(reader),
+            decoder: FrameDecoder::new(),
+            done: false,
+        };
+        decoder.done = !decoder.next_frame()?;
+        Ok(decoder)
+    }
+
+    /// Starts decoding the next frame. Returns false at the end of the
+    /// source.
+    fn next_frame(&mut self) -> io::Result<bool> {
+        loop {
+            if self.source.fill_buf()?.is_empty() {
+                return Ok(false);
+            }
+
+            match self.decoder.reset(&mut self.source) {
+                Ok(()) => return Ok(true),
+                Err(FrameDecoderError::ReadFrameHeaderError(
+                    ReadFrameHeaderError::SkipFrame { length, .. },
+                )) => {
+                    let length = u64::from(length);
+                    let skipped = io::copy(
+                        &mut (&mut self.source).take(length),
+                        &mut io::sink(),
+                    )?;
+                    if skipped < length {
+                        return Err(invalid_data("Truncated skippable frame"));
+                    }
+                }
+                Err(e) => return Err(invalid_data(e)),
+            }
+        }
+    }
+}
+
+impl<R: Read> Read for ZstdDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        while !self.done {
+            // The decoder may not reach the requested bytes at once.
+            while self.decoder.can_collect() < buf.len() &&
+                !self.decoder.is_finished()
+            {
+                let needed = buf.len() - self.decoder.can_collect();
+                self.decoder
+                    .decode_blocks(
+                        &mut self.source,
+                        BlockDecodingStrategy::UptoBytes(needed),
+                    )
+                    .map_err(invalid_data)?;
+            }
+            if self.decoder.can_collect() > 0 {
+                return self.decoder.read(buf);
+            }
+
+            // The frame is decoded and collected.
+            self.done = !self.next_frame()?;
+        }
+        Ok(0)
+    }
+}
+
+fn invalid_data<E: ToString>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+/// Opens a file for reading, see `decompress`. Errors tell the file
+/// name.
+pub fn open(path: &str) -> ParseResult<Box<dyn Read>> {
+    File::open(path)
+        .and_then(decompress)
+        .map_err(|e| Error::from(e).in_file(path))
+}
+
+/// Returns the path without the extension of a compressed file, if
+/// any.
+///
+/// # Examples
+///
+/// ```
+/// use rforests::format::compression::strip_extension;
+///
+/// assert_eq!(strip_extension("data/train.csv.gz"), "data/train.csv");
+/// assert_eq!(strip_extension("train.txt.zst"), "train.txt");
+/// assert_eq!(strip_extension("train.txt"), "train.txt");
+/// ```
+pub fn strip_extension(path: &str) -> &str {
+    match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if EXTENSIONS.contains(&ext) => {
+            &path[..path.len() - ext.len() - 1]
+        }
+        _ => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decompress_zstd() {
+        let zstd: &[u8] = &[
+            0x28, 0xb5, 0x2f, 0xfd, 0x04, 0x58, 0x71, 0x00, 0x00, 0x33,
+            0x20, 0x71, 0x69, 0x64, 0x3a, 0x31, 0x20, 0x31, 0x3a, 0x30,
+            0x2e, 0x35, 0x0a, 0xf5, 0xca, 0xd3, 0xfd,
+        ];
+        let mut s = String::new();
+        decompress(zstd).unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "3 qid:1 1:0.5\n");
+
+        // Two concatenated frames, each after a skippable frame as
+        // pzstd writes.
+        let skippable: &[u8] = &[
+            0x50, 0x2a, 0x4d, 0x18, 0x04, 0x00, 0x00, 0x00, 0x1b, 0x00,
+            0x00, 0x00,
+        ];
+        let frames = [skippable, zstd, skippable, zstd].concat();
+        let mut s = String::new();
+        decompress(frames.as_slice())
+            .unwrap()
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "3 qid:1 1:0.5\n3 qid:1 1:0.5\n");
+
+        // Trailing data that is not a frame.
+        let frames = [zstd, "3 qid:1".as_bytes()].concat();
+        let mut s = String::new();
+        assert!(
+            decompress(frames.as_slice())
+                .unwrap()
+                .read_to_string(&mut s)
+                .is_err()
+        );
+
+        // Shorter than the magic bytes.
+        let mut s = String::new();
+        decompress("3".as_bytes())
+            .unwrap()
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "3");
+    }
+}
@@ -0,0 +1,506 @@
+use clap::{Arg, ArgMatches};
+use std::io::BufReader;
+use std::io::prelude::*;
+use std::mem;
+use util::*;
+use train::dataset::*;
+use format::feature_names::FeatureNames;
+
+// Format of the CSV file. The first line is a header of the column
+// names, and each following line is an instance.
+// <header> .=. <name><delim><name><delim>...<name>
+// <line> .=. <field><delim><field><delim>...<field>
+// <field> .=. <string> | "<string>"
+//
+// The label and the qid columns are required, the docid column is
+// optional, and the other columns are features unless ignored. The
+// feature ids are assigned from 1 in the order of the columns, or by
+// the names of known features, and the feature names are the column
+// names. An empty feature field is a missing value.
+
+/// A column of a CSV file, by its name in the header or by its
+/// 1-based index.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+impl Column {
+    /// Parses a column, as an index if it is a positive integer, or
+    /// else as a name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rforests::format::csv::Column;
+    ///
+    /// assert_eq!(Column::new("2"), Column::Index(2));
+    /// assert_eq!(Column::new("label"), Column::Name("label".to_string()));
+    /// ```
+    pub fn new(s: &str) -> Column {
+        match s.parse::<usize>() {
+            Ok(index) if index > 0 => Column::Index(index),
+            _ => Column::Name(s.to_string()),
+        }
+    }
+
+    /// Returns the 0-based position of the column in the header.
+    fn position(&self, header: &[String]) -> Option<usize> {
+        match *self {
+            Column::Index(index) if index <= header.len() => Some(index - 1),
+            Column::Index(_) => None,
+            Column::Name(ref name) => {
+                header.iter().position(|column| column == name)
+            }
+        }
+    }
+
+    fn name(&self) -> String {
+        match *self {
+            Column::Index(index) => index.to_string(),
+            Column::Name(ref name) => name.clone(),
+        }
+    }
+}
+
+/// Roles of the columns of CSV files and the delimiter, used to load
+/// a CSV file into a DataSet.
+#[derive(Clone, Debug)]
+pub struct CsvFormat {
+    delimiter: char,
+    label_col: Column,
+    qid_col: Column,
+    docid_col: Option<Column>,
+    ignore_cols: Vec<Column>,
+    // Known features, whose ids are assigned to the columns of the
+    // same names.
+    feature_names: FeatureNames,
+}
+
+impl Default for CsvFormat {
+    /// Comma separated, with the label in the "label" column and the
+    /// qid in the "qid" column.
+    fn default() -> CsvFormat {
+        CsvFormat {
+            delimiter: ',',
+            label_col: Column::Name("label".to_string()),
+            qid_col: Column::Name("qid".to_string()),
+            docid_col: None,
+            ignore_cols: Vec::new(),
+            feature_names: FeatureNames::new(),
+        }
+    }
+}
+
+impl CsvFormat {
+    pub fn new() -> CsvFormat {
+        CsvFormat::default()
+    }
+
+    /// Creates the format from the arguments returned by `clap_args`.
+    pub fn from_matches<'a>(matches: &ArgMatches<'a>) -> CsvFormat {
+        let mut format = CsvFormat::new();
+        if let Some(col) = matches.value_of("label-col") {
+            format = format.with_label_col(Column::new(col));
+        }
+        if let Some(col) = matches.value_of("qid-col") {
+            format = format.with_qid_col(Column::new(col));
+        }
+        if let Some(col) = matches.value_of("docid-col") {
+            format = format.with_docid_col(Column::new(col));
+        }
+        if let Some(cols) = matches.values_of("ignore-cols") {
+            format = format.with_ignore_cols(cols.map(Column::new).collect());
+        }
+        format
+    }
+
+    /// Sets the field delimiter, ',' for CSV and '\t' for TSV files.
+    pub fn with_delimiter(mut self, delimiter: char) -> CsvFormat {
+        self.delimiter = delimiter;
+        self
+    }
+
+    pub fn with_label_col(mut self, col: Column) -> CsvFormat {
+        self.label_col = col;
+        self
+    }
+
+    pub fn with_qid_col(mut self, col: Column) -> CsvFormat {
+        self.qid_col = col;
+        self
+    }
+
+    /// Sets the column of the document ids, which are kept as the
+    /// info of the instances, "docid = <id>".
+    pub fn with_docid_col(mut self, col: Column) -> CsvFormat {
+        self.docid_col = Some(col);
+        self
+    }
+
+    /// Sets the columns that are neither features nor roles.
+    pub fn with_ignore_cols(mut self, cols: Vec<Column>) -> CsvFormat {
+        self.ignore_cols = cols;
+        self
+    }
+
+    /// Sets the known features, for example the features of a model
+    /// or of the training data. If any, the feature columns take the
+    /// ids of the features of the same names instead of the order of
+    /// the columns, so that the columns may be in another order. Each
+    /// feature column should be a known feature and each known feature
+    /// a column.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rforests::format::csv::CsvFormat;
+    /// use rforests::format::feature_names::FeatureNames;
+    ///
+    /// let mut names = FeatureNames::new();
+    /// names.insert(1, "bm25".to_string());
+    /// names.insert(2, "pagerank".to_string());
+    ///
+    /// let s = "qid,pagerank,label,bm25
+    /// 1,0.5,2,10.5";
+    /// let dataset = CsvFormat::new()
+    ///     .with_feature_names(names.clone())
+    ///     .load(s.as_bytes(), false)
+    ///     .unwrap();
+    /// assert_eq!(dataset[0].get(1), Some(10.5));
+    /// assert_eq!(dataset[0].get(2), Some(0.5));
+    ///
+    /// let s = "qid,label,bm25,tf
+    /// 1,2,10.5,3";
+    /// assert!(
+    ///     CsvFormat::new()
+    ///         .with_feature_names(names)
+    ///         .load(s.as_bytes(), false)
+    ///         .is_err()
+    /// );
+    /// ```
+    pub fn with_feature_names(mut self, names: FeatureNames) -> CsvFormat {
+        self.feature_names = names;
+        self
+    }
+
+    /// Loads a data set from a reader of a CSV file. The feature names
+    /// are the names of the feature columns, and the qids are
+    /// interned, see `QidMap`. The instances of a query are regrouped
+    /// if they are not consecutive and `regroup` is true, or else
+    /// they should be consecutive.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rforests::format::csv::{Column, CsvFormat};
+    ///
+    /// let s = "query,doc,relevance,bm25,\"url, length\",crawled
+    /// 1,d1,2,10.5,30,2017-10-01
+    /// 1,d2,0,,12,2017-10-02
+    /// 2,d3,1,3.0,8,2017-10-03";
+    ///
+    /// let format = CsvFormat::new()
+    ///     .with_label_col(Column::new("relevance"))
+    ///     .with_qid_col(Column::new("query"))
+    ///     .with_docid_col(Column::new("doc"))
+    ///     .with_ignore_cols(vec![Column::new("6")]);
+    /// let dataset = format.load(s.as_bytes(), false).unwrap();
+    ///
+    /// assert_eq!(dataset.len(), 3);
+    /// assert_eq!(dataset.nfeatures(), 2);
+    /// assert_eq!(dataset[0].label(), 2.0);
+    /// assert_eq!(dataset.qid_name(dataset[0].qid()), "1");
+    /// assert_eq!(dataset[0].docid(), Some("d1"));
+    /// assert_eq!(dataset[0].get(2), Some(30.0));
+    /// assert_eq!(dataset[1].get(1), None);
+    /// assert_eq!(dataset.feature_names().get(2), Some("url, length"));
+    /// ```
+    pub fn load<R: Read>(
+        &self,
+        reader: R,
+        regroup: bool,
+    ) -> ParseResult<DataSet> {
+        let mut lines = BufReader::new(reader)
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line))
+            .filter(|&(_, ref line)| match *line {
+                Ok(ref line) => !line.trim().is_empty(),
+                Err(_) => true,
+            });
+
+        let (header_line, header) = match lines.next() {
+            Some((line_number, line)) => {
+                let header = self.split(&line?)
+                    .map_err(|e| e.at_line(line_number))?;
+                (line_number, header)
+            }
+            None => return Err(Error::parse("", "Missing header")),
+        };
+
+        let position = |col: &Column| {
+            col.position(&header).ok_or_else(|| {
+                Error::parse(&col.name(), "Unknown column")
+                    .at_line(header_line)
+            })
+        };
+        let label_pos = position(&self.label_col)?;
+        let qid_pos = position(&self.qid_col)?;
+        let docid_pos = match self.docid_col {
+            Some(ref col) => Some(position(col)?),
+            None => None,
+        };
+        let mut ignored = vec![label_pos, qid_pos];
+        ignored.extend(docid_pos);
+        for col in self.ignore_cols.iter() {
+            ignored.push(position(col)?);
+        }
+
+        let features = self.feature_columns(&header, &ignored)
+            .map_err(|e| e.at_line(header_line))?;
+        let mut feature_names = FeatureNames::new();
+        for &(pos, id) in features.iter() {
+            feature_names.insert(id, header[pos].clone());
+        }
+
+        let mut instances = Vec::new();
+        let mut line_numbers = Vec::new();
+        let mut qids = QidMap::new();
+        for (line_number, line) in lines {
+            let line = line?;
+            let fields = self.split(&line)
+                .and_then(|fields| if fields.len() == header.len() {
+                    Ok(fields)
+                } else {
+                    Err(Error::parse(
+                        &line,
+                        format!("Expected {} fields", header.len()),
+                    ))
+                })
+                .map_err(|e| e.at_line(line_number))?;
+            let (label, values) = self.parse_fields(
+                &fields,
+                label_pos,
+                &features,
+            ).map_err(|e| e.at_line(line_number))?;
+            let qid = fields[qid_pos].trim();
+            if qid.is_empty() {
+                let e = Error::parse(&line, "Missing qid");
+                return Err(e.at_line(line_number));
+            }
+            let qid = qids.intern(qid);
+            let info = docid_pos.map(|pos| format!("docid = {}", fields[pos]));
+            instances
+                .push(Instance::sparse(label, qid, values).with_info(info));
+            line_numbers.push(line_number);
+        }
+
+        DataSet::from_instances(
+            instances,
+            &line_numbers,
+            qids,
+            feature_names,
+            regroup,
+        )
+    }
+
+    /// Returns the (position, feature id) pairs of the feature columns,
+    /// which are the columns not `ignored`.
+    fn feature_columns(
+        &self,
+        header: &[String],
+        ignored: &[usize],
+    ) -> ParseResult<Vec<(usize, Id)>> {
+        let positions =
+            (0..header.len()).filter(|pos| !ignored.contains(pos));
+        if self.feature_names.is_empty() {
+            return Ok(
+                positions
+                    .enumerate()
+                    .map(|(index, pos)| (pos, index + 1))
+                    .collect(),
+            );
+        }
+
+        let mut features = Vec::new();
+        for pos in positions {
+            let name = &header[pos];
+            let known = self.feature_names.iter().find(|&(_, known)| {
+                known == name
+            });
+            match known {
+                Some((id, _)) => features.push((pos, id)),
+                None => {
+                    return Err(Error::parse(
+                        name,
+                        "Unknown feature column, consider ignoring it",
+                    ))
+                }
+            }
+        }
+        for (id, name) in self.feature_names.iter() {
+            if !features.iter().any(|&(_, feature_id)| feature_id == id) {
+                return Err(Error::parse(name, "Missing feature column"));
+            }
+        }
+        Ok(features)
+    }
+
+    /// Parses the label and the (feature id, value) pairs from the
+    /// fields of a line.
+    fn parse_fields(
+        &self,
+        fields: &[String],
+        label_pos: usize,
+        features: &[(usize, Id)],
+    ) -> ParseResult<(Value, Vec<(Id, Value)>)> {
+        let label = fields[label_pos].trim();
+        let label = label
+            .parse::<Value>()
+            .map_err(|_| Error::parse(label, "Invalid label"))?;
+
+        let mut values = Vec::new();
+        for &(pos, id) in features.iter() {
+            let field = fields[pos].trim();
+            if field.is_empty() {
+                continue;
+            }
+            let value = field
+                .parse::<Value>()
+                .map_err(|_| Error::parse(field, "Invalid feature value"))?;
+            values.push((id, value));
+        }
+        Ok((label, values))
+    }
+
+    /// Splits a line into fields. A field can be quoted with '"' to
+    /// hold delimiters, and '""' in a quoted field is a '"'.
+    fn split(&self, line: &str) -> ParseResult<Vec<String>> {
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            if quoted {
+                if c != '"' {
+                    field.push(c);
+                } else if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            } else if c == '"' && field.is_empty() {
+                quoted = true;
+            } else if c == self.delimiter {
+                fields.push(mem::replace(&mut field, String::new()));
+            } else {
+                field.push(c);
+            }
+        }
+        if quoted {
+            return Err(Error::parse(line, "Unterminated quoted field"));
+        }
+        fields.push(field);
+        Ok(fields)
+    }
+}
+
+/// Returns the arguments of the column roles of CSV files, with the
+/// display order starting from `display_order`.
+pub fn clap_args<'a, 'b>(display_order: usize) -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("label-col")
+            .long("label-col")
+            .takes_value(true)
+            .value_name("COLUMN")
+            .empty_values(false)
+            .display_order(display_order)
+            .help("Label column of CSV/TSV files, by name or 1-based index [default: label]"),
+        Arg::with_name("qid-col")
+            .long("qid-col")
+            .takes_value(true)
+            .value_name("COLUMN")
+            .empty_values(false)
+            .display_order(display_order + 1)
+            .help("Query id column of CSV/TSV files [default: qid]"),
+        Arg::with_name("docid-col")
+            .long("docid-col")
+            .takes_value(true)
+            .value_name("COLUMN")
+            .empty_values(false)
+            .display_order(display_order + 2)
+            .help("Document id column of CSV/TSV files"),
+        Arg::with_name("ignore-cols")
+            .long("ignore-cols")
+            .takes_value(true)
+            .value_name("COLUMNS")
+            .multiple(true)
+            .require_delimiter(true)
+            .empty_values(false)
+            .display_order(display_order + 3)
+            .help("Comma separated columns of CSV/TSV files that are not features"),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        let format = CsvFormat::new();
+        assert_eq!(
+            format.split("a,\"b,c\",\"say \"\"hi\"\"\",").unwrap(),
+            vec!["a", "b,c", "say \"hi\"", ""]
+        );
+        assert!(format.split("a,\"b").is_err());
+
+        let format = CsvFormat::new().with_delimiter('\t');
+        assert_eq!(format.split("1\t2,3").unwrap(), vec!["1", "2,3"]);
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let s = "label,qid,f1\n1,1,0.5\n1,1\n";
+        match CsvFormat::new().load(s.as_bytes(), false) {
+            Err(Error::Parse { line, .. }) => assert_eq!(line, Some(3)),
+            _ => panic!("Expected a parse error"),
+        }
+
+        let s = "relevance,qid,f1\n1,1,0.5\n";
+        match CsvFormat::new().load(s.as_bytes(), false) {
+            Err(Error::Parse { line, token, .. }) => {
+                assert_eq!(line, Some(1));
+                assert_eq!(token, Some("label".to_string()));
+            }
+            _ => panic!("Expected a parse error"),
+        }
+
+        let mut names = FeatureNames::new();
+        names.insert(1, "f1".to_string());
+        names.insert(2, "f2".to_string());
+        let s = "\nlabel,qid,f2\n1,1,0.5\n";
+        match CsvFormat::new().with_feature_names(names).load(
+            s.as_bytes(),
+            false,
+        ) {
+            Err(Error::Parse { line, token, .. }) => {
+                assert_eq!(line, Some(2));
+                assert_eq!(token, Some("f1".to_string()));
+            }
+            _ => panic!("Expected a parse error"),
+        }
+
+        let s = "label,qid,f1\n1,q1,0.5\n\n1,q2,0.5\n0,q1,0.1\n";
+        match CsvFormat::new().load(s.as_bytes(), false) {
+            Err(Error::Parse { line, token, .. }) => {
+                assert_eq!(line, Some(5));
+                assert_eq!(token, Some("q1".to_string()));
+            }
+            _ => panic!("Expected a parse error"),
+        }
+    }
+}
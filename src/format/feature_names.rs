@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+use std::io::BufReader;
+use std::io::prelude::*;
+use util::*;
+use format::compression;
+
+/// A dictionary of the names of the features, keyed by feature id.
+///
+/// The names come from a feature name file, which has a line "<id>
+/// <name>" for each feature, or from header lines of a data file of
+/// the form "@Feature id:<id> name:<name>", optionally after a '#'.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeatureNames {
+    names: BTreeMap<Id, String>,
+}
+
+impl FeatureNames {
+    pub fn new() -> FeatureNames {
+        FeatureNames { names: BTreeMap::new() }
+    }
+
+    /// Sets the name of a feature.
+    pub fn insert(&mut self, id: Id, name: String) {
+        self.names.insert(id, name);
+    }
+
+    /// Returns the name of a feature, if known.
+    pub fn get(&self, id: Id) -> Option<&str> {
+        self.names.get(&id).map(|name| name.as_str())
+    }
+
+    /// Returns the number of named features.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Returns an iterator over the (feature id, name) pairs in
+    /// ascending order of the ids.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (Id, &'a str)> + 'a {
+        self.names.iter().map(|(&id, name)| (id, name.as_str()))
+    }
+
+    /// Adds the names in `other`, which replace the existing names of
+    /// the same features.
+    pub fn extend(&mut self, other: FeatureNames) {
+        self.names.extend(other.names);
+    }
+
+    /// Returns the feature for human-readable output, "<id>:<name>"
+    /// if the feature is named, or else "<id>".
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rforests::format::feature_names::FeatureNames;
+    ///
+    /// let mut names = FeatureNames::new();
+    /// names.insert(2, "bm25".to_string());
+    /// assert_eq!(names.describe(2), "2:bm25");
+    /// assert_eq!(names.describe(3), "3");
+    /// ```
+    pub fn describe(&self, id: Id) -> String {
+        match self.get(id) {
+            Some(name) => format!("{}:{}", id, name),
+            None => id.to_string(),
+        }
+    }
+
+    /// Returns whether the line is a feature header, "@Feature
+    /// id:<id> name:<name>", optionally after a '#'.
+    pub fn is_header(line: &str) -> bool {
+        line.trim()
+            .trim_start_matches('#')
+            .trim_start()
+            .starts_with("@Feature")
+    }
+
+    /// Parses a feature header into (feature id, name). The name is
+    /// the rest of the line after "name:".
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rforests::format::feature_names::FeatureNames;
+    ///
+    /// let header = "# @Feature id:2 name:covered query term number";
+    /// assert_eq!(
+    ///     FeatureNames::parse_header(header).unwrap(),
+    ///     (2, "covered query term number".to_string())
+    /// );
+    /// ```
+    pub fn parse_header(line: &str) -> ParseResult<(Id, String)> {
+        let header = line.trim().trim_start_matches('#').trim_start();
+        if !header.starts_with("@Feature") {
+            return Err(Error::parse(line, "Invalid feature header"));
+        }
+
+        let id = header
+            .split_whitespace()
+            .find(|field| field.starts_with("id:"))
+            .map(|field| field["id:".len()..].parse::<Id>());
+        let name = header
+            .find("name:")
+            .map(|pos| header[pos + "name:".len()..].trim());
+        match (id, name) {
+            (Some(Ok(id)), Some(name)) if id > 0 && !name.is_empty() => {
+                Ok((id, name.to_string()))
+            }
+            _ => Err(Error::parse(line, "Invalid feature header")),
+        }
+    }
+
+    /// Loads a feature name file from a reader. Empty lines and lines
+    /// starting with '#' are skipped, except for feature headers.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rforests::format::feature_names::FeatureNames;
+    ///
+    /// let s = "# Feature names
+    /// 1 tf
+    /// 2 bm25 score
+    /// @Feature id:3 name:pagerank";
+    ///
+    /// let names = FeatureNames::load(s.as_bytes()).unwrap();
+    /// assert_eq!(names.len(), 3);
+    /// assert_eq!(names.get(2), Some("bm25 score"));
+    /// assert_eq!(names.get(3), Some("pagerank"));
+    /// ```
+    pub fn load<R: Read>(reader: R) -> ParseResult<FeatureNames> {
+        fn parse(line: &str) -> ParseResult<(Id, String)> {
+            if FeatureNames::is_header(line) {
+                return FeatureNames::parse_header(line);
+            }
+
+            let mut fields = line.splitn(2, char::is_whitespace);
+            let id = fields.next().unwrap().parse::<Id>().ok();
+            let name = fields.next().unwrap_or("").trim();
+            match id {
+                Some(id) if id > 0 && !name.is_empty() => {
+                    Ok((id, name.to_string()))
+                }
+                _ => Err(Error::parse(line, "Invalid feature name line")),
+            }
+        }
+
+        let mut names = FeatureNames::new();
+        for (index, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() ||
+                (line.starts_with('#') && !FeatureNames::is_header(line))
+            {
+                continue;
+            }
+
+            let (id, name) = parse(line).map_err(|e| e.at_line(index + 1))?;
+            names.insert(id, name);
+        }
+        Ok(names)
+    }
+
+    /// Loads a feature name file.
+    pub fn load_file(path: &str) -> ParseResult<FeatureNames> {
+        compression::open(path)
+            .and_then(FeatureNames::load)
+            .map_err(|e| e.in_file(path))
+    }
+}
@@ -1 +1,5 @@
 pub mod svmlight;
+pub mod feature_names;
+pub mod csv;
+pub mod compression;
+pub mod trec;
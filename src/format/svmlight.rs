@@ -1,9 +1,11 @@
 use std;
-use std::fs::File;
-use std::io::BufReader;
+use std::io::{self, BufReader};
 use std::io::prelude::*;
+use std::vec;
 use util::*;
 use train::dataset::*;
+use format::compression;
+use format::feature_names::FeatureNames;
 
 // Format of the example file. http://svmlight.joachims.org/
 // <line> .=. <target> <feature>:<value> <feature>:<value> ... <feature>:<value> # <info>
@@ -54,6 +56,10 @@ use train::dataset::*;
 
 const MAX_SCALE_VALUE: f64 = ::std::i16::MAX as f64 - 1.0;
 
+/// Scales the values of a feature into integers within [0,
+/// MAX_SCALE_VALUE]. If the range of the feature is too large, the
+/// values are log scaled.
+#[derive(Debug, Clone, Copy)]
 pub struct FeatureScale {
     logarithm: bool,
     scale: f64,
@@ -61,6 +67,14 @@ pub struct FeatureScale {
 }
 
 impl FeatureScale {
+    pub fn new(logarithm: bool, scale: f64, min: f64) -> FeatureScale {
+        FeatureScale {
+            logarithm: logarithm,
+            scale: scale,
+            min: min,
+        }
+    }
+
     pub fn scale(&self, value: f64) -> f64 {
         let output = if self.logarithm {
             (value - self.min + 1.0).ln() * self.scale
@@ -69,23 +83,40 @@ impl FeatureScale {
         };
         output.round()
     }
+
+    /// The inverse of `FeatureScale::scale`, except for the rounding.
+    pub fn unscale(&self, value: f64) -> f64 {
+        if self.logarithm {
+            (value / self.scale).exp() - 1.0 + self.min
+        } else {
+            value / self.scale + self.min
+        }
+    }
+
+    /// Returns the factor multiplied to the (log) value.
+    pub fn factor(&self) -> f64 {
+        self.scale
+    }
+
+    pub fn is_log_scaled(&self) -> bool {
+        self.logarithm
+    }
 }
 
 impl<'a> From<&'a FeatureStat> for FeatureScale {
     fn from(fstat: &'a FeatureStat) -> Self {
         let range = fstat.max - fstat.min;
-        if range < MAX_SCALE_VALUE {
-            FeatureScale {
-                logarithm: false,
-                scale: MAX_SCALE_VALUE / range,
-                min: fstat.min,
-            }
+        if range <= 0.0 {
+            // All the values are the same.
+            FeatureScale::new(false, 1.0, fstat.min)
+        } else if range < MAX_SCALE_VALUE {
+            FeatureScale::new(false, MAX_SCALE_VALUE / range, fstat.min)
         } else {
-            FeatureScale {
-                logarithm: true,
-                scale: MAX_SCALE_VALUE / (range + 1.0).ln(),
-                min: fstat.min,
-            }
+            FeatureScale::new(
+                true,
+                MAX_SCALE_VALUE / (range + 1.0).ln(),
+                fstat.min,
+            )
         }
     }
 }
@@ -117,6 +148,13 @@ impl FilesStats {
         Ok(stats)
     }
 
+    /// Performs statistics analysis of the data from a reader.
+    pub fn parse_reader<R: Read>(reader: R) -> Result<FilesStats> {
+        let mut stats = FilesStats::default();
+        stats.update_stats_from_reader("", reader)?;
+        Ok(stats)
+    }
+
     pub fn instances_count(&self, file_name: &str) -> usize {
         let result = self.instances_count.iter().find(
             |tuple| tuple.0 == file_name,
@@ -158,13 +196,24 @@ impl FilesStats {
     }
 
     fn update_stats_from_file(&mut self, filename: &str) -> Result<()> {
-        let file = File::open(filename)?;
+        let file = compression::open(filename)?;
+        self.update_stats_from_reader(filename, file)
+    }
 
+    fn update_stats_from_reader<R: Read>(
+        &mut self,
+        filename: &str,
+        reader: R,
+    ) -> Result<()> {
         let mut instance_count = 0;
         for (line_index, instance) in
-            SvmLightFile::instances(file).enumerate()
+            SvmLightFile::instances(reader).enumerate()
         {
-            let instance = instance?;
+            let instance = instance.map_err(|e| if filename.is_empty() {
+                e
+            } else {
+                e.in_file(filename)
+            })?;
             instance_count += 1;
 
             for (id, value) in instance.value_iter() {
@@ -185,136 +234,436 @@ impl FilesStats {
     }
 }
 
+/// A line of a SVMLight file.
+#[derive(Debug, PartialEq)]
+pub enum Record {
+    /// An instance, with the qid as it is in the file. The values are
+    /// (feature id, value) pairs, and the info is the comment
+    /// following '#', if any.
+    Instance {
+        label: Value,
+        qid: String,
+        values: Vec<(Id, Value)>,
+        info: Option<String>,
+    },
+    /// A feature header, "@Feature id:<id> name:<name>", as (feature
+    /// id, name).
+    Feature(Id, String),
+}
+
+/// Size in bytes of the chunks parsed in parallel by
+/// `SvmLightFile::par_records`.
+const CHUNK_SIZE: usize = 4 << 20;
+
 pub struct SvmLightFile;
 
 impl SvmLightFile {
-    /// Read from reader and create (label, qid, values) tuple for
-    /// each line.
+    /// Returns whether the line holds a record, i.e. it is neither
+    /// empty nor a comment. Feature headers are records even if
+    /// commented out.
+    fn is_record_line(line: &str) -> bool {
+        let trimed = line.trim();
+        !trimed.is_empty() &&
+            (!trimed.starts_with("#") || FeatureNames::is_header(trimed))
+    }
+
+    /// Returns an iterator over the (line number, line) pairs of the
+    /// reader, skipping empty lines and comment lines. Feature
+    /// headers are kept even if commented out.
+    fn lines<R>(reader: R) -> impl Iterator<Item = ParseResult<(usize, String)>>
+    where
+        R: std::io::Read,
+    {
+        BufReader::new(reader)
+            .lines()
+            .enumerate()
+            .filter(|&(_, ref result)| match result {
+                &Ok(ref line) => SvmLightFile::is_record_line(line),
+                &Err(_) => true,
+            })
+            .map(|(index, result)| {
+                result.map(|line| (index + 1, line)).map_err(Error::from)
+            })
+    }
+
+    /// Read from reader and create (label, qid, values, info) tuple
+    /// for each line. The values are (feature id, value) pairs, and
+    /// the info is the comment following '#', if any. Feature headers
+    /// are skipped.
     pub fn parse_reader<R>(
         reader: R,
-    ) -> impl Iterator<Item = Result<(Value, Id, Vec<Value>)>>
+    ) -> impl Iterator<
+        Item = ParseResult<(Value, String, Vec<(Id, Value)>, Option<String>)>,
+    >
     where
         R: std::io::Read,
     {
-        // Bring Error::description() into scope
-        use std::error::Error;
-
-        let buf_reader = BufReader::new(reader);
-
-        buf_reader
-            .lines()
-            // Filter empty line and comment line
+        SvmLightFile::lines(reader)
             .filter(|result| match result {
-                &Ok(ref line) => {
-                    let trimed = line.trim();
-                    !trimed.is_empty() && !trimed.starts_with("#")
-                }
+                &Ok((_, ref line)) => !FeatureNames::is_header(line),
                 &Err(_) => true,
             })
             .map(|result| {
-                result
-                // Change the error type to match the function signature
-                .map_err(|e| e.description().into())
-                .and_then(|line| {
-                    SvmLightFile::parse_str(line.as_str())
-                })
+                let (line_number, line) = result?;
+                SvmLightFile::parse_str(line.as_str())
+                    .map_err(|e| e.at_line(line_number))
             })
     }
 
-    /// Read from reader and create Instance struct for each line.
-    pub fn instances<R>(reader: R) -> impl Iterator<Item = Result<Instance>>
+    /// Read from reader and create Instance struct for each line. The
+    /// qids are interned, see `QidMap`.
+    pub fn instances<R>(
+        reader: R,
+    ) -> impl Iterator<Item = ParseResult<Instance>>
     where
         R: std::io::Read,
     {
-        SvmLightFile::parse_reader(reader).map(|parse_result| {
-            parse_result.map(|(label, qid, values)| {
-                Instance::new(label, qid, values)
+        let mut qids = QidMap::new();
+        SvmLightFile::parse_reader(reader).map(move |parse_result| {
+            parse_result.map(|(label, qid, values, info)| {
+                let qid = qids.intern(&qid);
+                Instance::sparse(label, qid, values).with_info(info)
             })
         })
     }
 
-    /// Parse "3".
-    fn parse_label(label: &str) -> Result<Value> {
-        let label = label.parse::<Value>()?;
-        Ok(label)
+    /// Read from reader and create a Record for each line, either an
+    /// instance or a feature header.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rforests::format::svmlight::{Record, SvmLightFile};
+    ///
+    /// let s = "# @Feature id:1 name:bm25
+    /// 3.0 qid:1 1:5.0";
+    ///
+    /// let mut records = SvmLightFile::records(s.as_bytes());
+    /// assert_eq!(
+    ///     records.next().unwrap().unwrap(),
+    ///     Record::Feature(1, "bm25".to_string())
+    /// );
+    /// match records.next().unwrap().unwrap() {
+    ///     Record::Instance { qid, values, .. } => {
+    ///         assert_eq!(qid, "1");
+    ///         assert_eq!(values, vec![(1, 5.0)]);
+    ///     }
+    ///     record => panic!("Unexpected record: {:?}", record),
+    /// }
+    /// assert!(records.next().is_none());
+    /// ```
+    ///
+    /// Errors tell the line number and the offending token:
+    ///
+    /// ```
+    /// use rforests::format::svmlight::SvmLightFile;
+    /// use rforests::util::Error;
+    ///
+    /// let s = "3.0 qid:1 1:5.0
+    ///
+    /// 2.0 qid:1 1:x";
+    ///
+    /// match SvmLightFile::records(s.as_bytes()).nth(1).unwrap() {
+    ///     Err(Error::Parse { line, token, .. }) => {
+    ///         assert_eq!(line, Some(3));
+    ///         assert_eq!(token, Some("1:x".to_string()));
+    ///     }
+    ///     _ => panic!("Expected a parse error"),
+    /// }
+    /// ```
+    pub fn records<R>(reader: R) -> impl Iterator<Item = ParseResult<Record>>
+    where
+        R: std::io::Read,
+    {
+        SvmLightFile::numbered_records(reader)
+            .map(|result| result.map(|(_, record)| record))
+    }
+
+    /// Like `records`, but each record is paired with its line
+    /// number.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rforests::format::svmlight::SvmLightFile;
+    ///
+    /// let s = "3.0 qid:1 1:5.0
+    ///
+    /// 2.0 qid:1 1:3.0";
+    ///
+    /// let lines: Vec<usize> = SvmLightFile::numbered_records(s.as_bytes())
+    ///     .map(|record| record.unwrap().0)
+    ///     .collect();
+    /// assert_eq!(lines, vec![1, 3]);
+    /// ```
+    pub fn numbered_records<R>(
+        reader: R,
+    ) -> impl Iterator<Item = ParseResult<(usize, Record)>>
+    where
+        R: std::io::Read,
+    {
+        SvmLightFile::lines(reader).map(|result| {
+            let (line_number, line) = result?;
+            SvmLightFile::parse_record(&line)
+                .map(|record| (line_number, record))
+                .map_err(|e| e.at_line(line_number))
+        })
+    }
+
+    /// Like `numbered_records`, but the lines are parsed in parallel
+    /// on the thread pool. The reader is read in chunks of whole
+    /// lines, and the records are yielded in the order of the file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rforests::format::svmlight::SvmLightFile;
+    ///
+    /// let s = "3.0 qid:1 1:5.0
+    /// 2.0 qid:1 1:3.0 # docid = 7
+    /// 1.0 qid:2 2:1.0";
+    ///
+    /// let records: Vec<_> = SvmLightFile::numbered_records(s.as_bytes())
+    ///     .map(|record| record.unwrap())
+    ///     .collect();
+    /// let par_records: Vec<_> = SvmLightFile::par_records(s.as_bytes())
+    ///     .with_chunk_size(16)
+    ///     .map(|record| record.unwrap())
+    ///     .collect();
+    /// assert_eq!(records, par_records);
+    /// ```
+    pub fn par_records<R: Read>(reader: R) -> ParRecords<R> {
+        ParRecords {
+            reader: reader,
+            chunk_size: CHUNK_SIZE,
+            remainder: Vec::new(),
+            nlines: 0,
+            eof: false,
+            records: Vec::new().into_iter(),
+            error: None,
+        }
     }
 
-    /// Parse "qid:3333".
-    fn parse_qid(qid: &str) -> Result<Id> {
-        let v: Vec<&str> = qid.split(':').collect();
-        if v.len() != 2 {
-            Err(format!("Invalid qid field: {}", qid))?;
+    /// Parses the lines of a chunk into records, up to the first
+    /// error. `first_line` is the line number of the first line of the
+    /// chunk.
+    fn parse_chunk(
+        chunk: &str,
+        first_line: usize,
+    ) -> (Vec<(usize, Record)>, Option<Error>) {
+        let mut records = Vec::new();
+        for (index, line) in chunk.lines().enumerate() {
+            if !SvmLightFile::is_record_line(line) {
+                continue;
+            }
+            let line_number = first_line + index;
+            match SvmLightFile::parse_record(line) {
+                Ok(record) => records.push((line_number, record)),
+                Err(e) => return (records, Some(e.at_line(line_number))),
+            }
         }
+        (records, None)
+    }
 
-        if v[0] != "qid" {
-            Err(format!("Invalid qid field: {}", v[0]))?;
+    /// Parse a line into a Record.
+    fn parse_record(line: &str) -> ParseResult<Record> {
+        if FeatureNames::is_header(line) {
+            let (id, name) = FeatureNames::parse_header(line)?;
+            return Ok(Record::Feature(id, name));
         }
+        let (label, qid, values, info) = SvmLightFile::parse_str(line)?;
+        Ok(Record::Instance {
+            label: label,
+            qid: qid,
+            values: values,
+            info: info,
+        })
+    }
 
-        let qid = v[1].parse::<Id>()?;
+    /// Parse "3".
+    fn parse_label(label: &str) -> ParseResult<Value> {
+        label
+            .parse::<Value>()
+            .map_err(|_| Error::parse(label, "Invalid label"))
+    }
 
-        Ok(qid)
+    /// Parse "qid:3333" into "3333". The qid can be any string.
+    fn parse_qid(qid: &str) -> ParseResult<&str> {
+        let mut v = qid.splitn(2, ':');
+        match (v.next(), v.next()) {
+            (Some("qid"), Some(id)) if !id.is_empty() => Ok(id),
+            _ => Err(Error::parse(qid, "Invalid qid field")),
+        }
     }
 
-    /// Parse &["1:3.0" "3:4.0"] into Vec of values. Absent indices
-    /// are filled with 0.0. The example above would result vec![0.0,
-    /// 3.0, 0.0, 4.0].
-    fn parse_values(fields: &[&str]) -> Result<Vec<f64>> {
-        fn parse(s: &str) -> Result<(Id, Value)> {
+    /// Parse &["1:3.0" "3:4.0"] into Vec of (id, value) pairs. The
+    /// example above would result vec![(1, 3.0), (3, 4.0)].
+    fn parse_values(fields: &[&str]) -> ParseResult<Vec<(Id, Value)>> {
+        fn parse(s: &str) -> ParseResult<(Id, Value)> {
             let v: Vec<&str> = s.split(':').collect();
             if v.len() != 2 {
-                Err(format!("Invalid string: {}", s))?;
+                return Err(Error::parse(s, "Invalid feature field"));
             }
 
-            let id = v[0].parse::<Id>()?;
-            let value = v[1].parse::<Value>()?;
+            let id = v[0]
+                .parse::<Id>()
+                .map_err(|_| Error::parse(s, "Invalid feature id"))?;
+            if id == 0 {
+                return Err(Error::parse(s, "Feature id should start from 1"));
+            }
+            let value = v[1]
+                .parse::<Value>()
+                .map_err(|_| Error::parse(s, "Invalid feature value"))?;
 
             Ok((id, value))
         }
 
         // (id, value) pairs
-        let v: Vec<(Id, Value)> =
-            fields.iter().map(|&s| parse(s)).collect::<Result<_>>()?;
-        let max_id = v.iter().max_by_key(|e| e.0).unwrap().0;
-        let mut ret: Vec<f64> = vec![0.0; max_id];
-        for &(id, value) in v.iter() {
-            ret[(id - 1) as usize] = value;
-        }
-
-        Ok(ret)
+        fields.iter().map(|&s| parse(s)).collect()
     }
 
-    /// Parse "3.0 qid:3864 1:3.000000 2:9.000000 4:3.0 # 3:10.0".
-    pub fn parse_str(s: &str) -> Result<(Value, Id, Vec<Value>)> {
-        let line: &str = s.trim().split('#').next().unwrap().trim();
+    /// Parse "3.0 qid:3864 1:3.000000 2:9.000000 4:3.0 # 3:10.0". The
+    /// qid is returned as the string "3864", and the comment "3:10.0"
+    /// as the info.
+    pub fn parse_str(
+        s: &str,
+    ) -> ParseResult<(Value, String, Vec<(Id, Value)>, Option<String>)> {
+        let mut parts = s.trim().splitn(2, '#');
+        let line: &str = parts.next().unwrap().trim();
+        let info = parts
+            .next()
+            .map(|info| info.trim())
+            .filter(|info| !info.is_empty())
+            .map(|info| info.to_string());
         let fields: Vec<&str> = line.split_whitespace().collect();
         if fields.len() < 2 {
-            Err(format!("Invalid line"))?;
+            return Err(Error::parse(line, "Missing label or qid"));
         }
 
         let label = SvmLightFile::parse_label(fields[0])?;
-        let qid = SvmLightFile::parse_qid(fields[1])?;
-        let values: Vec<Value> = SvmLightFile::parse_values(&fields[2..])?;
-
-        Ok((label, qid, values))
-    }
-
-    // pub fn write_compact_format(
-    //     input: File,
-    //     mut output: File,
-    //     scales: &Vec<FeatureScale>,
-    // ) -> Result<()> {
-    //     for (index, instance) in SvmLightFile::instances(input).enumerate() {
-    //         let mut instance = instance?;
-    //         instance.scale_and_trim_zeros(scales);
-    //         let line = instance.to_string() + "\n";
-    //         output.write_all(line.as_bytes())?;
-
-    //         if (index + 1) % 5000 == 0 {
-    //             info!("Written {} lines", index + 1);
-    //         }
-    //     }
-    //     Ok(())
-    // }
+        let qid = SvmLightFile::parse_qid(fields[1])?.to_string();
+        let values = SvmLightFile::parse_values(&fields[2..])?;
+
+        Ok((label, qid, values, info))
+    }
+}
+
+/// Iterator over the records of a SVMLight file parsed in parallel,
+/// see `SvmLightFile::par_records`. It stops after the first error.
+pub struct ParRecords<R> {
+    reader: R,
+    chunk_size: usize,
+    /// Bytes read after the last complete line.
+    remainder: Vec<u8>,
+    /// Number of lines in the chunks read so far.
+    nlines: usize,
+    eof: bool,
+    /// Parsed records not yet yielded, with their line numbers.
+    records: vec::IntoIter<(usize, Record)>,
+    /// Error following the records not yet yielded.
+    error: Option<Error>,
+}
+
+impl<R: Read> ParRecords<R> {
+    /// Sets the size in bytes of the chunks parsed by each thread. A
+    /// chunk is extended to the end of its last line.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> ParRecords<R> {
+        self.chunk_size = usize::max(chunk_size, 1);
+        self
+    }
+
+    /// Reads a chunk of whole lines, and returns it along with the
+    /// line number of its first line, or None at the end.
+    fn read_chunk(&mut self) -> ParseResult<Option<(usize, String)>> {
+        let mut chunk = ::std::mem::replace(&mut self.remainder, Vec::new());
+        while !self.eof {
+            let n = (&mut self.reader)
+                .take(self.chunk_size as u64)
+                .read_to_end(&mut chunk)?;
+            if n == 0 {
+                self.eof = true;
+                break;
+            }
+
+            // Stop at the last line break, or read further if a line
+            // is longer than the chunk size.
+            if let Some(pos) = chunk.iter().rposition(|&b| b == b'\n') {
+                self.remainder = chunk.split_off(pos + 1);
+                break;
+            }
+        }
+
+        if chunk.is_empty() {
+            return Ok(None);
+        }
+
+        let first_line = self.nlines + 1;
+        self.nlines += chunk.iter().filter(|&&b| b == b'\n').count();
+        let chunk = String::from_utf8(chunk).map_err(|e| {
+            Error::from(io::Error::new(io::ErrorKind::InvalidData, e))
+        })?;
+        Ok(Some((first_line, chunk)))
+    }
+
+    /// Reads a chunk for each thread of the pool and parses them in
+    /// parallel. Returns false at the end of the reader.
+    fn parse_chunks(&mut self) -> ParseResult<bool> {
+        let mut pool = POOL.lock().unwrap();
+        let mut chunks = Vec::new();
+        while chunks.len() < pool.thread_count() as usize {
+            match self.read_chunk()? {
+                Some(chunk) => chunks.push(chunk),
+                None => break,
+            }
+        }
+        if chunks.is_empty() {
+            return Ok(false);
+        }
+
+        let mut results: Vec<_> = chunks.iter().map(|_| None).collect();
+        pool.scoped(|scope| for (&(first_line, ref chunk), result) in
+            chunks.iter().zip(results.iter_mut())
+        {
+            scope.execute(move || {
+                *result = Some(SvmLightFile::parse_chunk(chunk, first_line));
+            });
+        });
+
+        // Keep the records before the first error.
+        let mut records = Vec::new();
+        for result in results {
+            let (chunk_records, error) = result.unwrap();
+            records.extend(chunk_records);
+            if error.is_some() {
+                self.error = error;
+                break;
+            }
+        }
+        self.records = records.into_iter();
+        Ok(true)
+    }
+}
+
+impl<R: Read> Iterator for ParRecords<R> {
+    type Item = ParseResult<(usize, Record)>;
+
+    fn next(&mut self) -> Option<ParseResult<(usize, Record)>> {
+        loop {
+            if let Some(record) = self.records.next() {
+                return Some(Ok(record));
+            }
+            if let Some(e) = self.error.take() {
+                self.eof = true;
+                self.remainder.clear();
+                return Some(Err(e));
+            }
+            match self.parse_chunks() {
+                Ok(true) => continue,
+                Ok(false) => return None,
+                Err(e) => self.error = Some(e),
+            }
+        }
+    }
 }
 
 // fn write_stats(stats: HashMap<u32, FeatureStat>) -> Result<()> {
@@ -339,12 +688,65 @@ mod tests {
     #[test]
     fn test_line_parse() {
         let s = "3.0 qid:3864 1:3.000000 2:9.000000 4:3.0 # 3:10.0";
-        let (label, qid, values) = SvmLightFile::parse_str(s).unwrap();
+        let (label, qid, values, info) = SvmLightFile::parse_str(s).unwrap();
         assert_eq!(label, 3.0);
-        assert_eq!(qid, 3864);
-        assert_eq!(values, vec![3.0, 9.0, 0.0, 3.0]);
+        assert_eq!(qid, "3864");
+        assert_eq!(values, vec![(1, 3.0), (2, 9.0), (4, 3.0)]);
+        assert_eq!(info, Some("3:10.0".to_string()));
+
+        let s = "3.0 qid:3864 1:3.0 #";
+        let (_, _, _, info) = SvmLightFile::parse_str(s).unwrap();
+        assert_eq!(info, None);
+
+        // Missing values are kept as NaN.
+        let s = "1 qid:1 1:nan 2:0.5";
+        let (_, _, values, _) = SvmLightFile::parse_str(s).unwrap();
+        assert!(values[0].1.is_nan());
+        let instance = Instance::sparse(1.0, 1, values);
+        assert_eq!(instance.get(1), None);
+        assert_eq!(instance.get(2), Some(0.5));
+
+        let s = "1 qid:Q-17:a 1:0.5";
+        let (_, qid, _, _) = SvmLightFile::parse_str(s).unwrap();
+        assert_eq!(qid, "Q-17:a");
+        assert!(SvmLightFile::parse_str("1 qid: 1:0.5").is_err());
+        assert!(SvmLightFile::parse_str("1 1:0.5").is_err());
+    }
+
+    #[test]
+    fn test_par_records() {
+        let s = "# @Feature id:1 name:bm25\r
+3.0 qid:1 1:5.0 2:1.0 # docid = 1\r
+2.0 qid:1 1:3.0
+
+# comment
+1.0 qid:2 2:1.0 # a long comment that exceeds the chunk size
+0.0 qid:2 1:2.0";
+
+        let expected: Vec<_> = SvmLightFile::numbered_records(s.as_bytes())
+            .map(|record| record.unwrap())
+            .collect();
+        for chunk_size in 1..s.len() + 1 {
+            let records: Vec<_> = SvmLightFile::par_records(s.as_bytes())
+                .with_chunk_size(chunk_size)
+                .map(|record| record.unwrap())
+                .collect();
+            assert_eq!(records, expected);
+        }
+
+        // The records before the error are kept.
+        let s = "3.0 qid:1 1:5.0\n\n2.0 qid:1 1:x\n1.0 qid:1 1:1.0\n";
+        for chunk_size in 1..s.len() + 1 {
+            let mut records = SvmLightFile::par_records(s.as_bytes())
+                .with_chunk_size(chunk_size);
+            assert!(records.next().unwrap().is_ok());
+            match records.next().unwrap() {
+                Err(Error::Parse { line, .. }) => assert_eq!(line, Some(3)),
+                _ => panic!("Expected a parse error"),
+            }
+            assert!(records.next().is_none());
+        }
     }
 }
-// @Feature id:2 name:abc
 // Record min and max value for each feature.
 // Max feature Id.
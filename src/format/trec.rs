@@ -0,0 +1,236 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::BufReader;
+use std::io::prelude::*;
+use util::*;
+use format::compression;
+
+/// Relevance judgments in the TREC qrels format, a line "<qid> <iter>
+/// <docno> <label>" for each judged document. The iter field is
+/// ignored.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Qrels {
+    queries: HashMap<String, HashMap<String, f64>>,
+}
+
+impl Qrels {
+    /// Returns the labels of the judged documents of a query, keyed by
+    /// docno.
+    pub fn get(&self, qid: &str) -> Option<&HashMap<String, f64>> {
+        self.queries.get(qid)
+    }
+
+    /// Returns the number of judged queries.
+    pub fn len(&self) -> usize {
+        self.queries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queries.is_empty()
+    }
+
+    /// Loads qrels from a reader. Empty lines are skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rforests::format::trec::Qrels;
+    ///
+    /// let s = "301 0 FBIS3-10082 1
+    /// 301 0 FBIS3-10169 0
+    /// 302 0 FT921-2097 2";
+    ///
+    /// let qrels = Qrels::load(s.as_bytes()).unwrap();
+    /// assert_eq!(qrels.len(), 2);
+    /// assert_eq!(qrels.get("301").unwrap()["FBIS3-10082"], 1.0);
+    /// assert_eq!(qrels.get("302").unwrap()["FT921-2097"], 2.0);
+    /// ```
+    pub fn load<R: Read>(reader: R) -> ParseResult<Qrels> {
+        fn parse(line: &str) -> ParseResult<(&str, &str, f64)> {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 4 {
+                return Err(Error::parse(
+                    line,
+                    "Expect \"<qid> <iter> <docno> <label>\"",
+                ));
+            }
+            let label = fields[3].parse::<f64>().map_err(|_| {
+                Error::parse(fields[3], "Invalid label")
+            })?;
+            Ok((fields[0], fields[2], label))
+        }
+
+        let mut queries: HashMap<String, HashMap<String, f64>> =
+            HashMap::new();
+        for (index, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let (qid, docno, label) =
+                parse(&line).map_err(|e| e.at_line(index + 1))?;
+            queries
+                .entry(qid.to_string())
+                .or_insert_with(HashMap::new)
+                .insert(docno.to_string(), label);
+        }
+        Ok(Qrels { queries: queries })
+    }
+
+    /// Loads a qrels file.
+    pub fn load_file(path: &str) -> ParseResult<Qrels> {
+        compression::open(path)
+            .and_then(Qrels::load)
+            .map_err(|e| e.in_file(path))
+    }
+}
+
+/// A run in the TREC format, a line "<qid> Q0 <docno> <rank> <score>
+/// <tag>" for each retrieved document. As in trec_eval, the documents
+/// are ranked by the scores, and the rank field is ignored.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Run {
+    // The documents of each query with their scores, in the order of
+    // the file.
+    queries: BTreeMap<String, Vec<(String, f64)>>,
+}
+
+impl Run {
+    /// Returns the number of queries.
+    pub fn len(&self) -> usize {
+        self.queries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queries.is_empty()
+    }
+
+    /// Returns an iterator over the queries in ascending order of the
+    /// qids, each with the docnos in descending order of the scores.
+    /// Ties are broken by the docnos in descending order, as in
+    /// trec_eval.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rforests::format::trec::Run;
+    ///
+    /// let s = "302 Q0 FT921-2097 1 0.5 bm25
+    /// 301 Q0 FBIS3-10169 1 0.8 bm25
+    /// 301 Q0 FBIS3-10082 2 0.9 bm25
+    /// 301 Q0 FBIS3-10083 3 0.8 bm25";
+    ///
+    /// let run = Run::load(s.as_bytes()).unwrap();
+    /// let mut rankings = run.rankings();
+    /// assert_eq!(
+    ///     rankings.next(),
+    ///     Some(("301", vec!["FBIS3-10082", "FBIS3-10169", "FBIS3-10083"]))
+    /// );
+    /// assert_eq!(rankings.next(), Some(("302", vec!["FT921-2097"])));
+    /// assert_eq!(rankings.next(), None);
+    /// ```
+    pub fn rankings<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (&'a str, Vec<&'a str>)> + 'a {
+        self.queries.iter().map(|(qid, docs)| {
+            let mut docs: Vec<&(String, f64)> = docs.iter().collect();
+            docs.sort_by(|&&(ref docno1, score1), &&(ref docno2, score2)| {
+                score2
+                    .partial_cmp(&score1)
+                    .unwrap()
+                    .then_with(|| docno2.cmp(docno1))
+            });
+            let docnos = docs.iter().map(|&&(ref docno, _)| docno.as_str());
+            (qid.as_str(), docnos.collect())
+        })
+    }
+
+    /// Loads a run from a reader. Empty lines are skipped. A document
+    /// retrieved twice for a query is an error.
+    pub fn load<R: Read>(reader: R) -> ParseResult<Run> {
+        fn parse(line: &str) -> ParseResult<(&str, &str, f64)> {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 6 {
+                return Err(Error::parse(
+                    line,
+                    "Expect \"<qid> Q0 <docno> <rank> <score> <tag>\"",
+                ));
+            }
+            let score = match fields[4].parse::<f64>() {
+                Ok(score) if !score.is_nan() => score,
+                _ => return Err(Error::parse(fields[4], "Invalid score")),
+            };
+            Ok((fields[0], fields[2], score))
+        }
+
+        let mut queries: BTreeMap<String, Vec<(String, f64)>> =
+            BTreeMap::new();
+        let mut retrieved: HashSet<(String, String)> = HashSet::new();
+        for (index, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let (qid, docno, score) =
+                parse(&line).map_err(|e| e.at_line(index + 1))?;
+            if !retrieved.insert((qid.to_string(), docno.to_string())) {
+                return Err(
+                    Error::parse(docno, "Duplicate document of the query")
+                        .at_line(index + 1),
+                );
+            }
+            queries
+                .entry(qid.to_string())
+                .or_insert_with(Vec::new)
+                .push((docno.to_string(), score));
+        }
+        Ok(Run { queries: queries })
+    }
+
+    /// Loads a run file.
+    pub fn load_file(path: &str) -> ParseResult<Run> {
+        compression::open(path)
+            .and_then(Run::load)
+            .map_err(|e| e.in_file(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_errors() {
+        match Qrels::load("301 0 FBIS3-10082\n".as_bytes()) {
+            Err(Error::Parse { line, .. }) => assert_eq!(line, Some(1)),
+            _ => panic!("Expected a parse error"),
+        }
+
+        match Qrels::load("\n301 0 FBIS3-10082 yes\n".as_bytes()) {
+            Err(Error::Parse { line, token, .. }) => {
+                assert_eq!(line, Some(2));
+                assert_eq!(token, Some("yes".to_string()));
+            }
+            _ => panic!("Expected a parse error"),
+        }
+
+        match Run::load("301 Q0 FBIS3-10082 1 high bm25\n".as_bytes()) {
+            Err(Error::Parse { line, token, .. }) => {
+                assert_eq!(line, Some(1));
+                assert_eq!(token, Some("high".to_string()));
+            }
+            _ => panic!("Expected a parse error"),
+        }
+
+        let s = "301 Q0 FBIS3-10082 1 0.9 bm25
+301 Q0 FBIS3-10082 2 0.8 bm25";
+        match Run::load(s.as_bytes()) {
+            Err(Error::Parse { line, token, .. }) => {
+                assert_eq!(line, Some(2));
+                assert_eq!(token, Some("FBIS3-10082".to_string()));
+            }
+            _ => panic!("Expected a parse error"),
+        }
+    }
+}
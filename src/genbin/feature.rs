@@ -1,10 +1,276 @@
-extern crate num;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use format::svmlight::{FeatureScale, FeatureStat};
+use std::io::prelude::*;
+use util::{Error, ParseResult, Result};
 
-pub struct Feature<T: num::Unsigned> {
-    id: u32,
-    // All the values that this feature may be
+/// The type of a feature block. It depends on how many distinct
+/// values the feature has, which decides the width of the index
+/// array.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FeatureType {
+    /// Only one value. No index array is stored.
+    Null,
+    /// Two values. Each index takes one bit.
+    Bit,
+    /// Up to 2^8 values.
+    Byte,
+    /// Up to 2^16 values.
+    Short,
+    /// Up to 2^32 values.
+    Int,
+}
+
+impl FeatureType {
+    fn from_values_count(count: usize) -> FeatureType {
+        if count <= 1 {
+            FeatureType::Null
+        } else if count == 2 {
+            FeatureType::Bit
+        } else if count <= ::std::u8::MAX as usize + 1 {
+            FeatureType::Byte
+        } else if count <= ::std::u16::MAX as usize + 1 {
+            FeatureType::Short
+        } else {
+            FeatureType::Int
+        }
+    }
+
+    pub fn to_u8(&self) -> u8 {
+        match *self {
+            FeatureType::Null => 0,
+            FeatureType::Bit => 1,
+            FeatureType::Byte => 2,
+            FeatureType::Short => 3,
+            FeatureType::Int => 4,
+        }
+    }
+
+    pub fn from_u8(value: u8) -> ParseResult<FeatureType> {
+        match value {
+            0 => Ok(FeatureType::Null),
+            1 => Ok(FeatureType::Bit),
+            2 => Ok(FeatureType::Byte),
+            3 => Ok(FeatureType::Short),
+            4 => Ok(FeatureType::Int),
+            _ => Err(Error::parse(&value.to_string(), "Invalid feature type")),
+        }
+    }
+}
+
+/// A feature in the binary format. The duplicates in the scaled
+/// values are removed, and each instance keeps an index into the
+/// distinct values.
+#[derive(Debug)]
+pub struct Feature {
+    name: String,
+    stat: FeatureStat,
+    scale: FeatureScale,
+    // All the values that this feature may be, in ascending order.
     values: Vec<i32>,
+    // indices[i] is the index into `values` of the i-th instance.
+    indices: Vec<u32>,
+}
+
+impl Feature {
+    /// Creates a feature from the scaled values of each instance.
+    pub fn new(
+        name: String,
+        stat: FeatureStat,
+        scale: FeatureScale,
+        column: &[i32],
+    ) -> Feature {
+        let mut values = column.to_vec();
+        values.sort();
+        values.dedup();
+
+        let indices = column
+            .iter()
+            .map(|value| values.binary_search(value).unwrap() as u32)
+            .collect();
+
+        Feature {
+            name: name,
+            stat: stat,
+            scale: scale,
+            values: values,
+            indices: indices,
+        }
+    }
+
+    pub fn feature_type(&self) -> FeatureType {
+        FeatureType::from_values_count(self.values.len())
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the original value of the feature of the instance at
+    /// the given index. The value is restored from the scaled value,
+    /// so it might differ slightly from the value in the text file.
+    pub fn value(&self, index: usize) -> f64 {
+        let value = match self.feature_type() {
+            FeatureType::Null => self.values[0],
+            _ => self.values[self.indices[index] as usize],
+        };
+        self.scale.unscale(value as f64)
+    }
+
+    /// Writes the feature block. The layout is the index array, the
+    /// values count, the values, then the name, min value, max value,
+    /// factor and whether the values are log scaled.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        match self.feature_type() {
+            FeatureType::Null => {}
+            FeatureType::Bit => {
+                // The bit of the i-th instance is the (i % 8)-th lowest
+                // bit of the (i / 8)-th byte.
+                for chunk in self.indices.chunks(8) {
+                    let byte = chunk.iter().enumerate().fold(
+                        0u8,
+                        |byte, (bit, &index)| byte | ((index as u8) << bit),
+                    );
+                    writer.write_u8(byte)?;
+                }
+            }
+            FeatureType::Byte => for &index in self.indices.iter() {
+                writer.write_u8(index as u8)?;
+            },
+            FeatureType::Short => for &index in self.indices.iter() {
+                writer.write_u16::<BigEndian>(index as u16)?;
+            },
+            FeatureType::Int => for &index in self.indices.iter() {
+                writer.write_u32::<BigEndian>(index)?;
+            },
+        }
+
+        writer.write_i32::<BigEndian>(self.values.len() as i32)?;
+        for &value in self.values.iter() {
+            writer.write_i32::<BigEndian>(value)?;
+        }
+
+        let name: Vec<u16> = self.name.encode_utf16().collect();
+        writer.write_i16::<BigEndian>(name.len() as i16)?;
+        for &c in name.iter() {
+            writer.write_u16::<BigEndian>(c)?;
+        }
+
+        writer.write_f64::<BigEndian>(self.stat.min)?;
+        writer.write_f64::<BigEndian>(self.stat.max)?;
+        writer.write_f64::<BigEndian>(self.scale.factor())?;
+        writer.write_u8(self.scale.is_log_scaled() as u8)?;
+        Ok(())
+    }
+
+    /// Reads a feature block written by `Feature::write`.
+    pub fn read<R: Read>(
+        reader: &mut R,
+        id: usize,
+        feature_type: FeatureType,
+        count: usize,
+    ) -> ParseResult<Feature> {
+        let indices: Vec<u32> = match feature_type {
+            FeatureType::Null => Vec::new(),
+            FeatureType::Bit => {
+                let mut bytes = vec![0u8; (count + 7) / 8];
+                reader.read_exact(&mut bytes)?;
+                (0..count)
+                    .map(|i| ((bytes[i / 8] >> (i % 8)) & 1) as u32)
+                    .collect()
+            }
+            FeatureType::Byte => {
+                let mut bytes = vec![0u8; count];
+                reader.read_exact(&mut bytes)?;
+                bytes.into_iter().map(|index| index as u32).collect()
+            }
+            FeatureType::Short => (0..count)
+                .map(|_| reader.read_u16::<BigEndian>().map(|i| i as u32))
+                .collect::<::std::io::Result<_>>()?,
+            FeatureType::Int => (0..count)
+                .map(|_| reader.read_u32::<BigEndian>())
+                .collect::<::std::io::Result<_>>()?,
+        };
+
+        let nvalues = reader.read_i32::<BigEndian>()? as usize;
+        let values = (0..nvalues)
+            .map(|_| reader.read_i32::<BigEndian>())
+            .collect::<::std::io::Result<Vec<i32>>>()?;
+        if FeatureType::from_values_count(nvalues) != feature_type ||
+            indices.iter().any(|&index| index as usize >= nvalues)
+        {
+            return Err(Error::parse(
+                &id.to_string(),
+                "Invalid values of the feature",
+            ));
+        }
+
+        let name_len = reader.read_i16::<BigEndian>()? as usize;
+        let name = (0..name_len)
+            .map(|_| reader.read_u16::<BigEndian>())
+            .collect::<::std::io::Result<Vec<u16>>>()?;
+        let name = String::from_utf16(&name).map_err(|_| {
+            Error::parse(&id.to_string(), "Invalid name of the feature")
+        })?;
+
+        let min = reader.read_f64::<BigEndian>()?;
+        let max = reader.read_f64::<BigEndian>()?;
+        let factor = reader.read_f64::<BigEndian>()?;
+        let logarithm = reader.read_u8()? != 0;
+
+        Ok(Feature {
+            name: name,
+            stat: FeatureStat {
+                id: id,
+                min: min,
+                max: max,
+            },
+            scale: FeatureScale::new(logarithm, factor, min),
+            values: values,
+            indices: indices,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(column: &[i32]) -> Feature {
+        let stat = FeatureStat {
+            id: 1,
+            min: 0.0,
+            max: 100000.0,
+        };
+        let scale = FeatureScale::new(false, 1.0, 0.0);
+        let feature = Feature::new("f".to_string(), stat, scale, column);
+
+        let mut buf = Vec::new();
+        feature.write(&mut buf).unwrap();
+        let ftype = feature.feature_type();
+        let mut reader = buf.as_slice();
+        let result =
+            Feature::read(&mut reader, 1, ftype, column.len()).unwrap();
+        assert!(reader.is_empty());
+        result
+    }
+
+    #[test]
+    fn test_feature_types() {
+        let feature = round_trip(&[7; 10]);
+        assert_eq!(feature.feature_type(), FeatureType::Null);
+        assert_eq!(feature.value(9), 7.0);
+
+        let column = [0, 5, 5, 0, 0, 5, 5, 5, 0, 5];
+        let feature = round_trip(&column);
+        assert_eq!(feature.feature_type(), FeatureType::Bit);
+        assert_eq!(feature.name(), "f");
+        for (index, &value) in column.iter().enumerate() {
+            assert_eq!(feature.value(index), value as f64);
+        }
 
-    // lines[0] means the index in values
-    lines: Vec<T>,
+        let column: Vec<i32> = (0..300).collect();
+        let feature = round_trip(&column);
+        assert_eq!(feature.feature_type(), FeatureType::Short);
+        assert_eq!(feature.value(299), 299.0);
+    }
 }
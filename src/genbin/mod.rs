@@ -1,47 +1,24 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use clap::{App, Arg, ArgMatches, SubCommand};
 use std::fs::File;
-use std::path::{Path, PathBuf};
-// use std::io::prelude::*;
-// use std::io::BufReader;
-// use std::error::Error;
-use std::collections::HashMap;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter, Cursor};
+use std::path::Path;
 
-use util::Result;
-use format::svmlight;
-use format::svmlight::SvmLightFile;
+use format::compression;
+use format::feature_names::FeatureNames;
+use format::svmlight::{FeatureStat, FilesStats, Record, SvmLightFile};
+use train;
+use train::dataset::{DataSet, Instance, QidMap};
+use util::{exit_on_error, Error, ParseResult, Result, Value};
 
 pub mod feature;
 
-#[derive(Debug, Deserialize)]
-pub struct Args {
-    arg_file: Vec<String>,
-    flag_ranking: bool,
+use self::feature::{Feature, FeatureType};
 
-    flag_help: bool,
-}
-
-pub const USAGE: &'static str = "
-Generate binary files
-
-Usage:
-    rforests genbin [--ranking] <file>...
-    rforests genbin (-h | --help)
-
-Options:
-    -r, --ranking               Support ranking
-    -h, --help                  Display this message
-";
-
-pub fn append_to_file_name(origin: &str, s: &str) -> String {
-    let path = Path::new(origin);
-    let mut new_name = path.file_stem().unwrap().to_os_string();
-    new_name.push(s);
-    let mut file_name = PathBuf::from(new_name);
-    if let Some(ext) = path.extension() {
-        file_name.set_extension(ext);
-    };
-
-    path.with_file_name(file_name).to_str().unwrap().to_string()
-}
+/// Version of the binary format. See "doc/Binary Format Version
+/// 3.pdf" for the layout.
+pub const BINARY_VERSION: i32 = 3;
 
 pub fn change_extension(origin: &str, new_ext: &str) -> String {
     Path::new(origin)
@@ -51,161 +28,402 @@ pub fn change_extension(origin: &str, new_ext: &str) -> String {
         .to_string()
 }
 
-pub fn execute(args: Args) -> Result<()> {
-    debug!("rforests genbin args: {:?}", args);
-    let input_files = args.arg_file.clone();
+pub fn execute(
+    input_files: &[String],
+    ranking: bool,
+    regroup: bool,
+) -> Result<()> {
+    debug!("rforests genbin files: {:?}", input_files);
 
-    // Generate statistics from the files
-    let stats = svmlight::FilesStats::parse(&input_files)?;
-    let feature_scales = stats.feature_scales();
-    let output_files: Vec<_> = input_files
-        .iter()
-        .map(|input| append_to_file_name(input, "-compact"))
-        .collect();
+    // Generate statistics from the files. The features of all the
+    // files share the same scales.
+    let stats = FilesStats::parse(input_files)?;
 
-    // Scale the input file and trim zeros
-    for (input_name, output_name) in
-        input_files.iter().zip(output_files.iter())
-    {
+    for input_name in input_files.iter() {
+        let output_name =
+            change_extension(compression::strip_extension(input_name), "bin");
         info!("Converting {} to {}", input_name, output_name);
 
-        let input = File::open(input_name.as_str())?;
-        let output = File::create(output_name)?;
-        // SvmLightFile::write_compact_format(input, output, &feature_scales)?;
+        let input = compression::open(input_name)?;
+        let output = File::create(&output_name)
+            .map_err(|e| Error::from(e).in_file(&output_name))?;
+        convert(input, output, &stats, ranking, regroup)?;
     }
 
-    // Load value maps from output files
-    let mut feature_value_hash: Vec<HashMap<u32, u32>> = Vec::new();
-    feature_value_hash.resize(stats.max_feature_id, HashMap::default());
-    for output_name in &output_files {
-        let output = File::open(&output_name)?;
-        for instance in SvmLightFile::instances(output) {
-            let instance = instance?;
+    Ok(())
+}
 
-            for (id, value) in instance.value_iter() {
-                let hash = &mut feature_value_hash[id - 1];
-                *hash.entry(value.round() as u32).or_insert(0) += 1;
+/// Converts a SVMLight file into the binary format, with the feature
+/// scales computed from `stats`. The names of the feature headers are
+/// kept. The queries count and boundaries are written only if
+/// `ranking` is true.
+///
+/// If the instances of a query are not consecutive, they are
+/// regrouped in the order of the first appearance of the queries when
+/// `regroup` is true, or else an error is returned.
+///
+/// The binary format can not tell missing values, which are written as
+/// 0 with a warning.
+pub fn convert<R, W>(
+    input: R,
+    output: W,
+    stats: &FilesStats,
+    ranking: bool,
+    regroup: bool,
+) -> Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    let nfeatures = stats.feature_count();
+    let feature_stats: Vec<FeatureStat> =
+        stats.feature_stats().cloned().collect();
+    let scales = stats.feature_scales();
+
+    let mut instances: Vec<Instance> = Vec::new();
+    let mut names = FeatureNames::new();
+    let mut qids = QidMap::new();
+    let mut prev_qid = None;
+    let mut consecutive = true;
+    for record in SvmLightFile::numbered_records(input) {
+        let (line_number, instance) = match record? {
+            (line_number, Record::Instance {
+                label, qid, values, ..
+            }) => {
+                let id = qids.intern(&qid);
+                // The qids are interned in the order of appearance,
+                // so a smaller qid is a query seen before.
+                if prev_qid.map_or(false, |prev_qid| id < prev_qid) {
+                    if !regroup {
+                        Err(Error::parse(
+                            &qid,
+                            "Instances of the query are not consecutive, \
+                             consider regrouping the queries",
+                        ).at_line(line_number))?;
+                    }
+                    consecutive = false;
+                }
+                prev_qid = Some(id);
+                (line_number, Instance::sparse(label, id, values))
             }
-        }
-    }
-
-    // Turn hash table into vector
-    let value_table: Vec<_> = feature_value_hash
-        .into_iter()
-        .map(|hash| {
-            // The hash does not contains 0 as its key. Add it.
-            let mut values =
-                (0..1).chain(hash.keys().cloned()).collect::<Vec<_>>();
-            values.sort();
-            // println!("Sorted values: {:?}", values);
-            values
-        })
-        .collect();
-
-    // Find indices for each value
-    let mut feature_indices: Vec<Vec<u32>> = Vec::new();
-    feature_indices.resize(stats.max_feature_id, Vec::new());
-    for output_name in output_files {
-        let output = File::open(&output_name)?;
-        for (instance_index, instance) in
-            SvmLightFile::instances(output).enumerate()
-        {
-            let instance = instance?;
-
-            // does not comiple // TODO some features are skipped
-            for (id, value) in instance.value_iter() {
-                let values = &value_table[id - 1];
-                let index = values.binary_search(&&(value as u32));
-                feature_indices[id - 1].push(index.unwrap() as u32);
-                // assert_eq!(
-                //     feature_indices[feature.id - 1].len(),
-                //     instance_index
-                // );
+            (_, Record::Feature(id, name)) => {
+                names.insert(id, name);
+                continue;
             }
+        };
+        instances.push(instance);
+
+        // Notify the user every 5000 lines.
+        if line_number % 5000 == 0 {
+            info!("Processed {} lines", line_number);
         }
     }
+    if !consecutive {
+        // The sort is stable, so the order within a query is kept.
+        instances.sort_by_key(|instance| instance.qid());
+    }
 
-    println!("Value table 0: {:?}", value_table[0]);
-    println!("Index table 0: {:?}", feature_indices[0]);
-
-    for dist in &value_table {
-        let len = dist.len();
-        if len <= 2 {
-            // std::collections::BitVec;
-        } else if len <= ::std::u8::MAX as usize {
-        } else if len <= ::std::u16::MAX as usize {
-        } else if len <= ::std::u32::MAX as usize {
+    // Scaled values, grouped by features.
+    let mut columns: Vec<Vec<i32>> = vec![Vec::new(); nfeatures];
+    let mut targets: Vec<Value> = Vec::new();
+    let mut boundaries: Vec<i32> = Vec::new();
+    let mut nmissing = 0;
+    for (index, instance) in instances.iter().enumerate() {
+        if index == 0 || instance.qid() != instances[index - 1].qid() {
+            boundaries.push(index as i32);
+        }
+        targets.push(instance.label());
+        for (fid, column) in columns.iter_mut().enumerate() {
+            let value = match instance.get(fid + 1) {
+                Some(value) => value,
+                None => {
+                    nmissing += 1;
+                    0.0
+                }
+            };
+            column.push(scales[fid].scale(value) as i32);
         }
-
-        println!("len {}", len);
     }
-    // Generate bin names
-    let bin_files: Vec<_> = input_files
+    boundaries.push(targets.len() as i32);
+    if nmissing > 0 {
+        warn!(
+            "{} missing feature values are written as 0, the binary \
+             format can not tell them",
+            nmissing
+        );
+    }
+
+    let features: Vec<Feature> = columns
         .iter()
-        .map(|input| change_extension(input, "bin"))
+        .enumerate()
+        .map(|(index, column)| {
+            Feature::new(
+                names.get(index + 1).unwrap_or("").to_string(),
+                feature_stats[index],
+                scales[index],
+                column,
+            )
+        })
         .collect();
-    for bin_name in bin_files {}
-
-    // stats.iter().map(|(feature_index, stat)| {
-    //     0
-    // });
-
-    // for (feature_index, stat) in &mut stats {
-    //     let range = stat.max - stat.min;
-    //     if range < MAX_FEATURE_VALUE as f64 {
-    //         stat.factor = MAX_FEATURE_VALUE as f64 / range;
-    //     } else {
-    //         stat.factor = MAX_FEATURE_VALUE as f64 / (range + 1.0).ln();
-    //         stat.log = true;
-    //     }
-    // }
+
+    let mut writer = BufWriter::new(output);
+    write(&mut writer, &targets, &features, if ranking {
+        Some(&boundaries)
+    } else {
+        None
+    })?;
+    writer.flush()?;
     Ok(())
 }
 
-fn convert(
-    input: &str,
-    output: &str,
-    stats: &svmlight::FilesStats,
+/// Writes the header, the targets, the features and the optional
+/// query boundaries. The queries count is in the header, after the
+/// feature types, and the boundaries are at the end.
+fn write<W: Write>(
+    writer: &mut W,
+    targets: &[Value],
+    features: &[Feature],
+    boundaries: Option<&[i32]>,
 ) -> Result<()> {
-    // let file = svmlight::SvmLightFile::open(input)?;
+    // Each feature block is prefixed by its size in the header.
+    let blocks = features
+        .iter()
+        .map(|feature| {
+            let mut block = Vec::new();
+            feature.write(&mut block).map(|_| block)
+        })
+        .collect::<Result<Vec<Vec<u8>>>>()?;
+
+    writer.write_i32::<BigEndian>(BINARY_VERSION)?;
+    writer.write_i32::<BigEndian>(features.len() as i32)?;
+    writer.write_i32::<BigEndian>(targets.len() as i32)?;
+    for block in blocks.iter() {
+        writer.write_i32::<BigEndian>(block.len() as i32)?;
+    }
+    for feature in features.iter() {
+        writer.write_u8(feature.feature_type().to_u8())?;
+    }
+    if let Some(boundaries) = boundaries {
+        writer.write_i32::<BigEndian>(boundaries.len() as i32 - 1)?;
+    }
 
-    // 1. Scale the values according to svmlight
-    // for line in file.instances() {}
+    for &target in targets.iter() {
+        writer.write_f64::<BigEndian>(target)?;
+    }
+
+    for block in blocks.iter() {
+        writer.write_all(block)?;
+    }
 
-    // Load the values into a hash map
-    // Convert the hash map into a sorted vec of values
-    // Update each feature to contain index into the vec
+    if let Some(boundaries) = boundaries {
+        for &boundary in boundaries.iter() {
+            writer.write_i32::<BigEndian>(boundary)?;
+        }
+    }
     Ok(())
 }
 
-// pub fn run<'de, Flags: Deserialize<'de>>(
-//             exec: fn(Flags, &Config) -> Result<()>,
-//             config: &Config,
-//             usage: &str,
-//             args: &[String],
-//             options_first: bool) -> Result<()> {
-//     let docopt = Docopt::new(usage).unwrap()
-//         .options_first(options_first)
-//         .argv(args.iter().map(|s| &s[..]))
-//         .help(true);
+/// Loads a data set from a binary file generated by `rforests
+/// genbin`. The feature values and names are restored. If the file
+/// has no query boundaries, all the instances belong to one query.
+///
+/// # Examples
+///
+/// ```
+/// use rforests::format::svmlight::FilesStats;
+/// use rforests::genbin;
+/// use rforests::util::Error;
+///
+/// let s = "# @Feature id:1 name:bm25
+/// 3.0 qid:1 1:5.0 2:1.0
+/// 2.0 qid:1 1:7.0
+/// 1.0 qid:2 2:3.0";
+/// let stats = FilesStats::parse_reader(s.as_bytes()).unwrap();
+///
+/// let mut bin = Vec::new();
+/// genbin::convert(s.as_bytes(), &mut bin, &stats, true, false).unwrap();
+/// // The queries count follows the version, the features count, the
+/// // targets count, the feature block sizes and the feature types.
+/// assert_eq!(&bin[22..26], &[0, 0, 0, 2]);
+/// let dataset = genbin::load(bin.as_slice()).unwrap();
+///
+/// assert_eq!(dataset.len(), 3);
+/// assert_eq!(dataset[1].label(), 2.0);
+/// assert_eq!(dataset[1].value(1), 7.0);
+/// assert_eq!(dataset[2].value(2), 3.0);
+/// assert_eq!(dataset.query_iter().count(), 2);
+/// assert_eq!(dataset.feature_names().get(1), Some("bm25"));
+/// assert_eq!(dataset.feature_names().get(2), None);
+///
+/// // A truncated header fails to be read, and a truncated body does
+/// // not match the header.
+/// match genbin::load(&bin[..10]) {
+///     Err(Error::Io { .. }) => {}
+///     _ => panic!("Expected an IO error"),
+/// }
+/// match genbin::load(&bin[..30]) {
+///     Err(Error::Parse { .. }) => {}
+///     _ => panic!("Expected a parse error"),
+/// }
+/// ```
+pub fn load<R: Read>(reader: R) -> ParseResult<DataSet> {
+    let mut reader = BufReader::new(reader);
+
+    let version = reader.read_i32::<BigEndian>()?;
+    if version != BINARY_VERSION {
+        return Err(Error::parse(
+            &version.to_string(),
+            "Unsupported binary format version",
+        ));
+    }
+    let nfeatures = reader.read_i32::<BigEndian>()? as usize;
+    let ntargets = reader.read_i32::<BigEndian>()? as usize;
+    let block_sizes = (0..nfeatures)
+        .map(|_| reader.read_i32::<BigEndian>().map(|size| size as usize))
+        .collect::<::std::io::Result<Vec<usize>>>()?;
+    let feature_types = (0..nfeatures)
+        .map(|_| reader.read_u8().map_err(Error::from))
+        .map(|result| result.and_then(FeatureType::from_u8))
+        .collect::<ParseResult<Vec<FeatureType>>>()?;
+
+    // The queries count is only present in the files of the ranking
+    // option, which is told by the size of the rest of the file.
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest)?;
+    let data_len = 8 * ntargets + block_sizes.iter().sum::<usize>();
+    let mut reader = Cursor::new(rest.as_slice());
+    let nqueries = if rest.len() == data_len {
+        None
+    } else {
+        let nqueries = reader.read_i32::<BigEndian>()?;
+        if nqueries < 0 ||
+            rest.len() != 4 + data_len + 4 * (nqueries as usize + 1)
+        {
+            return Err(invalid("The file size does not match the header"));
+        }
+        Some(nqueries as usize)
+    };
+
+    let targets = (0..ntargets)
+        .map(|_| reader.read_f64::<BigEndian>())
+        .collect::<::std::io::Result<Vec<Value>>>()?;
+
+    let features = feature_types
+        .iter()
+        .enumerate()
+        .map(|(index, &feature_type)| {
+            Feature::read(&mut reader, index + 1, feature_type, ntargets)
+        })
+        .collect::<ParseResult<Vec<Feature>>>()?;
+
+    let boundaries = match nqueries {
+        Some(nqueries) => (0..nqueries + 1)
+            .map(|_| reader.read_i32::<BigEndian>().map(|b| b as usize))
+            .collect::<::std::io::Result<Vec<usize>>>()?,
+        None => vec![0, ntargets],
+    };
 
-//     let flags = docopt.deserialize().map_err(|e| {
-//         let code = if e.fatal() {1} else {0};
-//         CliError::new(e.to_string().into(), code)
-//     })?;
+    let mut instances = Vec::with_capacity(ntargets);
+    for (qid, range) in boundaries.windows(2).enumerate() {
+        for index in range[0]..range[1] {
+            let values: Vec<Value> = features
+                .iter()
+                .map(|feature| feature.value(index))
+                .collect();
+            instances.push((targets[index], qid, values));
+        }
+    }
+    if instances.len() != ntargets {
+        return Err(invalid("Query boundaries do not match the targets count"));
+    }
 
-//     exec(flags, config);
+    let mut names = FeatureNames::new();
+    for (index, feature) in features.iter().enumerate() {
+        if !feature.name().is_empty() {
+            names.insert(index + 1, feature.name().to_string());
+        }
+    }
+    let mut dataset: DataSet = instances.into_iter().collect();
+    dataset.add_feature_names(names);
+    Ok(dataset)
+}
 
-//     // let mut f = File::open(config.filename)?;
+/// Returns a parse error of the binary file, which has no offending
+/// token.
+fn invalid(message: &str) -> Error {
+    Error::Parse {
+        file: None,
+        line: None,
+        token: None,
+        message: message.to_string(),
+    }
+}
 
-//     // let mut contents = String::new();
-//     // f.read_to_string(&mut contents)?;
+pub fn main<'a>(matches: &ArgMatches<'a>) {
+    let files: Vec<String> = matches
+        .values_of("file")
+        .unwrap()
+        .map(|file| file.to_string())
+        .collect();
+    let ranking = matches.is_present("ranking");
+    let regroup = matches.is_present("regroup-queries");
 
-//     // println!("With text:\n{}", contents);
+    if let Err(e) = execute(&files, ranking, regroup) {
+        exit_on_error(e);
+    }
+}
 
-//     // let mut results: Vec<String> = Vec::new();
+/// Returns the genbin command.
+pub fn clap_command<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("genbin")
+        .about("Generate binary files")
+        .arg(
+            Arg::with_name("ranking")
+                .short("r")
+                .long("ranking")
+                .display_order(1)
+                .help("Support ranking, keeping the query boundaries"),
+        )
+        .arg(train::regroup_queries_arg().display_order(2))
+        .arg(
+            Arg::with_name("file")
+                .value_name("FILE")
+                .multiple(true)
+                .required(true)
+                .help("SVMLight files to convert"),
+        )
+}
 
-//     // for line in contents.lines() {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_regroup() {
+        let s = "3.0 qid:1 1:5.0
+2.0 qid:2 1:7.0
+
+1.0 qid:1 1:3.0";
+        let stats = FilesStats::parse_reader(s.as_bytes()).unwrap();
+
+        let mut bin = Vec::new();
+        let e = convert(s.as_bytes(), &mut bin, &stats, true, false)
+            .unwrap_err();
+        match e.downcast_ref::<Error>() {
+            Some(&Error::Parse {
+                line, ref token, ..
+            }) => {
+                assert_eq!(line, Some(4));
+                assert_eq!(token, &Some("1".to_string()));
+            }
+            _ => panic!("Expected a parse error"),
+        }
 
-//     unimplemented!()
-// }
+        let mut bin = Vec::new();
+        convert(s.as_bytes(), &mut bin, &stats, true, true).unwrap();
+        let dataset = load(bin.as_slice()).unwrap();
+        let labels: Vec<Value> = dataset.label_iter().collect();
+        assert_eq!(labels, vec![3.0, 1.0, 2.0]);
+        assert_eq!(dataset.query_iter().count(), 2);
+    }
+}
@@ -13,6 +13,10 @@ extern crate scoped_threadpool;
 #[macro_use]
 extern crate lazy_static;
 extern crate num_cpus;
+extern crate byteorder;
+extern crate rand;
+extern crate flate2;
+extern crate ruzstd;
 
 use clap::App;
 
@@ -20,23 +24,47 @@ pub mod util;
 pub mod format;
 pub mod metric;
 pub mod train;
+pub mod predict;
+pub mod genbin;
+pub mod eval;
+pub mod compare;
 
 pub fn main() {
     env_logger::init().unwrap();
 
     let train_command = train::clap_command();
+    let predict_command = predict::clap_command();
+    let genbin_command = genbin::clap_command();
+    let eval_command = eval::clap_command();
+    let compare_command = compare::clap_command();
 
     let matches = App::new("rforests")
         .version(crate_version!())
         .author(crate_authors!())
         .about("A Rust library of tree-based learning algorithms")
         .subcommand(train_command)
+        .subcommand(predict_command)
+        .subcommand(genbin_command)
+        .subcommand(eval_command)
+        .subcommand(compare_command)
         .get_matches();
 
     match matches.subcommand_name() {
         Some("train") => train::main(
             matches.subcommand_matches("train").unwrap(),
         ),
+        Some("predict") => predict::main(
+            matches.subcommand_matches("predict").unwrap(),
+        ),
+        Some("genbin") => genbin::main(
+            matches.subcommand_matches("genbin").unwrap(),
+        ),
+        Some("eval") => eval::main(
+            matches.subcommand_matches("eval").unwrap(),
+        ),
+        Some("compare") => compare::main(
+            matches.subcommand_matches("compare").unwrap(),
+        ),
         _ => (),
     }
 }
@@ -1,12 +1,22 @@
-use super::Measure;
+use super::{Gain, Measure};
 
 pub struct DCGScorer {
     truncation_level: usize,
+    gain: Gain,
 }
 
 impl DCGScorer {
     pub fn new(truncation_level: usize) -> DCGScorer {
-        DCGScorer { truncation_level: truncation_level }
+        DCGScorer {
+            truncation_level: truncation_level,
+            gain: Gain::Exponential,
+        }
+    }
+
+    /// Sets the gain of the labels.
+    pub fn with_gain(mut self, gain: Gain) -> DCGScorer {
+        self.gain = gain;
+        self
     }
 
     // Maybe cache the values. But I haven't come up with a method to
@@ -16,13 +26,13 @@ impl DCGScorer {
     }
 
     fn gain(&self, score: f64) -> f64 {
-        score.exp2() - 1.0
+        self.gain.apply(score)
     }
 }
 
 impl Measure for DCGScorer {
     fn name(&self) -> String {
-        format!("DCG@{}", self.truncation_level)
+        format!("DCG@{}{}", self.truncation_level, self.gain.suffix())
     }
 
     fn get_k(&self) -> usize {
@@ -113,4 +123,14 @@ mod test {
             });
         assert!(check);
     }
+
+    #[test]
+    fn test_dcg_linear_gain() {
+        let dcg = DCGScorer::new(10).with_gain(Gain::Linear);
+        assert_eq!(dcg.name(), "DCG@10:gain=linear");
+        assert_eq!(
+            dcg.measure(&vec![3.0, 2.0, 4.0]),
+            3.0 / 2.0_f64.log2() + 2.0 / 3.0_f64.log2() + 4.0 / 4.0_f64.log2()
+        );
+    }
 }
@@ -0,0 +1,128 @@
+use super::Measure;
+
+/// Expected reciprocal rank (Chapelle et al., 2009). The label l at
+/// rank r satisfies the user with the probability (2^l - 1) / 2^max,
+/// and the score is the expectation of 1/r over the rank r where the
+/// user stops, within the top k.
+pub struct ERRScorer {
+    truncation_level: usize,
+    max_label: f64,
+}
+
+impl ERRScorer {
+    /// Creates the scorer with the max label 4, as in the LETOR and
+    /// MSLR data sets.
+    pub fn new(truncation_level: usize) -> ERRScorer {
+        ERRScorer {
+            truncation_level: truncation_level,
+            max_label: 4.0,
+        }
+    }
+
+    /// Sets the max label. Greater labels are taken as the max.
+    pub fn with_max_label(mut self, max_label: f64) -> ERRScorer {
+        self.max_label = max_label;
+        self
+    }
+
+    /// The probability that the label satisfies the user.
+    fn satisfaction(&self, label: f64) -> f64 {
+        let label = label.min(self.max_label).max(0.0);
+        (label.exp2() - 1.0) / self.max_label.exp2()
+    }
+}
+
+impl Measure for ERRScorer {
+    fn name(&self) -> String {
+        if self.max_label == 4.0 {
+            format!("ERR@{}", self.truncation_level)
+        } else {
+            format!("ERR@{}:max={}", self.truncation_level, self.max_label)
+        }
+    }
+
+    fn get_k(&self) -> usize {
+        self.truncation_level
+    }
+
+    fn measure(&self, labels: &[f64]) -> f64 {
+        let n = usize::min(labels.len(), self.truncation_level);
+        let mut unsatisfied = 1.0;
+        let mut score = 0.0;
+        for i in 0..n {
+            let satisfaction = self.satisfaction(labels[i]);
+            score += unsatisfied * satisfaction / (i as f64 + 1.0);
+            unsatisfied *= 1.0 - satisfaction;
+        }
+        score
+    }
+
+    fn swap_changes(&self, labels: &[f64]) -> Vec<Vec<f64>> {
+        let nlabels = labels.len();
+        let k = self.truncation_level;
+
+        let mut changes = vec![vec![0.0; nlabels]; nlabels];
+
+        // unsatisfied[i]: probability that the user is not satisfied
+        // before i. sums[i]: the score of the labels in [0, i).
+        let satisfactions: Vec<f64> =
+            labels.iter().map(|&label| self.satisfaction(label)).collect();
+        let mut unsatisfied = vec![1.0; nlabels + 1];
+        let mut sums = vec![0.0; nlabels + 1];
+        for i in 0..nlabels {
+            unsatisfied[i + 1] = unsatisfied[i] * (1.0 - satisfactions[i]);
+            sums[i + 1] = sums[i];
+            if i < k {
+                sums[i + 1] +=
+                    unsatisfied[i] * satisfactions[i] / (i as f64 + 1.0);
+            }
+        }
+
+        let term = |i: usize, satisfaction: f64, unsatisfied: f64| if i < k {
+            unsatisfied * satisfaction / (i as f64 + 1.0)
+        } else {
+            0.0
+        };
+        for i in 0..usize::min(k, nlabels) {
+            for j in i + 1..nlabels {
+                let (si, sj) = (satisfactions[i], satisfactions[j]);
+                if si == sj {
+                    continue;
+                }
+
+                // The labels after i are less likely to be reached by
+                // the ratio, and the labels after j are unchanged.
+                let ratio = (1.0 - sj) / (1.0 - si);
+                let between = sums[j] - sums[i + 1];
+                let origin = term(i, si, unsatisfied[i]) + between +
+                    term(j, sj, unsatisfied[j]);
+                let swapped = term(i, sj, unsatisfied[i]) + between * ratio +
+                    term(j, si, unsatisfied[j] * ratio);
+                changes[i][j] = origin - swapped;
+                changes[j][i] = changes[i][j];
+            }
+        }
+
+        changes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_err_score() {
+        let err = ERRScorer::new(10).with_max_label(2.0);
+        let r0 = 3.0 / 4.0;
+        let r1 = 1.0 / 4.0;
+        assert_eq!(
+            err.measure(&vec![2.0, 1.0, 0.0]),
+            r0 + (1.0 - r0) * r1 / 2.0
+        );
+
+        let err = ERRScorer::new(1).with_max_label(2.0);
+        assert_eq!(err.measure(&vec![2.0, 1.0, 0.0]), r0);
+        assert_eq!(err.measure(&vec![3.0]), r0);
+    }
+}
@@ -0,0 +1,120 @@
+use super::{is_relevant, Measure};
+
+/// Average precision, the mean over the relevant labels of the
+/// precision at their ranks. Only the ranks within the top k count,
+/// while all the relevant labels are in the denominator. The mean
+/// over the queries is the MAP.
+pub struct APScorer {
+    truncation_level: usize,
+}
+
+impl APScorer {
+    pub fn new(truncation_level: usize) -> APScorer {
+        APScorer { truncation_level: truncation_level }
+    }
+}
+
+impl Measure for APScorer {
+    fn name(&self) -> String {
+        format!("MAP@{}", self.truncation_level)
+    }
+
+    fn get_k(&self) -> usize {
+        self.truncation_level
+    }
+
+    fn measure(&self, labels: &[f64]) -> f64 {
+        let mut count = 0;
+        let mut sum = 0.0;
+        for (i, &label) in labels.iter().enumerate() {
+            if !is_relevant(label) {
+                continue;
+            }
+            count += 1;
+            if i < self.truncation_level {
+                sum += count as f64 / (i as f64 + 1.0);
+            }
+        }
+
+        if count == 0 { 0.0 } else { sum / count as f64 }
+    }
+
+    fn swap_changes(&self, labels: &[f64]) -> Vec<Vec<f64>> {
+        let nlabels = labels.len();
+
+        let mut changes = vec![vec![0.0; nlabels]; nlabels];
+
+        // counts[i]: number of relevant labels in [0, i].
+        // sums[i]: sum of 1 / (r + 1) for relevant r in [0, i) and
+        // within the top k.
+        let mut counts = vec![0; nlabels];
+        let mut sums = vec![0.0; nlabels + 1];
+        let mut count = 0;
+        for (i, &label) in labels.iter().enumerate() {
+            let relevant = is_relevant(label);
+            if relevant {
+                count += 1;
+            }
+            counts[i] = count;
+            sums[i + 1] = sums[i];
+            if relevant && i < self.truncation_level {
+                sums[i + 1] += 1.0 / (i as f64 + 1.0);
+            }
+        }
+        if count == 0 {
+            return changes;
+        }
+
+        let k = self.truncation_level;
+        let precision = |count: usize, i: usize| if i < k {
+            count as f64 / (i as f64 + 1.0)
+        } else {
+            0.0
+        };
+        for i in 0..usize::min(k, nlabels) {
+            for j in i + 1..nlabels {
+                let between = sums[j] - sums[i + 1];
+                let change = match (is_relevant(labels[i]),
+                                    is_relevant(labels[j])) {
+                    // The relevant label at i moves down to j, and
+                    // the relevant labels between lose one count.
+                    (true, false) => {
+                        precision(counts[i], i) + between -
+                            precision(counts[j], j)
+                    }
+                    // The relevant label at j moves up to i, and the
+                    // relevant labels between gain one count.
+                    (false, true) => {
+                        precision(counts[j], j) -
+                            precision(counts[i] + 1, i) -
+                            between
+                    }
+                    _ => 0.0,
+                };
+                changes[i][j] = change / count as f64;
+                changes[j][i] = changes[i][j];
+            }
+        }
+
+        changes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ap_score() {
+        let ap = APScorer::new(10);
+        // Relevant at ranks 1 and 3.
+        assert_eq!(
+            ap.measure(&vec![1.0, 0.0, 2.0, 0.0]),
+            (1.0 + 2.0 / 3.0) / 2.0
+        );
+        assert_eq!(ap.measure(&vec![0.0, 0.0]), 0.0);
+
+        let ap = APScorer::new(2);
+        assert_eq!(ap.measure(&vec![1.0, 0.0, 2.0, 0.0]), 1.0 / 2.0);
+    }
+}
@@ -1,7 +1,20 @@
 pub mod dcg;
 pub mod ndcg;
+pub mod map;
+pub mod err;
+pub mod reciprocal_rank;
+pub mod precision;
 pub use self::dcg::DCGScorer;
 pub use self::ndcg::NDCGScorer;
+pub use self::map::APScorer;
+pub use self::err::ERRScorer;
+pub use self::reciprocal_rank::RRScorer;
+pub use self::precision::PrecisionScorer;
+
+use util::{Error, ParseResult};
+
+/// Names of the metrics known to `new`.
+pub const NAMES: [&str; 6] = ["NDCG", "DCG", "MAP", "ERR", "RR", "P"];
 
 pub trait Measure: Sync {
     fn get_k(&self) -> usize;
@@ -15,10 +28,264 @@ pub trait Measure: Sync {
     fn name(&self) -> String;
 }
 
-pub fn new(name: &str, k: usize) -> Option<Box<Measure>> {
+pub fn new(name: &str, k: usize) -> Option<Box<dyn Measure>> {
     match name {
         "NDCG" => Some(Box::new(NDCGScorer::new(k))),
         "DCG" => Some(Box::new(DCGScorer::new(k))),
+        "MAP" => Some(Box::new(APScorer::new(k))),
+        "ERR" => Some(Box::new(ERRScorer::new(k))),
+        "RR" => Some(Box::new(RRScorer::new(k))),
+        "P" => Some(Box::new(PrecisionScorer::new(k))),
         _ => None,
     }
 }
+
+/// The gain of a label in DCG and NDCG.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gain {
+    /// 2^label - 1, the default.
+    Exponential,
+    /// The label itself.
+    Linear,
+}
+
+impl Gain {
+    pub fn new(name: &str) -> Option<Gain> {
+        match name {
+            "exp" => Some(Gain::Exponential),
+            "linear" => Some(Gain::Linear),
+            _ => None,
+        }
+    }
+
+    /// Name of the gain. For display.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Gain::Exponential => "exp",
+            Gain::Linear => "linear",
+        }
+    }
+
+    fn apply(&self, label: f64) -> f64 {
+        match *self {
+            Gain::Exponential => label.exp2() - 1.0,
+            Gain::Linear => label,
+        }
+    }
+
+    /// The suffix of the metric name, empty for the default gain.
+    fn suffix(&self) -> String {
+        match *self {
+            Gain::Exponential => String::new(),
+            gain => format!(":gain={}", gain.name()),
+        }
+    }
+}
+
+/// Parses a metric spec, "NAME[@k][:OPTION]...", for example "NDCG@10"
+/// or "ERR@20:max=2". The name is one of `NAMES`, in any case, and k
+/// defaults to `default_k`. The options are
+///
+/// * gain=exp|linear: the gain of the labels in DCG and NDCG, 2^l - 1
+///   by default.
+/// * max=LABEL: the max label of ERR, 4 by default.
+///
+/// # Examples
+///
+/// ```
+/// use rforests::metric;
+///
+/// assert_eq!(metric::parse("NDCG@5", 10).unwrap().name(), "NDCG@5");
+/// assert_eq!(metric::parse("map", 10).unwrap().name(), "MAP@10");
+/// assert_eq!(
+///     metric::parse("DCG@3:gain=linear", 10).unwrap().name(),
+///     "DCG@3:gain=linear"
+/// );
+/// assert!(metric::parse("AUC@5", 10).is_err());
+/// ```
+pub fn parse(spec: &str, default_k: usize) -> ParseResult<Box<dyn Measure>> {
+    let invalid = |message: String| {
+        Error::Config(format!("Invalid metric \"{}\": {}", spec, message))
+    };
+
+    let mut parts = spec.split(':');
+    let head = parts.next().unwrap();
+    let (name, k) = match head.find('@') {
+        Some(pos) => (&head[..pos], Some(&head[pos + 1..])),
+        None => (head, None),
+    };
+    let name = name.to_uppercase();
+    if !NAMES.contains(&name.as_str()) {
+        return Err(invalid(format!(
+            "unknown metric {}, expected one of {}",
+            name,
+            NAMES.join(", ")
+        )));
+    }
+    let k = match k {
+        Some(k) => match k.parse::<usize>() {
+            Ok(k) if k > 0 => k,
+            _ => {
+                return Err(invalid(format!(
+                    "k should be a positive integer: {}",
+                    k
+                )))
+            }
+        },
+        None => default_k,
+    };
+
+    let mut gain = None;
+    let mut max_label = None;
+    for option in parts {
+        let mut fields = option.splitn(2, '=');
+        match (fields.next().unwrap(), fields.next()) {
+            ("gain", Some(value)) => match Gain::new(value) {
+                Some(value) => gain = Some(value),
+                None => {
+                    return Err(invalid(format!(
+                        "gain should be exp or linear: {}",
+                        value
+                    )))
+                }
+            },
+            ("max", Some(value)) => match value.parse::<f64>() {
+                Ok(value) if value > 0.0 => max_label = Some(value),
+                _ => {
+                    return Err(invalid(format!(
+                        "max label should be a positive number: {}",
+                        value
+                    )))
+                }
+            },
+            _ => {
+                return Err(invalid(format!("unknown option {}", option)))
+            }
+        }
+    }
+
+    if gain.is_some() && name != "NDCG" && name != "DCG" {
+        return Err(invalid(format!("{} does not take a gain", name)));
+    }
+    if max_label.is_some() && name != "ERR" {
+        return Err(invalid(format!("{} does not take a max label", name)));
+    }
+
+    let gain = gain.unwrap_or(Gain::Exponential);
+    let metric: Box<dyn Measure> = match name.as_str() {
+        "NDCG" => Box::new(NDCGScorer::new(k).with_gain(gain)),
+        "DCG" => Box::new(DCGScorer::new(k).with_gain(gain)),
+        "ERR" => Box::new(
+            ERRScorer::new(k).with_max_label(max_label.unwrap_or(4.0)),
+        ),
+        name => new(name, k).unwrap(),
+    };
+    Ok(metric)
+}
+
+/// Whether a label is relevant for the binary metrics, MAP, RR and P.
+fn is_relevant(label: f64) -> bool {
+    label > 0.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Checks the swap changes against the scores of the swapped
+    /// labels.
+    fn check_swap_changes(metric: &Box<dyn Measure>, labels: &[f64]) {
+        let origin = metric.measure(labels);
+        let changes = metric.swap_changes(labels);
+        for i in 0..labels.len() {
+            for j in 0..labels.len() {
+                if i == j {
+                    continue;
+                }
+
+                let mut swapped = labels.to_vec();
+                swapped.swap(i, j);
+                let expected = origin - metric.measure(&swapped);
+                assert!(
+                    (changes[i][j] - expected).abs() < 1e-9,
+                    "{} swap ({}, {}) of {:?}: {} != {}",
+                    metric.name(),
+                    i,
+                    j,
+                    labels,
+                    changes[i][j],
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("NDCG@5", 10).unwrap().name(), "NDCG@5");
+        assert_eq!(parse("P", 10).unwrap().name(), "P@10");
+        assert_eq!(parse("ndcg:gain=exp", 3).unwrap().name(), "NDCG@3");
+        assert_eq!(
+            parse("NDCG@5:gain=linear", 10).unwrap().name(),
+            "NDCG@5:gain=linear"
+        );
+        assert_eq!(parse("ERR@20:max=2", 10).unwrap().name(), "ERR@20:max=2");
+        assert_eq!(parse("ERR@20:max=4", 10).unwrap().name(), "ERR@20");
+
+        let message = |spec: &str| parse(spec, 10).err().unwrap().to_string();
+        assert_eq!(
+            message("AUC@5"),
+            "Invalid metric \"AUC@5\": unknown metric AUC, expected one of \
+             NDCG, DCG, MAP, ERR, RR, P"
+        );
+        assert_eq!(
+            message("NDCG@"),
+            "Invalid metric \"NDCG@\": k should be a positive integer: "
+        );
+        assert_eq!(
+            message("NDCG@0"),
+            "Invalid metric \"NDCG@0\": k should be a positive integer: 0"
+        );
+        assert_eq!(
+            message("NDCG:gain=log"),
+            "Invalid metric \"NDCG:gain=log\": gain should be exp or \
+             linear: log"
+        );
+        assert_eq!(
+            message("ERR:max=-1"),
+            "Invalid metric \"ERR:max=-1\": max label should be a positive \
+             number: -1"
+        );
+        assert_eq!(
+            message("MAP:gain=linear"),
+            "Invalid metric \"MAP:gain=linear\": MAP does not take a gain"
+        );
+        assert_eq!(
+            message("NDCG:max=2"),
+            "Invalid metric \"NDCG:max=2\": NDCG does not take a max label"
+        );
+        assert_eq!(
+            message("NDCG@5:cutoff"),
+            "Invalid metric \"NDCG@5:cutoff\": unknown option cutoff"
+        );
+    }
+
+    #[test]
+    fn test_swap_changes() {
+        let labels_list = vec![
+            vec![3.0, 2.0, 4.0],
+            vec![0.0, 1.0, 0.0, 2.0, 0.0, 4.0, 1.0, 0.0],
+            vec![2.0, 0.0, 0.0, 1.0, 3.0, 0.0, 0.0],
+            vec![0.0, 0.0, 0.0],
+            vec![1.0],
+        ];
+        for &name in ["MAP", "ERR", "RR", "P"].iter() {
+            for &k in [1, 3, 5, 10].iter() {
+                let metric = new(name, k).unwrap();
+                for labels in labels_list.iter() {
+                    check_swap_changes(&metric, labels);
+                }
+            }
+        }
+    }
+}
@@ -1,12 +1,15 @@
-use super::Measure;
+use super::{Gain, Measure};
 use super::DCGScorer;
 
 lazy_static! {
-    static ref DISCOUNT: Vec<f64> = (0..128).map(|i| 1.0 / (i as f64 + 2.0).log2()).collect();
+    static ref DISCOUNT: Vec<f64> = (0..128)
+        .map(|i| 1.0 / (i as f64 + 2.0).log2())
+        .collect();
 }
 
 pub struct NDCGScorer {
     truncation_level: usize,
+    gain: Gain,
     dcg: DCGScorer,
 }
 
@@ -14,10 +17,18 @@ impl NDCGScorer {
     pub fn new(truncation_level: usize) -> NDCGScorer {
         NDCGScorer {
             truncation_level: truncation_level,
+            gain: Gain::Exponential,
             dcg: DCGScorer::new(truncation_level),
         }
     }
 
+    /// Sets the gain of the labels.
+    pub fn with_gain(mut self, gain: Gain) -> NDCGScorer {
+        self.gain = gain;
+        self.dcg = self.dcg.with_gain(gain);
+        self
+    }
+
     // Maybe cache the values. But I haven't come up with a method to
     // share the cached values.
     fn discount(&self, i: usize) -> f64 {
@@ -30,7 +41,7 @@ impl NDCGScorer {
     }
 
     fn gain(&self, score: f64) -> f64 {
-        score.exp2() - 1.0
+        self.gain.apply(score)
     }
 
     fn max_dcg(&self, labels: &[f64]) -> f64 {
@@ -44,7 +55,7 @@ impl NDCGScorer {
 
 impl Measure for NDCGScorer {
     fn name(&self) -> String {
-        format!("NDCG@{}", self.truncation_level)
+        format!("NDCG@{}{}", self.truncation_level, self.gain.suffix())
     }
 
     fn get_k(&self) -> usize {
@@ -0,0 +1,71 @@
+use super::{is_relevant, Measure};
+
+/// Precision at k, the fraction of relevant labels in the top k.
+pub struct PrecisionScorer {
+    truncation_level: usize,
+}
+
+impl PrecisionScorer {
+    pub fn new(truncation_level: usize) -> PrecisionScorer {
+        PrecisionScorer { truncation_level: truncation_level }
+    }
+
+    fn relevance(&self, label: f64) -> f64 {
+        if is_relevant(label) { 1.0 } else { 0.0 }
+    }
+}
+
+impl Measure for PrecisionScorer {
+    fn name(&self) -> String {
+        format!("P@{}", self.truncation_level)
+    }
+
+    fn get_k(&self) -> usize {
+        self.truncation_level
+    }
+
+    fn measure(&self, labels: &[f64]) -> f64 {
+        let n = usize::min(labels.len(), self.truncation_level);
+        if n == 0 {
+            return 0.0;
+        }
+
+        let relevant: f64 =
+            labels[..n].iter().map(|&label| self.relevance(label)).sum();
+        relevant / n as f64
+    }
+
+    fn swap_changes(&self, labels: &[f64]) -> Vec<Vec<f64>> {
+        let nlabels = labels.len();
+        let n = usize::min(nlabels, self.truncation_level);
+
+        let mut changes = vec![vec![0.0; nlabels]; nlabels];
+
+        // Only the swaps between the top n and the rest change the
+        // score.
+        for i in 0..n {
+            for j in n..nlabels {
+                changes[i][j] = (self.relevance(labels[i]) -
+                                     self.relevance(labels[j])) /
+                    n as f64;
+                changes[j][i] = changes[i][j];
+            }
+        }
+
+        changes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_precision_score() {
+        let precision = PrecisionScorer::new(2);
+        assert_eq!(precision.measure(&vec![3.0, 0.0, 4.0]), 0.5);
+        assert_eq!(precision.measure(&vec![0.0, 0.0, 4.0]), 0.0);
+        assert_eq!(precision.measure(&vec![1.0]), 1.0);
+        assert_eq!(precision.measure(&vec![]), 0.0);
+    }
+}
@@ -0,0 +1,90 @@
+use super::{is_relevant, Measure};
+
+/// Reciprocal rank, 1/r for the first relevant label at rank r within
+/// the top k, or 0 if there is none. The mean over the queries is the
+/// MRR.
+pub struct RRScorer {
+    truncation_level: usize,
+}
+
+impl RRScorer {
+    pub fn new(truncation_level: usize) -> RRScorer {
+        RRScorer { truncation_level: truncation_level }
+    }
+
+    /// The score of the first relevant label at index i.
+    fn reciprocal_rank(&self, i: usize) -> f64 {
+        if i < self.truncation_level {
+            1.0 / (i as f64 + 1.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Measure for RRScorer {
+    fn name(&self) -> String {
+        format!("RR@{}", self.truncation_level)
+    }
+
+    fn get_k(&self) -> usize {
+        self.truncation_level
+    }
+
+    fn measure(&self, labels: &[f64]) -> f64 {
+        match labels.iter().position(|&label| is_relevant(label)) {
+            Some(i) => self.reciprocal_rank(i),
+            None => 0.0,
+        }
+    }
+
+    fn swap_changes(&self, labels: &[f64]) -> Vec<Vec<f64>> {
+        let nlabels = labels.len();
+
+        let mut changes = vec![vec![0.0; nlabels]; nlabels];
+
+        let mut relevant = (0..nlabels).filter(|&i| is_relevant(labels[i]));
+        let first = match relevant.next() {
+            Some(first) => first,
+            None => return changes,
+        };
+        let second = relevant.next().unwrap_or(nlabels);
+        let origin = self.reciprocal_rank(first);
+
+        // Only the swaps with a relevant label at the first index
+        // change the score.
+        for i in 0..first + 1 {
+            for j in i + 1..nlabels {
+                if is_relevant(labels[i]) == is_relevant(labels[j]) {
+                    continue;
+                }
+                let new_first = if i < first {
+                    // A relevant label moves above the first one.
+                    i
+                } else {
+                    // The first relevant label moves down to j.
+                    usize::min(j, second)
+                };
+                changes[i][j] = origin - self.reciprocal_rank(new_first);
+                changes[j][i] = changes[i][j];
+            }
+        }
+
+        changes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rr_score() {
+        let rr = RRScorer::new(10);
+        assert_eq!(rr.measure(&vec![0.0, 0.0, 2.0, 1.0]), 1.0 / 3.0);
+        assert_eq!(rr.measure(&vec![0.0, 0.0]), 0.0);
+
+        let rr = RRScorer::new(2);
+        assert_eq!(rr.measure(&vec![0.0, 0.0, 2.0, 1.0]), 0.0);
+    }
+}
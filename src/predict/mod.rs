@@ -1,29 +1,185 @@
-use util::{Result};
-
-#[derive(Debug, Deserialize)]
-pub struct Args {
-    flag_model: String,
-    flag_tree: String,
-    flag_test: String,
-    flag_output: String,
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use format::csv::{self, CsvFormat};
+use train::dataset::DataSet;
+use train::model;
+use train::{self, Evaluate};
+use util::{exit_on_error, Error, Result};
+
+struct PredictParameter<'a> {
+    model_file_path: &'a str,
+    test_file_path: &'a str,
+    output_file_path: Option<&'a str>,
+    with_ids: bool,
+    csv_format: CsvFormat,
+    regroup_queries: bool,
 }
 
-pub const USAGE: &'static str = "
-Rust implementation of jforests
+impl<'a> PredictParameter<'a> {
+    pub fn parse(matches: &'a ArgMatches<'a>) -> PredictParameter<'a> {
+        PredictParameter {
+            model_file_path: matches.value_of("model-file").unwrap(),
+            test_file_path: matches.value_of("test-file").unwrap(),
+            output_file_path: matches.value_of("output-file"),
+            with_ids: matches.is_present("with-ids"),
+            csv_format: CsvFormat::from_matches(matches),
+            regroup_queries: matches.is_present("regroup-queries"),
+        }
+    }
+
+    pub fn execute(&self) -> Result<()> {
+        let model = model::load_file(self.model_file_path)?;
 
-Usage:
-    rforests predict --model <file> --tree <type> --test <file> --output <file>
-    rforests predict (-h | --help | --version)
+        // The columns of CSV files are mapped to the features of the
+        // model by name.
+        let csv_format = self.csv_format.clone().with_feature_names(
+            model.feature_names().cloned().unwrap_or_default(),
+        );
+        let test_set = DataSet::load_file_with(
+            self.test_file_path,
+            &csv_format,
+            self.regroup_queries,
+        )?;
 
-Options:
-    -m <mode>, --model <model>  Specify model file
-    -t <tree>, --tree <type>    Specify tree type
-    -s <file>, --test <file>    Specify test file
-    -o <file>, --output <file>  Specify output file
-    -h, --help                  Display this message
-";
+        match self.output_file_path {
+            Some(path) => {
+                let output = File::create(path)
+                    .map_err(|e| Error::from(e).in_file(path))?;
+                predict(&*model, &test_set, output, self.with_ids)
+            }
+            None => {
+                let stdout = ::std::io::stdout();
+                predict(&*model, &test_set, stdout.lock(), self.with_ids)
+            }
+        }
+    }
+}
 
-pub fn execute(args: Args) -> Result<()> {
-    debug!("rforests predict args: {:?}", args);
+/// Scores every instance of the data set and writes one score per
+/// line, in the order of the data file even if the queries were
+/// regrouped, see `DataSet::file_order`. If `with_ids` is true, each
+/// line is prefixed by the qid in the data file and the document id,
+/// separated by tabs, see `DataSet::id_iter`.
+///
+/// # Examples
+///
+/// ```
+/// use rforests::predict::predict;
+/// use rforests::train::dataset::DataSet;
+/// use rforests::train::lambdamart::regression_tree::Ensemble;
+///
+/// let dataset: DataSet = vec![(3.0, 1, vec![5.0]), (2.0, 1, vec![7.0])]
+///     .into_iter()
+///     .collect();
+/// let ensemble = Ensemble::new();
+///
+/// let mut output = Vec::new();
+/// predict(&ensemble, &dataset, &mut output, true).unwrap();
+/// assert_eq!(String::from_utf8(output).unwrap(), "1\t0\t0\n1\t1\t0\n");
+///
+/// let s = "3.0 qid:1 1:5.0 # docid = GX000-00-0000000
+/// 2.0 qid:1 1:7.0 # 1A";
+/// let dataset = DataSet::load(::std::io::Cursor::new(s)).unwrap();
+///
+/// let mut output = Vec::new();
+/// predict(&ensemble, &dataset, &mut output, true).unwrap();
+/// assert_eq!(
+///     String::from_utf8(output).unwrap(),
+///     "1\tGX000-00-0000000\t0\n1\t1A\t0\n"
+/// );
+///
+/// let s = "3.0 qid:1 1:5.0 # 1A
+/// 2.0 qid:2 1:7.0 # 2A
+/// 1.0 qid:1 1:3.0 # 1B";
+/// let dataset = DataSet::load_with(s.as_bytes(), true).unwrap();
+///
+/// let mut output = Vec::new();
+/// predict(&ensemble, &dataset, &mut output, true).unwrap();
+/// assert_eq!(
+///     String::from_utf8(output).unwrap(),
+///     "1\t1A\t0\n2\t2A\t0\n1\t1B\t0\n"
+/// );
+/// ```
+pub fn predict<E, W>(
+    model: &E,
+    dataset: &DataSet,
+    writer: W,
+    with_ids: bool,
+) -> Result<()>
+where
+    E: Evaluate + ?Sized,
+    W: Write,
+{
+    let mut writer = BufWriter::new(writer);
+    let ids: Vec<(String, String)> = if with_ids {
+        dataset.id_iter().collect()
+    } else {
+        Vec::new()
+    };
+    for index in dataset.file_order() {
+        let score = model.evaluate(&dataset[index]);
+        match ids.get(index) {
+            Some(&(ref qid, ref docid)) => {
+                writeln!(writer, "{}\t{}\t{}", qid, docid, score)?
+            }
+            None => writeln!(writer, "{}", score)?,
+        }
+    }
+    writer.flush()?;
     Ok(())
 }
+
+pub fn main<'a>(matches: &ArgMatches<'a>) {
+    let param = PredictParameter::parse(matches);
+    if let Err(e) = param.execute() {
+        exit_on_error(e);
+    }
+}
+
+/// Returns the predict command.
+pub fn clap_command<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("predict")
+        .about("Score instances with a trained model")
+        .arg(
+            Arg::with_name("model-file")
+                .short("m")
+                .long("model")
+                .value_name("FILE")
+                .takes_value(true)
+                .empty_values(false)
+                .required(true)
+                .display_order(1)
+                .help("Model file"),
+        )
+        .arg(
+            Arg::with_name("test-file")
+                .short("T")
+                .long("test")
+                .value_name("FILE")
+                .takes_value(true)
+                .empty_values(false)
+                .required(true)
+                .display_order(2)
+                .help("Testing file"),
+        )
+        .arg(
+            Arg::with_name("output-file")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .takes_value(true)
+                .empty_values(false)
+                .display_order(3)
+                .help("Output file for the scores, defaults to stdout"),
+        )
+        .arg(
+            Arg::with_name("with-ids")
+                .long("with-ids")
+                .display_order(4)
+                .help("Prefix each score with the qid and document id"),
+        )
+        .args(&csv::clap_args(5))
+        .arg(train::regroup_queries_arg().display_order(9))
+}
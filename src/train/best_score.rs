@@ -0,0 +1,242 @@
+use metric::Measure;
+
+/// The best score of the training process. If there is validation
+/// data, the best iteration is decided by the validating score,
+/// otherwise by the training score. Several metrics can be measured,
+/// of which one decides the best iteration, and the scores of all of
+/// them at the best iteration are kept.
+pub struct BestScore {
+    names: Vec<String>,
+    // Index of the metric deciding the best iteration.
+    stop: usize,
+    iter: Option<usize>,
+    train: Vec<f64>,
+    validate: Option<Vec<f64>>,
+}
+
+impl BestScore {
+    /// Creates the best score of the metrics of `names`, of which the
+    /// one at index `stop` decides the best iteration.
+    pub fn new(names: &[String], stop: usize) -> BestScore {
+        assert!(stop < names.len());
+        BestScore {
+            names: names.to_vec(),
+            stop: stop,
+            iter: None,
+            train: Vec::new(),
+            validate: None,
+        }
+    }
+
+    /// Updates with the scores of an iteration, in the order of the
+    /// metrics.
+    pub fn update(
+        &mut self,
+        iter: usize,
+        train: &[f64],
+        validate: Option<&[f64]>,
+    ) {
+        let stop = self.stop;
+        let better = match (self.iter, validate, self.validate.as_ref()) {
+            (None, _, _) => true,
+            (_, Some(validate), Some(best)) => validate[stop] > best[stop],
+            (_, Some(_), None) => true,
+            (_, None, _) => train[stop] > self.train[stop],
+        };
+
+        if better {
+            self.iter = Some(iter);
+            self.train = train.to_vec();
+            self.validate = validate.map(|validate| validate.to_vec());
+        }
+    }
+
+    /// Return the best iteration of the validate score.
+    pub fn best_iter(&self) -> Option<usize> {
+        self.validate.as_ref().and(self.iter)
+    }
+
+    /// Returns true if the best validating score is more than
+    /// `early_stop` iterations earlier than `iter`.
+    pub fn should_stop(&self, iter: usize, early_stop: usize) -> bool {
+        self.best_iter()
+            .map(|best_iter| best_iter + early_stop < iter)
+            .unwrap_or(false)
+    }
+}
+
+impl ::std::fmt::Display for BestScore {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        let iter = match self.iter {
+            Some(iter) => iter,
+            None => return Ok(()),
+        };
+
+        writeln!(f, "\nBest score at #iter {}:", iter)?;
+        for (index, name) in self.names.iter().enumerate() {
+            writeln!(
+                f,
+                "{} on training data: {:.4}",
+                name,
+                self.train[index]
+            )?;
+            if let Some(ref validate) = self.validate {
+                writeln!(
+                    f,
+                    "{} on validating data: {:.4}",
+                    name,
+                    validate[index]
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the metrics measured in training, which are the metric to
+/// optimize, the metric deciding the best iteration and early
+/// stopping, which defaults to the former, and the metrics only
+/// reported. The metrics of the same name are measured once. Returns
+/// the metrics along with the index of the stopping metric.
+pub fn training_metrics<'a>(
+    metric: &'a Box<dyn Measure>,
+    stop_metric: Option<&'a Box<dyn Measure>>,
+    report_metrics: &'a [Box<dyn Measure>],
+) -> (Vec<&'a Box<dyn Measure>>, usize) {
+    let mut metrics: Vec<&Box<dyn Measure>> = vec![metric];
+    let mut push = |metric: &'a Box<dyn Measure>| {
+        let name = metric.name();
+        match metrics.iter().position(|m| m.name() == name) {
+            Some(index) => index,
+            None => {
+                metrics.push(metric);
+                metrics.len() - 1
+            }
+        }
+    };
+
+    let stop = stop_metric.map_or(0, |stop_metric| push(stop_metric));
+    for metric in report_metrics.iter() {
+        push(metric);
+    }
+    (metrics, stop)
+}
+
+/// The table of the scores of each iteration. There is a column for
+/// each metric and data set, for example "NDCG@10-T" for the training
+/// data and "NDCG@10-V" for the validating data.
+pub struct ScoreTable {
+    headers: Vec<String>,
+    nsets: usize,
+}
+
+impl ScoreTable {
+    /// Creates a table of the metrics of `names` on the data sets of
+    /// the suffixes, for example ["T", "V"].
+    pub fn new(names: &[String], suffixes: &[&str]) -> ScoreTable {
+        let mut headers = Vec::new();
+        for name in names.iter() {
+            for suffix in suffixes.iter() {
+                headers.push(format!("{}-{}", name, suffix));
+            }
+        }
+        ScoreTable {
+            headers: headers,
+            nsets: suffixes.len(),
+        }
+    }
+
+    /// Returns the header of the table.
+    pub fn header(&self) -> String {
+        let mut header = format!("{:<7}", "#iter");
+        for name in self.headers.iter() {
+            header += &format!(" | {:>9}", name);
+        }
+        header
+    }
+
+    /// Returns a row of the table. `scores` has the scores of the
+    /// metrics on each data set in the order of the suffixes, or None
+    /// if the data set is not measured.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rforests::train::best_score::ScoreTable;
+    ///
+    /// let names = vec!["NDCG@10".to_string(), "MAP@10".to_string()];
+    /// let table = ScoreTable::new(&names, &["T", "V"]);
+    /// assert_eq!(
+    ///     table.header(),
+    ///     "#iter   | NDCG@10-T | NDCG@10-V |  MAP@10-T |  MAP@10-V"
+    /// );
+    /// assert_eq!(
+    ///     table.row(3, &[Some(&[0.5, 0.25][..]), None]),
+    ///     "3       |    0.5000 |           |    0.2500 |"
+    /// );
+    /// ```
+    pub fn row(&self, iteration: usize, scores: &[Option<&[f64]>]) -> String {
+        assert_eq!(scores.len(), self.nsets);
+        let mut row = format!("{:<7}", iteration);
+        for (column, name) in self.headers.iter().enumerate() {
+            let width = usize::max(9, name.len());
+            let (metric, set) = (column / self.nsets, column % self.nsets);
+            row += &match scores[set] {
+                Some(scores) => {
+                    format!(" | {:>width$.4}", scores[metric], width = width)
+                }
+                None => format!(" | {:>width$}", "", width = width),
+            };
+        }
+        row.trim_end().to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_best_score() {
+        let mut best_score = BestScore::new(&["NDCG@10".to_string()], 0);
+        best_score.update(0, &[0.5], Some(&[0.4]));
+        best_score.update(1, &[0.6], Some(&[0.45]));
+        best_score.update(2, &[0.7], Some(&[0.42]));
+        best_score.update(3, &[0.8], Some(&[0.41]));
+
+        assert_eq!(best_score.best_iter(), Some(1));
+        assert!(!best_score.should_stop(3, 2));
+        assert!(best_score.should_stop(4, 2));
+    }
+
+    #[test]
+    fn test_best_score_without_validation() {
+        let mut best_score = BestScore::new(&["NDCG@10".to_string()], 0);
+        best_score.update(0, &[0.5], None);
+        best_score.update(1, &[0.4], None);
+
+        assert_eq!(best_score.best_iter(), None);
+        assert!(!best_score.should_stop(100, 2));
+    }
+
+    #[test]
+    fn test_best_score_stop_metric() {
+        let names = vec!["NDCG@10".to_string(), "MAP@10".to_string()];
+        let mut best_score = BestScore::new(&names, 1);
+        best_score.update(0, &[0.5, 0.3], Some(&[0.4, 0.3]));
+        best_score.update(1, &[0.6, 0.2], Some(&[0.5, 0.2]));
+        best_score.update(2, &[0.7, 0.4], Some(&[0.3, 0.35]));
+
+        assert_eq!(best_score.best_iter(), Some(2));
+        assert_eq!(
+            best_score.to_string(),
+            "\nBest score at #iter 2:
+NDCG@10 on training data: 0.7000
+NDCG@10 on validating data: 0.3000
+MAP@10 on training data: 0.4000
+MAP@10 on validating data: 0.3500
+"
+        );
+    }
+}
@@ -1,25 +1,85 @@
-use format::svmlight::SvmLightFile;
-use util::{Id, Result, Value};
+use format::compression;
+use format::csv::CsvFormat;
+use format::feature_names::FeatureNames;
+use format::svmlight::{Record, SvmLightFile};
+use genbin;
+use std::path::Path;
+use util::{Error, Id, ParseResult, Value};
 use std;
 use std::cmp::Ordering::*;
+use std::collections::HashMap;
 use train::Evaluate;
 use metric::*;
 
 /// An instance of a label, a qid, and a group of feature values.
+///
+/// Only the given values are stored, as (feature id, value) pairs in
+/// ascending order of the ids, so the memory scales with the given
+/// values rather than the max feature id. The features not given, or
+/// given as NaN, are missing.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Instance {
     qid: Id,
     label: Value, // or label
-    values: Vec<Value>, // index from 0
+    values: Vec<(Id, Value)>,
+    // The max feature id given, including the zero values.
+    max_feature_id: Id,
+    // The comment following '#' in the input line, which usually
+    // carries the document id.
+    info: Option<String>,
 }
 
 impl Instance {
-    /// Creates a new instance.
+    /// Creates a new instance from dense values. The value of feature
+    /// id `i` is `values[i - 1]`. NaN values are missing.
     pub fn new(label: Value, qid: Id, values: Vec<Value>) -> Instance {
+        let max_feature_id = values.len();
+        let values = values
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, value)| !value.is_nan())
+            .map(|(index, value)| (index + 1, value))
+            .collect();
         Instance {
             label: label,
             qid: qid,
             values: values,
+            max_feature_id: max_feature_id,
+            info: None,
+        }
+    }
+
+    /// Creates a new instance from (feature id, value) pairs. Absent
+    /// features and NaN values are missing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rforests::train::dataset::Instance;
+    ///
+    /// let values = vec![(30, 2.0), (4, 0.5), (9, 0.0), (12, std::f64::NAN)];
+    /// let instance = Instance::sparse(1.0, 7, values);
+    /// assert_eq!(instance.get(4), Some(0.5));
+    /// assert_eq!(instance.get(9), Some(0.0));
+    /// assert_eq!(instance.get(12), None);
+    /// assert_eq!(instance.get(5), None);
+    /// assert_eq!(instance.max_feature_id(), 30);
+    /// assert_eq!(instance.value_iter().count(), 3);
+    /// ```
+    pub fn sparse(
+        label: Value,
+        qid: Id,
+        mut values: Vec<(Id, Value)>,
+    ) -> Instance {
+        values.sort_by_key(|&(id, _)| id);
+        let max_feature_id = values.last().map_or(0, |&(id, _)| id);
+        values.retain(|&(_, value)| !value.is_nan());
+        Instance {
+            label: label,
+            qid: qid,
+            values: values,
+            max_feature_id: max_feature_id,
+            info: None,
         }
     }
 
@@ -33,23 +93,96 @@ impl Instance {
         self.label
     }
 
-    /// Returns the value of the given feature id.
+    /// Returns the value of the given feature id, or None if it is
+    /// missing.
+    pub fn get(&self, id: Id) -> Option<Value> {
+        match self.values.binary_search_by_key(&id, |&(id, _)| id) {
+            Ok(index) => Some(self.values[index].1),
+            Err(_) => None,
+        }
+    }
+
+    /// Returns the value of the given feature id. Missing values are
+    /// taken as 0.0, for the models that can not handle them.
     pub fn value(&self, id: Id) -> Value {
-        self.values.get(id - 1).map_or(0.0, |v| *v)
+        self.get(id).unwrap_or(0.0)
     }
 
     // See https://github.com/rust-lang/rust/issues/38615 for the
     // reason that 'a is required.
-    /// Returns an iterator over the (feature id, value) pairs.
+    /// Returns an iterator over the (feature id, value) pairs of the
+    /// values that are not missing.
     pub fn value_iter<'a>(&'a self) -> impl Iterator<Item = (Id, Value)> + 'a {
-        self.values.iter().enumerate().map(|(index, &value)| {
-            (index + 1, value)
-        })
+        self.values.iter().cloned()
     }
 
     /// Returns the max feature id.
     pub fn max_feature_id(&self) -> Id {
-        self.values.len() as Id
+        self.max_feature_id
+    }
+
+    /// Sets the comment of the instance, the text following '#' in
+    /// the input line.
+    pub fn with_info(mut self, info: Option<String>) -> Instance {
+        self.info = info;
+        self
+    }
+
+    /// Returns the comment of the instance, if any.
+    pub fn info(&self) -> Option<&str> {
+        self.info.as_ref().map(|info| info.as_str())
+    }
+
+    /// Returns the document id in the comment of the instance. It is
+    /// the value of a "docid" field, as in "# docid = GX000-00-0000000
+    /// inc = 1", or else the first word of the comment, as in "# 1A".
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rforests::train::dataset::Instance;
+    ///
+    /// let instance = Instance::new(1.0, 1, vec![1.0]);
+    /// assert_eq!(instance.docid(), None);
+    ///
+    /// let instance = instance.with_info(Some("1A".to_string()));
+    /// assert_eq!(instance.docid(), Some("1A"));
+    ///
+    /// let info = "docid = GX000-00-0000000 inc = 1".to_string();
+    /// let instance = instance.with_info(Some(info));
+    /// assert_eq!(instance.docid(), Some("GX000-00-0000000"));
+    /// ```
+    pub fn docid(&self) -> Option<&str> {
+        let info = match self.info {
+            Some(ref info) => info,
+            None => return None,
+        };
+        let words: Vec<&str> = info.split_whitespace().collect();
+        let is_separator = |c| c == '=' || c == ':';
+        for (pos, &word) in words.iter().enumerate() {
+            if !word.starts_with("docid") {
+                continue;
+            }
+            // The separator is either attached to docid, or the next
+            // word.
+            let (rest, next) = if word == "docid" {
+                match words.get(pos + 1) {
+                    Some(&next) => (next, pos + 2),
+                    None => continue,
+                }
+            } else {
+                (&word["docid".len()..], pos + 1)
+            };
+            if !rest.starts_with(is_separator) {
+                continue;
+            }
+            let value = rest.trim_start_matches(is_separator);
+            if !value.is_empty() {
+                return Some(value);
+            }
+            return words.get(next).cloned();
+        }
+        words.first().cloned()
     }
 }
 
@@ -63,21 +196,71 @@ impl std::fmt::Display for Instance {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         let mut values = self.values
             .iter()
-            .enumerate()
-            .map(|(index, value)| format!("{}:{}", index + 1, value))
+            .map(|&(id, value)| format!("{}:{}", id, value))
             .collect::<Vec<_>>();
 
         let mut v = vec![self.label.to_string(), format!("qid:{}", self.qid)];
         v.append(&mut values);
+        if let Some(ref info) = self.info {
+            v.push(format!("# {}", info));
+        }
         write!(f, "{}", v.join(" "))
     }
 }
 
-impl std::ops::Deref for Instance {
-    type Target = Vec<Value>;
+/// Interns the qids of a data file, which can be any strings, into
+/// ids from 1 in the order of their first appearance.
+///
+/// # Examples
+///
+/// ```
+/// use rforests::train::dataset::QidMap;
+///
+/// let mut qids = QidMap::new();
+/// assert_eq!(qids.intern("q7"), 1);
+/// assert_eq!(qids.intern("3"), 2);
+/// assert_eq!(qids.intern("q7"), 1);
+/// assert_eq!(qids.name(2), Some("3"));
+/// assert_eq!(qids.name(3), None);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct QidMap {
+    ids: HashMap<String, Id>,
+    names: Vec<String>,
+}
+
+impl QidMap {
+    pub fn new() -> QidMap {
+        QidMap::default()
+    }
+
+    /// Returns the id of the qid, which is assigned if it is new.
+    pub fn intern(&mut self, name: &str) -> Id {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+
+        self.names.push(name.to_string());
+        let id = self.names.len();
+        self.ids.insert(name.to_string(), id);
+        id
+    }
+
+    /// Returns the qid of an id.
+    pub fn name(&self, id: Id) -> Option<&str> {
+        if id == 0 {
+            return None;
+        }
+        self.names.get(id - 1).map(|name| name.as_str())
+    }
+
+    /// Returns the number of qids.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
 
-    fn deref(&self) -> &Vec<Value> {
-        &self.values
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
     }
 }
 
@@ -114,10 +297,17 @@ pub struct DataSet {
     instances: Vec<Instance>,
     // Group by queries. (Start index, Query Length).
     queries: Vec<(usize, usize)>,
+    feature_names: FeatureNames,
+    // Qids of the data file, if interned.
+    qids: QidMap,
+    // Positions of the instances in the data file, if regrouped.
+    positions: Option<Vec<usize>>,
 }
 
 impl std::iter::FromIterator<(Value, Id, Vec<Value>)> for DataSet {
-    /// Load data from an Iterator.
+    /// Load data from an Iterator. The instances of a query need not
+    /// be consecutive, and are regrouped in the order of the first
+    /// appearance of the queries.
     ///
     /// # Examples
     ///
@@ -138,25 +328,103 @@ impl std::iter::FromIterator<(Value, Id, Vec<Value>)> for DataSet {
     /// assert_eq!(dataset[0].value(1), 5.0);
     /// assert_eq!(dataset[1].qid(), 2);
     /// assert_eq!(dataset[2].qid(), 3);
+    ///
+    /// let data = vec![
+    ///     (3.0, 1, vec![5.0]),
+    ///     (2.0, 2, vec![7.0]),
+    ///     (1.0, 1, vec![3.0]),
+    /// ];
+    ///
+    /// let dataset: DataSet = data.into_iter().collect();
+    /// assert_eq!(dataset.query_iter().count(), 2);
+    /// assert_eq!(dataset[1].value(1), 3.0);
     /// ```
     fn from_iter<T>(iter: T) -> DataSet
     where
         T: IntoIterator<Item = (Value, Id, Vec<Value>)>,
     {
-        let mut instances = Vec::new();
+        let instances = iter.into_iter().map(Instance::from).collect();
+        // Regrouping never fails.
+        DataSet::from_instances(
+            instances,
+            &[],
+            QidMap::new(),
+            FeatureNames::new(),
+            true,
+        ).unwrap()
+    }
+}
+
+impl DataSet {
+    /// Creates a data set from instances. `line_numbers` holds the
+    /// line numbers of the instances in the data file, if known, and
+    /// `qids` holds the qids of the instances if they are interned.
+    ///
+    /// If the instances of a query are not consecutive, they are
+    /// regrouped in the order of the first appearance of the queries
+    /// when `regroup` is true, or else an error is returned. A file
+    /// not sorted by qid would otherwise make several queries out of
+    /// one.
+    pub fn from_instances(
+        instances: Vec<Instance>,
+        line_numbers: &[usize],
+        qids: QidMap,
+        feature_names: FeatureNames,
+        regroup: bool,
+    ) -> ParseResult<DataSet> {
+        // Ranks of the queries in the order of their first appearance.
+        let mut ranks: HashMap<Id, usize> = HashMap::new();
+        let mut consecutive = true;
+        let mut prev_qid = None;
+        for (index, instance) in instances.iter().enumerate() {
+            let qid = instance.qid();
+            if prev_qid == Some(qid) {
+                continue;
+            }
+            prev_qid = Some(qid);
+
+            if !ranks.contains_key(&qid) {
+                let rank = ranks.len();
+                ranks.insert(qid, rank);
+            } else if regroup {
+                consecutive = false;
+            } else {
+                let name = qids.name(qid)
+                    .map_or_else(|| qid.to_string(), |name| name.to_string());
+                let error = Error::parse(
+                    &name,
+                    "Instances of the query are not consecutive, \
+                     consider regrouping the queries",
+                );
+                return Err(match line_numbers.get(index) {
+                    Some(&line_number) => error.at_line(line_number),
+                    None => error,
+                });
+            }
+        }
+
+        let (instances, positions) = if consecutive {
+            (instances, None)
+        } else {
+            // The sort is stable, so the order within a query is kept.
+            let mut indexed: Vec<(usize, Instance)> =
+                instances.into_iter().enumerate().collect();
+            indexed.sort_by_key(|&(_, ref instance)| ranks[&instance.qid()]);
+            let (positions, instances) = indexed.into_iter().unzip();
+            (instances, Some(positions))
+        };
+
         let mut nfeatures = 0;
         let mut queries = Vec::new();
         let mut query_start = 0;
         let mut query_len = 0;
-        for (label, qid, values) in iter {
-            let instance = Instance::from((label, qid, values));
+        for (index, instance) in instances.iter().enumerate() {
             nfeatures =
                 usize::max(nfeatures, instance.max_feature_id() as usize);
-            instances.push(instance);
 
-            if qid != instances[query_start].qid() {
+            if instance.qid() != instances[query_start].qid() {
                 queries.push((query_start, query_len));
-                query_start = instances.len() - 1;
+                query_start = index;
                 query_len = 1;
             } else {
                 query_len += 1;
@@ -164,23 +432,28 @@ impl std::iter::FromIterator<(Value, Id, Vec<Value>)> for DataSet {
         }
         queries.push((query_start, query_len));
 
-        DataSet {
+        Ok(DataSet {
             instances: instances,
             nfeatures: nfeatures,
             queries: queries,
-        }
+            feature_names: feature_names,
+            qids: qids,
+            positions: positions,
+        })
     }
-}
 
-impl DataSet {
-    /// Load data set from a reader.
+    /// Load data set from a reader. Feature headers, "@Feature
+    /// id:<id> name:<name>", are collected into the feature names.
+    /// The qids are interned, see `QidMap`, and the instances of a
+    /// query should be consecutive.
     ///
     /// # Examples
     ///
     /// ```
     /// use rforests::train::dataset::DataSet;
     ///
-    /// let s = "3.0 qid:1 1:5.0
+    /// let s = "# @Feature id:1 name:bm25
+    /// 3.0 qid:1 1:5.0
     /// 2.0 qid:2 1:7.0
     /// 3.0 qid:3 1:3.0";
     ///
@@ -191,44 +464,146 @@ impl DataSet {
     /// assert_eq!(dataset[0].value(1), 5.0);
     /// assert_eq!(dataset[1].qid(), 2);
     /// assert_eq!(dataset[2].qid(), 3);
+    /// assert_eq!(dataset.feature_names().get(1), Some("bm25"));
+    /// ```
+    pub fn load<R>(reader: R) -> ParseResult<DataSet>
+    where
+        R: ::std::io::Read,
+    {
+        DataSet::load_with(reader, false)
+    }
+
+    /// Like `load`, but the instances of a query are regrouped if
+    /// they are not consecutive and `regroup` is true.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rforests::train::dataset::DataSet;
+    /// use rforests::util::Error;
+    ///
+    /// let s = "3.0 qid:q7 1:5.0
+    /// 2.0 qid:q2 1:7.0
+    /// 1.0 qid:q7 1:3.0";
+    ///
+    /// match DataSet::load_with(s.as_bytes(), false) {
+    ///     Err(Error::Parse { line, token, .. }) => {
+    ///         assert_eq!(line, Some(3));
+    ///         assert_eq!(token, Some("q7".to_string()));
+    ///     }
+    ///     _ => panic!("Expected a parse error"),
+    /// }
+    ///
+    /// let dataset = DataSet::load_with(s.as_bytes(), true).unwrap();
+    /// let queries: Vec<_> = dataset
+    ///     .query_iter()
+    ///     .map(|(qid, query)| (dataset.qid_name(qid), query))
+    ///     .collect();
+    /// assert_eq!(
+    ///     queries,
+    ///     vec![("q7".to_string(), vec![0, 1]), ("q2".to_string(), vec![2])]
+    /// );
     /// ```
-    pub fn load<R>(reader: R) -> Result<DataSet>
+    pub fn load_with<R>(reader: R, regroup: bool) -> ParseResult<DataSet>
     where
         R: ::std::io::Read,
     {
         let mut instances = Vec::new();
-        let mut nfeatures = 0;
-        let mut queries = Vec::new();
-        let mut query_start = 0;
-        let mut query_len = 0;
+        let mut line_numbers = Vec::new();
+        let mut qids = QidMap::new();
+        let mut feature_names = FeatureNames::new();
         debug!("Loading data...");
-        for instance_result in SvmLightFile::instances(reader) {
-            let instance = instance_result?;
-            nfeatures =
-                usize::max(nfeatures, instance.max_feature_id() as usize);
-            let qid = instance.qid();
-            instances.push(instance);
-
-            if qid != instances[query_start].qid() {
-                queries.push((query_start, query_len));
-                query_start = instances.len() - 1;
-                query_len = 1;
-            } else {
-                query_len += 1;
+        for record in SvmLightFile::par_records(reader) {
+            let (line_number, record) = record?;
+            match record {
+                Record::Instance {
+                    label,
+                    qid,
+                    values,
+                    info,
+                } => {
+                    let qid = qids.intern(&qid);
+                    let instance =
+                        Instance::sparse(label, qid, values).with_info(info);
+                    instances.push(instance);
+                    line_numbers.push(line_number);
+                }
+                Record::Feature(id, name) => feature_names.insert(id, name),
             }
         }
-        queries.push((query_start, query_len));
+
+        let dataset = DataSet::from_instances(
+            instances,
+            &line_numbers,
+            qids,
+            feature_names,
+            regroup,
+        )?;
         debug!(
             "Loaded {} instances, {} features.",
-            instances.len(),
-            nfeatures
+            dataset.len(),
+            dataset.nfeatures()
         );
+        Ok(dataset)
+    }
 
-        Ok(DataSet {
-            instances: instances,
-            nfeatures: nfeatures,
-            queries: queries,
-        })
+    /// Load data set from a file. Files with the "bin" extension are
+    /// read as binary files generated by `rforests genbin`, others as
+    /// SVMLight files. Files compressed by gzip or zstd are
+    /// decompressed, and the extension before ".gz" or ".zst" tells
+    /// the format. Errors tell the file name.
+    pub fn load_file(path: &str) -> ParseResult<DataSet> {
+        DataSet::load_file_with(path, &CsvFormat::default(), false)
+    }
+
+    /// Like `load_file`, but files with the "csv" or "tsv" extension
+    /// are read as CSV files of the columns in `csv_format`, separated
+    /// by ',' or '\t' respectively. The instances of a query are
+    /// regrouped if they are not consecutive and `regroup` is true.
+    pub fn load_file_with(
+        path: &str,
+        csv_format: &CsvFormat,
+        regroup: bool,
+    ) -> ParseResult<DataSet> {
+        let extension = Path::new(compression::strip_extension(path))
+            .extension();
+        let result = compression::open(path).and_then(
+            |file| match extension {
+                Some(ext) if ext == "bin" => genbin::load(file),
+                Some(ext) if ext == "csv" => csv_format.load(file, regroup),
+                Some(ext) if ext == "tsv" => {
+                    csv_format.clone().with_delimiter('\t').load(file, regroup)
+                }
+                _ => DataSet::load_with(file, regroup),
+            },
+        );
+        result.map_err(|e| e.in_file(path))
+    }
+
+    /// Returns the qid in the data file of an interned qid, or the qid
+    /// itself if the qids are not interned.
+    pub fn qid_name(&self, qid: Id) -> String {
+        match self.qids.name(qid) {
+            Some(name) => name.to_string(),
+            None => qid.to_string(),
+        }
+    }
+
+    /// Returns the names of the features.
+    pub fn feature_names(&self) -> &FeatureNames {
+        &self.feature_names
+    }
+
+    /// Adds feature names, for example loaded from a feature name
+    /// file. They replace the existing names of the same features.
+    pub fn add_feature_names(&mut self, feature_names: FeatureNames) {
+        self.feature_names.extend(feature_names);
+    }
+
+    /// Returns the number of features, which is also the max feature
+    /// id.
+    pub fn nfeatures(&self) -> usize {
+        self.nfeatures
     }
 
     /// Returns an iterator over the feature ids in the data set.
@@ -278,6 +653,94 @@ impl DataSet {
         self.instances.iter().map(|instance| instance.label)
     }
 
+    /// Returns an iterator over the document ids of the instances,
+    /// see `Instance::docid`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rforests::train::dataset::DataSet;
+    ///
+    /// let s = "3.0 qid:1 1:5.0 # 1A
+    /// 2.0 qid:1 1:7.0";
+    ///
+    /// let dataset = DataSet::load(::std::io::Cursor::new(s)).unwrap();
+    ///
+    /// let mut iter = dataset.docid_iter();
+    /// assert_eq!(iter.next(), Some(Some("1A")));
+    /// assert_eq!(iter.next(), Some(None));
+    /// assert_eq!(iter.next(), None);
+    /// ```
+    pub fn docid_iter<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = Option<&'a str>> + 'a {
+        self.instances.iter().map(|instance| instance.docid())
+    }
+
+    /// Returns an iterator over the ids of the instances, each the qid
+    /// in the data file and the document id. The document id is taken
+    /// from the comment of the instance (see `Instance::docid`), or is
+    /// the index of the instance in its query if there is no comment.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rforests::train::dataset::DataSet;
+    ///
+    /// let s = "3.0 qid:1 1:5.0 # 1A
+    /// 2.0 qid:1 1:7.0";
+    ///
+    /// let dataset = DataSet::load(::std::io::Cursor::new(s)).unwrap();
+    ///
+    /// let mut iter = dataset.id_iter();
+    /// assert_eq!(iter.next(), Some(("1".to_string(), "1A".to_string())));
+    /// assert_eq!(iter.next(), Some(("1".to_string(), "1".to_string())));
+    /// assert_eq!(iter.next(), None);
+    /// ```
+    pub fn id_iter<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (String, String)> + 'a {
+        self.query_iter().flat_map(move |(qid, query)| {
+            let qid = self.qid_name(qid);
+            query.into_iter().enumerate().map(move |(doc_index, index)| {
+                let docid = match self[index].docid() {
+                    Some(docid) => docid.to_string(),
+                    None => doc_index.to_string(),
+                };
+                (qid.clone(), docid)
+            })
+        })
+    }
+
+    /// Returns the indices of the instances in the order of the data
+    /// file. It differs from the order of the data set only if the
+    /// queries were regrouped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rforests::train::dataset::DataSet;
+    ///
+    /// let s = "3.0 qid:1 1:5.0
+    /// 2.0 qid:2 1:7.0
+    /// 1.0 qid:1 1:3.0";
+    ///
+    /// let dataset = DataSet::load_with(s.as_bytes(), true).unwrap();
+    /// assert_eq!(dataset.file_order(), vec![0, 2, 1]);
+    /// ```
+    pub fn file_order(&self) -> Vec<usize> {
+        match self.positions {
+            Some(ref positions) => {
+                let mut order = vec![0; positions.len()];
+                for (index, &position) in positions.iter().enumerate() {
+                    order[position] = index;
+                }
+                order
+            }
+            None => (0..self.len()).collect(),
+        }
+    }
+
     pub fn feature_value_iter<'a>(
         &'a self,
         fid: Id,
@@ -320,12 +783,24 @@ impl DataSet {
         })
     }
 
+    /// Measures the ranking of each query by the model with the
+    /// metric, and returns the average over the queries.
     pub fn evaluate<E: Evaluate>(
         &self,
         e: &E,
-        metric: &Box<Measure>,
+        metric: &Box<dyn Measure>,
     ) -> f64 {
-        let mut score = 0.0;
+        self.evaluate_metrics(e, &[metric])[0]
+    }
+
+    /// Like `evaluate`, but measures the ranking with several metrics
+    /// at once. Returns the scores in the order of the metrics.
+    pub fn evaluate_metrics<E: Evaluate>(
+        &self,
+        e: &E,
+        metrics: &[&Box<dyn Measure>],
+    ) -> Vec<f64> {
+        let mut scores = vec![0.0; metrics.len()];
         let mut count: usize = 0;
         for (qid, query) in self.query_iter() {
             let mut model_scores: Vec<(Id, Value)> = query
@@ -340,16 +815,67 @@ impl DataSet {
                 .iter()
                 .map(|&(id, _)| self.instances[id].label())
                 .collect();
-            let query_score = metric.measure(&labels);
-            debug!("Model score for qid {}: {}", qid, score);
+            for (score, metric) in scores.iter_mut().zip(metrics.iter()) {
+                let query_score = metric.measure(&labels);
+                debug!("Model score for qid {}: {}", qid, query_score);
+                *score += query_score;
+            }
 
             count += 1;
-            score += query_score;
         }
 
-        let result = score / count as f64;
-        debug!("Model score for validation data: {}", result);
-        result
+        for score in scores.iter_mut() {
+            *score /= count as f64;
+        }
+        debug!("Model scores for validation data: {:?}", scores);
+        scores
+    }
+
+    /// Measures the ranking of each query by the scores of the
+    /// instances, which are in the order of the instances. Returns the
+    /// score of each query in the order of the queries.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rforests::metric::{Measure, PrecisionScorer};
+    /// use rforests::train::dataset::DataSet;
+    ///
+    /// let data = vec![
+    ///     // label, qid, values
+    ///     (1.0, 1, vec![5.0]),
+    ///     (0.0, 1, vec![7.0]),
+    ///     (0.0, 2, vec![3.0]),
+    ///     (2.0, 2, vec![2.0]),
+    /// ];
+    /// let dataset: DataSet = data.into_iter().collect();
+    ///
+    /// let metric: Box<dyn Measure> = Box::new(PrecisionScorer::new(1));
+    /// let scores = [0.9, 0.1, 0.2, 0.8];
+    /// assert_eq!(dataset.measure_queries(&scores, &metric), vec![1.0, 1.0]);
+    /// let scores = [0.1, 0.9, 0.2, 0.8];
+    /// assert_eq!(dataset.measure_queries(&scores, &metric), vec![0.0, 1.0]);
+    /// ```
+    pub fn measure_queries(
+        &self,
+        scores: &[Value],
+        metric: &Box<dyn Measure>,
+    ) -> Vec<f64> {
+        assert_eq!(scores.len(), self.len());
+        self.query_iter()
+            .map(|(_qid, mut query)| {
+                query.sort_by(|&index1, &index2| {
+                    scores[index2]
+                        .partial_cmp(&scores[index1])
+                        .unwrap_or(Equal)
+                });
+                let labels: Vec<f64> = query
+                    .iter()
+                    .map(|&index| self.instances[index].label())
+                    .collect();
+                metric.measure(&labels)
+            })
+            .collect()
     }
 }
 
@@ -365,6 +891,22 @@ impl std::ops::Deref for DataSet {
 mod test {
     use super::*;
 
+    #[test]
+    fn test_docid() {
+        let docid = |info: &str| {
+            let instance = Instance::new(1.0, 1, vec![1.0]);
+            let instance = instance.with_info(Some(info.to_string()));
+            instance.docid().map(|docid| docid.to_string())
+        };
+        assert_eq!(docid("docid=7"), Some("7".to_string()));
+        assert_eq!(docid("docid: 7"), Some("7".to_string()));
+        assert_eq!(docid("inc = 1 docid =7"), Some("7".to_string()));
+        assert_eq!(docid("docids=3 docid = 7"), Some("7".to_string()));
+        // Without a docid, the first word.
+        assert_eq!(docid("docids=3"), Some("docids=3".to_string()));
+        assert_eq!(docid("1A docid"), Some("1A".to_string()));
+    }
+
     #[test]
     fn test_generate_queries() {
         let s = "0 qid:3864 1:1.0 2:0.0 3:0.0 4:0.0 5:0.0
@@ -375,17 +917,44 @@ mod test {
         assert_eq!(dataset.nfeatures, 5);
         assert_eq!(
             dataset.instances[0],
-            Instance::new(0.0, 3864, vec![1.0, 0.0, 0.0, 0.0, 0.0])
+            Instance::new(0.0, 1, vec![1.0, 0.0, 0.0, 0.0, 0.0])
         );
         assert_eq!(
             dataset.instances[1],
-            Instance::new(2.0, 3864, vec![1.0, 0.007042, 0.0, 0.0, 0.221591])
+            Instance::new(2.0, 1, vec![1.0, 0.007042, 0.0, 0.0, 0.221591])
         );
         assert_eq!(
             dataset.instances[2],
-            Instance::new(0.0, 3865, vec![0.289474, 0.014085, 0.4, 0.0, 0.085227])
+            Instance::new(0.0, 2, vec![0.289474, 0.014085, 0.4, 0.0, 0.085227])
         );
         assert_eq!(dataset.queries[0], (0, 2));
         assert_eq!(dataset.queries[1], (2, 1));
+        assert_eq!(dataset.qid_name(1), "3864");
+        assert_eq!(dataset.qid_name(2), "3865");
+    }
+
+    #[test]
+    fn test_regroup_queries() {
+        let s = "2 qid:b 1:1.0
+1 qid:a 1:2.0
+0 qid:b 1:3.0
+1 qid:c 1:4.0
+0 qid:a 1:5.0";
+        match DataSet::load_with(s.as_bytes(), false) {
+            Err(Error::Parse { token, .. }) => {
+                assert_eq!(token, Some("b".to_string()))
+            }
+            _ => panic!("Expected a parse error"),
+        }
+
+        let dataset = DataSet::load_with(s.as_bytes(), true).unwrap();
+        let values: Vec<Value> = dataset.iter().map(|i| i.value(1)).collect();
+        assert_eq!(values, vec![1.0, 3.0, 2.0, 5.0, 4.0]);
+        assert_eq!(dataset.queries, vec![(0, 2), (2, 2), (4, 1)]);
+        let qids: Vec<String> = dataset
+            .query_iter()
+            .map(|(qid, _)| dataset.qid_name(qid))
+            .collect();
+        assert_eq!(qids, vec!["b", "a", "c"]);
     }
 }
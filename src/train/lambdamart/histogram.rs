@@ -1,24 +1,24 @@
 use std;
 use util::*;
 
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq)]
 struct HistogramBin {
-    // Max value of this bin
-    threshold: f64,
+    // Count of the values in this bin.
+    count: usize,
 
-    // Accumulated count of all the values of this and preceding bins.
-    acc_count: usize,
+    // Sum of the labels in this bin.
+    sum: f64,
 
-    // Accumulated sum of all the labels of this and preceding bins.
-    acc_sum: f64,
+    // Sum of the weights in this bin.
+    weight: f64,
 }
 
 impl HistogramBin {
-    pub fn new(threshold: f64, acc_count: usize, acc_sum: f64) -> HistogramBin {
+    pub fn new(count: usize, sum: f64, weight: f64) -> HistogramBin {
         HistogramBin {
-            threshold: threshold,
-            acc_count: acc_count,
-            acc_sum: acc_sum,
+            count: count,
+            sum: sum,
+            weight: weight,
         }
     }
 }
@@ -27,31 +27,79 @@ impl std::fmt::Debug for HistogramBin {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
             f,
-            "HistogramBin {{ threshold: {}, acc_count: {}, acc_sum: {} }}",
-            if self.threshold == std::f64::MAX {
-                "f64::MAX".to_string()
-            } else {
-                self.threshold.to_string()
-            },
-            self.acc_count,
-            self.acc_sum.to_string()
+            "HistogramBin {{ count: {}, sum: {}, weight: {} }}",
+            self.count,
+            self.sum,
+            self.weight
         )
     }
 }
 
-#[derive(Debug)]
+/// The statistics of the labels (lambdas) and weights of the
+/// instances in each threshold interval of a feature. The i-th bin
+/// holds the values in (thresholds[i - 1], thresholds[i]]. One more
+/// bin at the end holds the missing values.
+#[derive(Debug, Clone)]
 pub struct Histogram {
-    // [from, to]
     bins: Vec<HistogramBin>,
 }
 
 impl Histogram {
-    fn new(bins: Vec<HistogramBin>) -> Histogram {
+    /// Creates an empty histogram with the given count of bins, plus
+    /// the bin of the missing values, whose index is `nbins`.
+    pub fn new(nbins: usize) -> Histogram {
+        Histogram { bins: vec![HistogramBin::new(0, 0.0, 0.0); nbins + 1] }
+    }
+
+    /// Adds a value to the bin.
+    #[inline]
+    pub fn add(&mut self, bin: usize, label: Value, weight: Value) {
+        let bin = &mut self.bins[bin];
+        bin.count += 1;
+        bin.sum += label;
+        bin.weight += weight;
+    }
+
+    /// Adds `count` values to the bin, whose labels and weights sum to
+    /// `sum` and `weight`.
+    #[inline]
+    pub fn add_values(
+        &mut self,
+        bin: usize,
+        count: usize,
+        sum: Value,
+        weight: Value,
+    ) {
+        let bin = &mut self.bins[bin];
+        bin.count += count;
+        bin.sum += sum;
+        bin.weight += weight;
+    }
+
+    /// Returns the histogram of the values in self but not in
+    /// `other`, which should be a histogram of a part of the values
+    /// in self. Building the histogram of one child of a split node
+    /// and subtracting it from the node's histogram is much cheaper
+    /// than building the histograms of both children.
+    pub fn subtract(&self, other: &Histogram) -> Histogram {
+        assert_eq!(self.bins.len(), other.bins.len());
+        let bins = self.bins
+            .iter()
+            .zip(other.bins.iter())
+            .map(|(a, b)| {
+                HistogramBin::new(
+                    a.count - b.count,
+                    a.sum - b.sum,
+                    a.weight - b.weight,
+                )
+            })
+            .collect();
         Histogram { bins: bins }
     }
 
     /// Return the best splitting point. The returned value is of the
-    /// form (threshold, s value).
+    /// form (threshold, missing values go left, s value).
+    /// `thresholds` are the upper bounds of the bins.
     ///
     /// The best split minimizes the sum of the variance of the left
     /// part and right part.
@@ -73,57 +121,136 @@ impl Histogram {
     ///
     /// To minimize the result, we just need to find a point that
     /// maximizes sum(left_label) ^ 2 + sum(right_labels) ^ 2
-    pub fn best_split(&self, min_leaf: usize) -> Option<(Value, f64)> {
-        let sum = self.bins.last().unwrap().acc_sum;
-        let count = self.bins.last().unwrap().acc_count;
-        let mut split: Option<(f64, f64)> = None;
-        for bin in self.bins.iter() {
-            let count_left = bin.acc_count;
-            let count_right = count - count_left;
-            if count_left < min_leaf || count_right < min_leaf {
-                continue;
-            }
-
-            let sum_left = bin.acc_sum;
-            let sum_right = sum - sum_left;
+    ///
+    /// Each part should have at least `min_leaf` values, and the
+    /// weights of the values in each part should sum to at least
+    /// `min_weight`.
+    ///
+    /// The missing values are tried on both sides, and go to the one
+    /// with the better s value. If there are no missing values, they
+    /// go to the side with more values.
+    pub fn best_split(
+        &self,
+        thresholds: &[Value],
+        min_leaf: usize,
+        min_weight: f64,
+    ) -> Option<(Value, bool, f64)> {
+        assert_eq!(self.bins.len(), thresholds.len() + 1);
+        let (missing, bins) = self.bins.split_last().unwrap();
+        let (count, sum, weight) =
+            self.bins.iter().fold((0, 0.0, 0.0), |(c, s, w), bin| {
+                (c + bin.count, s + bin.sum, w + bin.weight)
+            });
 
-            let s_value = sum_left * sum_left / count_left as f64 +
-                sum_right * sum_right / count_right as f64;
+        let mut split: Option<(f64, bool, f64)> = None;
+        let (mut count_left, mut sum_left, mut weight_left) = (0, 0.0, 0.0);
+        for (bin, &threshold) in bins.iter().zip(thresholds.iter()) {
+            count_left += bin.count;
+            sum_left += bin.sum;
+            weight_left += bin.weight;
 
-            split = split.map_or(
-                Some((bin.threshold, s_value)),
-                |(old_threshold, old_s)| if s_value > old_s {
-                    Some((bin.threshold, s_value))
+            let sides: &[bool] = if missing.count > 0 {
+                &[true, false]
+            } else if count_left >= count - count_left {
+                &[true]
+            } else {
+                &[false]
+            };
+            for &missing_left in sides {
+                let (count_left, sum_left, weight_left) = if missing_left {
+                    (
+                        count_left + missing.count,
+                        sum_left + missing.sum,
+                        weight_left + missing.weight,
+                    )
                 } else {
-                    Some((old_threshold, old_s))
-                },
-            );
-        }
+                    (count_left, sum_left, weight_left)
+                };
 
-        split
-    }
-}
+                let count_right = count - count_left;
+                if count_left < min_leaf || count_right < min_leaf {
+                    continue;
+                }
 
-use std::iter::FromIterator;
-impl FromIterator<(Value, usize, Value)> for Histogram {
-    fn from_iter<T>(iter: T) -> Histogram
-    where
-        T: IntoIterator<Item = (Value, usize, Value)>,
-    {
-        let bins: Vec<HistogramBin> = iter.into_iter()
-            .map(|(threshold, acc_count, acc_sum)| {
-                HistogramBin::new(threshold, acc_count, acc_sum)
-            })
-            .collect();
+                let weight_right = weight - weight_left;
+                if weight_left < min_weight || weight_right < min_weight {
+                    continue;
+                }
 
-        Histogram::new(bins)
+                let sum_right = sum - sum_left;
+
+                let s_value = sum_left * sum_left / count_left as f64 +
+                    sum_right * sum_right / count_right as f64;
+
+                split = split.map_or(
+                    Some((threshold, missing_left, s_value)),
+                    |old| if s_value > old.2 {
+                        Some((threshold, missing_left, s_value))
+                    } else {
+                        Some(old)
+                    },
+                );
+            }
+        }
+
+        split
     }
 }
 
 #[cfg(test)]
 mod test {
     // use train::dataset::*;
-    // use super::*;
+    use super::*;
+
+    #[test]
+    fn test_histogram_subtract() {
+        let thresholds = vec![1.0, 2.0, std::f64::MAX];
+
+        // (bin, label)
+        let left = vec![(0, 3.0), (2, 1.0)];
+        let right = vec![(0, 1.0), (1, 2.0), (1, 4.0)];
+
+        let mut parent = Histogram::new(3);
+        let mut left_histogram = Histogram::new(3);
+        let mut right_histogram = Histogram::new(3);
+        for &(bin, label) in left.iter() {
+            parent.add(bin, label, 1.0);
+            left_histogram.add(bin, label, 1.0);
+        }
+        for &(bin, label) in right.iter() {
+            parent.add(bin, label, 1.0);
+            right_histogram.add(bin, label, 1.0);
+        }
+
+        let subtracted = parent.subtract(&left_histogram);
+        assert_eq!(subtracted.bins, right_histogram.bins);
+
+        // [1] | [2, 4]: 1 ^ 2 / 1 + 6 ^ 2 / 2 = 19
+        assert_eq!(
+            subtracted.best_split(&thresholds, 1, 0.0),
+            Some((1.0, false, 19.0))
+        );
+        assert_eq!(subtracted.best_split(&thresholds, 2, 0.0), None);
+        assert_eq!(subtracted.best_split(&thresholds, 1, 2.0), None);
+    }
+
+    #[test]
+    fn test_histogram_missing() {
+        let thresholds = vec![1.0, 2.0, std::f64::MAX];
+
+        // (bin, label), bin 3 is of the missing values.
+        let values = vec![(0, 1.0), (1, 2.0), (1, 4.0), (3, 1.0)];
+        let mut histogram = Histogram::new(3);
+        for &(bin, label) in values.iter() {
+            histogram.add(bin, label, 1.0);
+        }
+
+        // [1, missing 1] | [2, 4]: 2 ^ 2 / 2 + 6 ^ 2 / 2 = 20
+        assert_eq!(
+            histogram.best_split(&thresholds, 1, 0.0),
+            Some((1.0, true, 20.0))
+        );
+    }
 
     // #[test]
     // fn test_feature_histogram() {
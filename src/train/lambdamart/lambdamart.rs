@@ -1,14 +1,19 @@
+use rand::{self, SeedableRng, StdRng};
 use super::regression_tree::*;
 use train::dataset::*;
 use util::*;
 use metric::*;
 use super::training_set::*;
+use super::objective::Objective;
 use train::validate_set::*;
+use train::best_score::*;
+use train::Evaluate;
 
 /// A instance of LambdaMART algorithm.
 pub struct LambdaMART {
     config: Config,
     ensemble: Ensemble,
+    rng: StdRng,
 }
 
 /// Configurable options for LambdaMART.
@@ -17,75 +22,43 @@ pub struct Config {
     pub validate: Option<DataSet>,
     pub test: Option<DataSet>,
 
-    pub metric: Box<Measure>,
+    pub metric: Box<dyn Measure>,
+    // The metric deciding the best iteration and early stopping, or
+    // `metric` if None.
+    pub stop_metric: Option<Box<dyn Measure>>,
+    // Metrics only reported in each iteration.
+    pub report_metrics: Vec<Box<dyn Measure>>,
+    // The loss that the trees are boosted to minimize.
+    pub objective: Box<dyn Objective>,
     pub trees: usize,
     pub max_leaves: usize,
     pub learning_rate: f64,
     pub thresholds: usize,
+    // How the thresholds are generated from the feature values.
+    pub binning: Binning,
     pub min_leaf_samples: usize,
+    // Max depth of the leaves, or no limit if None.
+    pub max_depth: Option<usize>,
+    // Minimal gain of the s value required to split a node.
+    pub min_split_gain: f64,
+    // Minimal sum of the weights (hessians) per leaf.
+    pub min_leaf_weight: f64,
+    // L1 and L2 regularization of the leaf outputs.
+    pub l1: f64,
+    pub l2: f64,
     pub early_stop: usize,
+    // Fraction of the queries that each tree is fitted to. The queries
+    // are drawn without replacement.
+    pub subsample: f64,
+    // Fraction of the features that each tree may split on.
+    pub feature_fraction_per_tree: f64,
+    // Fraction of the features of the tree considered when splitting
+    // a node.
+    pub feature_fraction_per_node: f64,
+    pub seed: usize,
     pub print_metric: bool,
 }
 
-struct BestScore {
-    name: String,
-    iter: Option<usize>,
-    train: Option<f64>,
-    validate: Option<f64>,
-}
-
-impl BestScore {
-    pub fn new(name: &str) -> BestScore {
-        BestScore {
-            name: name.to_string(),
-            iter: None,
-            train: None,
-            validate: None,
-        }
-    }
-
-    pub fn update(&mut self, iter: usize, train: f64, validate: Option<f64>) {
-        self.iter = self.iter.or(Some(iter));
-        self.train = self.train.or(Some(train));
-        self.validate = self.validate.or(validate);
-
-        if let Some(validate) = validate {
-            if validate > self.validate.unwrap() {
-                self.iter = Some(iter);
-                self.train = Some(train);
-                self.validate = Some(validate);
-            }
-        } else {
-            if train > self.train.unwrap() {
-                self.iter = Some(iter);
-                self.train = Some(train);
-            }
-        }
-    }
-
-    /// Return the best iteration of the validate score.
-    pub fn best_iter(&self) -> Option<usize> {
-        self.validate.and(self.iter)
-    }
-}
-
-impl ::std::fmt::Display for BestScore {
-    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
-        match (self.iter, self.train) {
-            (Some(iter), Some(train)) => {
-                writeln!(f, "\nBest score at #iter {}:", iter)?;
-                writeln!(f, "{} on training data: {:.4}", self.name, train)?;
-            }
-            _ => (),
-        }
-        if let Some(validate) = self.validate {
-            writeln!(f, "{} on validating data: {:.4}", self.name, validate)?;
-        }
-
-        Ok(())
-    }
-}
-
 impl LambdaMART {
     /// Create a new LambdaMART instance.
     ///
@@ -97,7 +70,9 @@ impl LambdaMART {
     ///     use std::fs::File;
     ///     use rforests::train::dataset::*;
     ///     use rforests::train::lambdamart::lambdamart::*;
+    ///     use rforests::train::lambdamart::objective;
     ///     use rforests::metric;
+    ///     use rforests::train::lambdamart::training_set::Binning;
     ///
     ///     let f = File::open(train_path)?;
     ///     let dataset = DataSet::load(f).unwrap();
@@ -111,12 +86,28 @@ impl LambdaMART {
     ///         learning_rate: 0.1,
     ///         max_leaves: 10,
     ///         min_leaf_samples: 1,
+    ///         max_depth: None,
+    ///         min_split_gain: 0.0,
+    ///         min_leaf_weight: 0.0,
+    ///         l1: 0.0,
+    ///         l2: 0.0,
     ///         thresholds: 256,
+    ///         binning: Binning::Uniform,
     ///         print_metric: true,
     ///         metric: metric::new("NDCG", 10).unwrap(),
+    ///         stop_metric: None,
+    ///         report_metrics: vec![metric::new("MAP", 10).unwrap()],
+    ///         objective: objective::new(
+    ///             "lambda",
+    ///             metric::new("NDCG", 10).unwrap(),
+    ///         ).unwrap(),
     ///         validate: Some(validate),
     ///         test: None,
     ///         early_stop: 100,
+    ///         subsample: 1.0,
+    ///         feature_fraction_per_tree: 1.0,
+    ///         feature_fraction_per_node: 1.0,
+    ///         seed: 1,
     ///     };
     ///     let mut lambdamart = LambdaMART::new(config);
     ///     lambdamart.init()?;
@@ -125,9 +116,45 @@ impl LambdaMART {
     /// # }
     /// ```
     pub fn new(config: Config) -> LambdaMART {
+        let mut ensemble = Ensemble::new();
+        ensemble.set_nfeatures(config.train.nfeatures());
+        ensemble.set_feature_names(config.train.feature_names().clone());
+        ensemble.add_param("algorithm", "LambdaMART");
+        ensemble.add_param("metric", config.metric.name());
+        ensemble.add_param("objective", config.objective.name());
+        ensemble.add_param("trees", config.trees);
+        ensemble.add_param("leaves", config.max_leaves);
+        ensemble.add_param("shrinkage", config.learning_rate);
+        ensemble.add_param("thresholds", config.thresholds);
+        ensemble.add_param("binning", config.binning.name());
+        ensemble.add_param("min-leaf-support", config.min_leaf_samples);
+        ensemble.add_param(
+            "max-depth",
+            config
+                .max_depth
+                .map_or("None".to_string(), |depth| depth.to_string()),
+        );
+        ensemble.add_param("min-split-gain", config.min_split_gain);
+        ensemble.add_param("min-leaf-weight", config.min_leaf_weight);
+        ensemble.add_param("l1", config.l1);
+        ensemble.add_param("l2", config.l2);
+        ensemble.add_param("early-stop", config.early_stop);
+        ensemble.add_param("subsample", config.subsample);
+        ensemble.add_param(
+            "feature-fraction-per-tree",
+            config.feature_fraction_per_tree,
+        );
+        ensemble.add_param(
+            "feature-fraction-per-node",
+            config.feature_fraction_per_node,
+        );
+        ensemble.add_param("seed", config.seed);
+
+        let rng: StdRng = SeedableRng::from_seed(&[config.seed][..]);
         LambdaMART {
             config: config,
-            ensemble: Ensemble::new(),
+            ensemble: ensemble,
+            rng: rng,
         }
     }
 
@@ -137,54 +164,115 @@ impl LambdaMART {
     }
 
     /// Learns from the given training data, using the configuration
-    /// specified when creating LambdaMART instance.
+    /// specified when creating LambdaMART instance. If subsample is
+    /// less than 1, each tree is fitted to a random part of the
+    /// queries. Likewise for the features with the feature fractions.
     pub fn learn(&mut self) -> Result<()> {
-        let mut training =
-            TrainSet::new(&self.config.train, self.config.thresholds);
+        let mut training = TrainSet::with_binning(
+            &self.config.train,
+            self.config.thresholds,
+            self.config.binning,
+        );
+        let queries: Vec<Vec<Id>> = self.config
+            .train
+            .query_iter()
+            .map(|(_qid, query)| query)
+            .collect();
+        let nsampled = ((queries.len() as f64 * self.config.subsample)
+            .round() as usize)
+            .max(1);
+        let fids: Vec<Id> = training.fid_iter().collect();
+        let fraction = self.config.feature_fraction_per_tree;
+        let ntree_fids =
+            ((fids.len() as f64 * fraction).round() as usize).max(1);
         let mut validate =
             self.config.validate.as_ref().map(|v| ValidateSet::from(v));
-        let mut best_score = BestScore::new(&self.config.metric.name());
+        let (metrics, stop) = training_metrics(
+            &self.config.metric,
+            self.config.stop_metric.as_ref(),
+            &self.config.report_metrics,
+        );
+        let names: Vec<String> =
+            metrics.iter().map(|metric| metric.name()).collect();
+        let mut best_score = BestScore::new(&names, stop);
+        let table = ScoreTable::new(&names, &["T", "V"]);
 
-        self.print_metric_header();
+        self.print(&table.header());
         for i in 0..self.config.trees {
-            training.update_lambdas_weights(&self.config.metric);
+            self.config.objective.update(&mut training);
 
             let mut tree = RegressionTree::new(
                 self.config.learning_rate,
                 self.config.max_leaves,
                 self.config.min_leaf_samples,
             );
+            tree.set_feature_fraction(self.config.feature_fraction_per_node);
+            tree.set_max_depth(self.config.max_depth);
+            tree.set_min_split_gain(self.config.min_split_gain);
+            tree.set_min_leaf_weight(self.config.min_leaf_weight);
+            tree.set_regularization(self.config.l1, self.config.l2);
+
+            let tree_fids = if ntree_fids < fids.len() {
+                rand::seq::sample_slice(&mut self.rng, &fids, ntree_fids)
+            } else {
+                fids.clone()
+            };
 
             // The scores of the model are updated when the tree node
             // does not split and becomes a leaf.
-            let leaf_output = tree.fit(&training);
+            let leaf_output = if nsampled < queries.len() {
+                // Sample whole queries, so that the lambdas of a query
+                // are fitted together.
+                let sampled = rand::seq::sample_slice_ref(
+                    &mut self.rng,
+                    &queries,
+                    nsampled,
+                );
+                let indices: Vec<Id> = sampled
+                    .into_iter()
+                    .flat_map(|query| query.iter().cloned())
+                    .collect();
+                let sample = TrainSample::new(&training, indices);
+                tree.fit_sample(&training, sample, &tree_fids, &mut self.rng);
+
+                // The instances out of the sample are not on the leaves.
+                self.config
+                    .train
+                    .iter()
+                    .map(|instance| tree.evaluate(instance))
+                    .collect()
+            } else {
+                let sample = TrainSample::from(&training);
+                tree.fit_sample(&training, sample, &tree_fids, &mut self.rng)
+            };
 
             // Update the scores fitted by the regression tree.
             training.update_result(&leaf_output);
 
             // Measure on the training data set.
-            let train_score = training.measure(&self.config.metric);
+            let train_scores: Vec<f64> = metrics
+                .iter()
+                .map(|metric| training.measure(metric))
+                .collect();
 
             // Update scores on validate set.
             validate.as_mut().map(|v| v.update(&tree));
 
             // Measure on validate set.
-            let validate_score =
-                validate.as_ref().map(|v| v.measure(&self.config.metric));
+            let validate_scores: Option<Vec<f64>> = validate.as_ref().map(|v| {
+                metrics.iter().map(|metric| v.measure(metric)).collect()
+            });
+            let validate_scores = validate_scores.as_ref().map(|v| &v[..]);
 
             self.ensemble.push(tree);
 
-            self.print_metric(i, train_score, validate_score);
+            self.print(&table.row(i, &[Some(&train_scores), validate_scores]));
 
             // Check if the best validation score is `early_stop`
             // round earlier.
-            best_score.update(i, train_score, validate_score);
+            best_score.update(i, &train_scores, validate_scores);
 
-            let stop = best_score
-                .best_iter()
-                .map(|iter| iter + self.config.early_stop < i)
-                .unwrap_or(false);
-            if stop {
+            if best_score.should_stop(i, self.config.early_stop) {
                 self.ensemble.truncate(best_score.best_iter().unwrap());
                 break;
             }
@@ -194,6 +282,11 @@ impl LambdaMART {
         Ok(())
     }
 
+    /// Returns the trees learned so far.
+    pub fn ensemble(&self) -> &Ensemble {
+        &self.ensemble
+    }
+
     pub fn evaluate(&self, dataset: &DataSet) -> f64 {
         dataset.evaluate(&self.ensemble, &self.config.metric)
     }
@@ -203,41 +296,13 @@ impl LambdaMART {
             println!("{}", msg);
         }
     }
-
-    /// Print metric header.
-    fn print_metric_header(&self) {
-        self.print(&format!(
-            "{:<7} | {:>9} | {:>9}",
-            "#iter",
-            self.config.metric.name() + "-T",
-            self.config.metric.name() + "-V"
-        ));
-    }
-
-    /// Print metric of each iteration.
-    fn print_metric(
-        &self,
-        iteration: usize,
-        train_score: f64,
-        validate_score: Option<f64>,
-    ) {
-        let s = format!(
-            "{:<7} | {:>9.4} | {}",
-            iteration,
-            train_score,
-            validate_score
-                .map(|score| format!("{:>9.4}", score))
-                .unwrap_or("".to_string())
-        );
-        self.print(&s);
-
-    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use std::fs::File;
+    use train::lambdamart::objective::*;
 
     #[test]
     fn test_lambda_mart() {
@@ -252,12 +317,25 @@ mod test {
             test: None,
             trees: 10,
             early_stop: 100,
+            subsample: 1.0,
+            feature_fraction_per_tree: 1.0,
+            feature_fraction_per_node: 1.0,
+            seed: 1,
             learning_rate: 0.1,
             max_leaves: 10,
             min_leaf_samples: 1,
+            max_depth: None,
+            min_split_gain: 0.0,
+            min_leaf_weight: 0.0,
+            l1: 0.0,
+            l2: 0.0,
             thresholds: 256,
+            binning: Binning::Uniform,
             print_metric: false,
             metric: Box::new(NDCGScorer::new(10)),
+            stop_metric: None,
+            report_metrics: Vec::new(),
+            objective: Box::new(Lambda::new(Box::new(NDCGScorer::new(10)))),
             validate: None,
         };
         let mut lambdamart = LambdaMART::new(config);
@@ -266,5 +344,136 @@ mod test {
         // This is a verified result. Use as a guard for future
         // modifications.
         assert_eq!(lambdamart.evaluate(&validate_set), 0.5694960535660895);
+
+        // The saved model gives the same result.
+        let mut buf = Vec::new();
+        lambdamart.ensemble().save(&mut buf).unwrap();
+        let ensemble = Ensemble::load(buf.as_slice()).unwrap();
+        assert_eq!(ensemble.len(), 10);
+        assert_eq!(ensemble.nfeatures(), 46);
+        assert_eq!(
+            validate_set.evaluate(&ensemble, &lambdamart.config.metric),
+            0.5694960535660895
+        );
+    }
+
+    /// Returns a config of 5 small trees without sampling.
+    fn sampling_config(train: DataSet, seed: usize) -> Config {
+        Config {
+            train: train,
+            test: None,
+            trees: 5,
+            early_stop: 100,
+            subsample: 1.0,
+            feature_fraction_per_tree: 1.0,
+            feature_fraction_per_node: 1.0,
+            seed: seed,
+            learning_rate: 0.1,
+            max_leaves: 10,
+            min_leaf_samples: 1,
+            max_depth: Some(3),
+            min_split_gain: 0.01,
+            min_leaf_weight: 0.1,
+            l1: 0.1,
+            l2: 1.0,
+            thresholds: 256,
+            binning: Binning::Uniform,
+            print_metric: false,
+            metric: Box::new(NDCGScorer::new(10)),
+            stop_metric: None,
+            report_metrics: Vec::new(),
+            objective: Box::new(Lambda::new(Box::new(NDCGScorer::new(10)))),
+            validate: None,
+        }
+    }
+
+    #[test]
+    fn test_sampling() {
+        let path = "./data/train-lite.txt";
+        let f = File::open(path).unwrap();
+        let dataset = DataSet::load(f).unwrap();
+
+        let learn = |seed: usize| {
+            let mut config = sampling_config(dataset.clone(), seed);
+            config.subsample = 0.5;
+            config.feature_fraction_per_tree = 0.5;
+            config.feature_fraction_per_node = 0.5;
+            let mut lambdamart = LambdaMART::new(config);
+            lambdamart.learn().unwrap();
+            lambdamart.evaluate(&dataset)
+        };
+
+        // The same seed gives the same model.
+        assert_eq!(learn(1), learn(1));
+    }
+
+    #[test]
+    fn test_feature_fraction() {
+        let path = "./data/train-lite.txt";
+        let f = File::open(path).unwrap();
+        let dataset = DataSet::load(f).unwrap();
+
+        // The features split on by each tree of the saved model.
+        let learn = |seed: usize| {
+            let mut config = sampling_config(dataset.clone(), seed);
+            config.feature_fraction_per_tree = 0.1;
+            config.feature_fraction_per_node = 0.5;
+            let mut lambdamart = LambdaMART::new(config);
+            lambdamart.learn().unwrap();
+
+            let mut buf = Vec::new();
+            lambdamart.ensemble().save(&mut buf).unwrap();
+            let mut trees: Vec<Vec<Id>> = Vec::new();
+            for line in String::from_utf8(buf).unwrap().lines() {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                match fields[0] {
+                    "tree" => trees.push(Vec::new()),
+                    "split" => {
+                        let fid = fields[1].parse().unwrap();
+                        trees.last_mut().unwrap().push(fid);
+                    }
+                    _ => {}
+                }
+            }
+            for fids in trees.iter_mut() {
+                fids.sort();
+                fids.dedup();
+            }
+            trees
+        };
+
+        // Each tree splits on at most 5 of the 46 features.
+        let trees = learn(1);
+        assert_eq!(trees.len(), 5);
+        for fids in trees.iter() {
+            assert!(!fids.is_empty() && fids.len() <= 5);
+        }
+        assert_eq!(learn(1), trees);
+        assert_ne!(learn(2), trees);
+    }
+
+    #[test]
+    fn test_subsample() {
+        let path = "./data/train-lite.txt";
+        let f = File::open(path).unwrap();
+        let dataset = DataSet::load(f).unwrap();
+
+        // The scores of the instances by the model.
+        let learn = |seed: usize, subsample: f64| {
+            let mut config = sampling_config(dataset.clone(), seed);
+            config.subsample = subsample;
+            let mut lambdamart = LambdaMART::new(config);
+            lambdamart.learn().unwrap();
+            dataset
+                .iter()
+                .map(|instance| lambdamart.ensemble().evaluate(instance))
+                .collect::<Vec<f64>>()
+        };
+
+        // Without sampling the seed makes no difference, and with
+        // sampling it draws the queries.
+        assert_eq!(learn(1, 1.0), learn(2, 1.0));
+        assert_eq!(learn(1, 0.5), learn(1, 0.5));
+        assert_ne!(learn(1, 0.5), learn(2, 0.5));
     }
 }
@@ -2,27 +2,48 @@ use clap::{App, Arg, ArgMatches, SubCommand};
 use std::fs::File;
 use self::lambdamart::*;
 use std;
-use std::process::exit;
+use util::{exit_on_error, Error};
 use metric;
 use train::dataset::*;
+use format::csv::CsvFormat;
+use format::feature_names::FeatureNames;
+use train::lambdamart::training_set::Binning;
 
 pub mod training_set;
 pub mod lambdamart;
 pub mod regression_tree;
 pub mod histogram;
+pub mod objective;
 
 struct LambdaMARTParameter<'a> {
     train_file_path: &'a str,
     validate_file_path: Option<&'a str>,
     test_file_path: Option<&'a str>,
+    model_file_path: Option<&'a str>,
+    feature_names_file_path: Option<&'a str>,
+    csv_format: CsvFormat,
+    regroup_queries: bool,
     metric: &'a str,
     metric_k: usize,
+    stop_metric: Option<&'a str>,
+    report_metrics: Vec<&'a str>,
+    objective: &'a str,
     trees: usize,
     leaves: usize,
     shrinkage: f64,
     thresholds_count: usize,
+    binning: Binning,
     min_leaf_samples: usize,
+    max_depth: Option<usize>,
+    min_split_gain: f64,
+    min_leaf_weight: f64,
+    l1: f64,
+    l2: f64,
     early_stop: usize,
+    subsample: f64,
+    feature_fraction_per_tree: f64,
+    feature_fraction_per_node: f64,
+    seed: usize,
 }
 
 impl<'a> LambdaMARTParameter<'a> {
@@ -31,9 +52,15 @@ impl<'a> LambdaMARTParameter<'a> {
         let train_file_path = matches.value_of("train-file").unwrap();
         let validate_file_path = matches.value_of("validate-file");
         let test_file_path = matches.value_of("test-file");
-        let metric = matches.value_of("metric").unwrap();
+        let model_file_path = matches.value_of("model-file");
+        let feature_names_file_path = matches.value_of("feature-names-file");
+        let csv_format = CsvFormat::from_matches(matches);
+        let regroup_queries = matches.is_present("regroup-queries");
         let metric_k = value_t!(matches.value_of("metric-k"), usize)
             .unwrap_or_else(|e| e.exit());
+        let (metric, stop_metric, report_metrics) =
+            super::metric_specs(matches, metric_k);
+        let objective = matches.value_of("objective").unwrap();
         let trees = value_t!(matches.value_of("trees"), usize).unwrap_or_else(
             |e| e.exit(),
         );
@@ -43,46 +70,126 @@ impl<'a> LambdaMARTParameter<'a> {
             .unwrap_or_else(|e| e.exit());
         let thresholds_count = value_t!(matches.value_of("thresholds"), usize)
             .unwrap_or_else(|e| e.exit());
+        // The value is valid.
+        let binning = Binning::new(matches.value_of("binning").unwrap())
+            .unwrap();
         let min_leaf_samples =
             value_t!(matches.value_of("min-leaf-support"), usize)
                 .unwrap_or_else(|e| e.exit());
+        let max_depth = matches.value_of("max-depth").map(|_| {
+            value_t!(matches.value_of("max-depth"), usize)
+                .unwrap_or_else(|e| e.exit())
+        });
+        let min_split_gain = value_t!(matches.value_of("min-split-gain"), f64)
+            .unwrap_or_else(|e| e.exit());
+        let min_leaf_weight =
+            value_t!(matches.value_of("min-leaf-weight"), f64)
+                .unwrap_or_else(|e| e.exit());
+        let l1 = value_t!(matches.value_of("l1"), f64)
+            .unwrap_or_else(|e| e.exit());
+        let l2 = value_t!(matches.value_of("l2"), f64)
+            .unwrap_or_else(|e| e.exit());
         let early_stop = value_t!(matches.value_of("early-stop"), usize)
             .unwrap_or_else(|e| e.exit());
+        let subsample = value_t!(matches.value_of("subsample"), f64)
+            .unwrap_or_else(|e| e.exit());
+        let feature_fraction_per_tree =
+            value_t!(matches.value_of("feature-fraction-per-tree"), f64)
+                .unwrap_or_else(|e| e.exit());
+        let feature_fraction_per_node =
+            value_t!(matches.value_of("feature-fraction-per-node"), f64)
+                .unwrap_or_else(|e| e.exit());
+        let seed = value_t!(matches.value_of("seed"), usize)
+            .unwrap_or_else(|e| e.exit());
+
+        if subsample <= 0.0 || subsample > 1.0 {
+            exit_on_error(Error::Config(
+                format!("Subsample should be in (0, 1]: {}", subsample),
+            ));
+        }
+        for &fraction in
+            [feature_fraction_per_tree, feature_fraction_per_node].iter()
+        {
+            if fraction <= 0.0 || fraction > 1.0 {
+                exit_on_error(Error::Config(format!(
+                    "Feature fraction should be in (0, 1]: {}",
+                    fraction
+                )));
+            }
+        }
 
         LambdaMARTParameter {
             train_file_path: train_file_path,
             validate_file_path: validate_file_path,
             test_file_path: test_file_path,
+            model_file_path: model_file_path,
+            feature_names_file_path: feature_names_file_path,
+            csv_format: csv_format,
+            regroup_queries: regroup_queries,
             metric: metric,
             metric_k: metric_k,
+            stop_metric: stop_metric,
+            report_metrics: report_metrics,
+            objective: objective,
             trees: trees,
             leaves: leaves,
             shrinkage: shrinkage,
             thresholds_count: thresholds_count,
+            binning: binning,
             min_leaf_samples: min_leaf_samples,
+            max_depth: max_depth,
+            min_split_gain: min_split_gain,
+            min_leaf_weight: min_leaf_weight,
+            l1: l1,
+            l2: l2,
             early_stop: early_stop,
+            subsample: subsample,
+            feature_fraction_per_tree: feature_fraction_per_tree,
+            feature_fraction_per_node: feature_fraction_per_node,
+            seed: seed,
         }
     }
 
     pub fn config(&self) -> Config {
-        let train_file =
-            File::open(self.train_file_path).unwrap_or_else(|_e| exit(1));
-        let train_set = DataSet::load(train_file).unwrap_or_else(|_e| exit(1));
+        let mut train_set = DataSet::load_file_with(
+            self.train_file_path,
+            &self.csv_format,
+            self.regroup_queries,
+        ).unwrap_or_else(|e| exit_on_error(e));
+
+        if let Some(path) = self.feature_names_file_path {
+            let feature_names = FeatureNames::load_file(path)
+                .unwrap_or_else(|e| exit_on_error(e));
+            train_set.add_feature_names(feature_names);
+        }
 
+        // The columns of CSV files are mapped to the features of the
+        // training data by name.
+        let csv_format = self.csv_format
+            .clone()
+            .with_feature_names(train_set.feature_names().clone());
         let validate_set = self.validate_file_path.map(|path| {
-            let file = File::open(path).unwrap_or_else(|_e| exit(1));
-            let dataset = DataSet::load(file).unwrap_or_else(|_e| exit(1));
-            dataset
+            DataSet::load_file_with(
+                path,
+                &csv_format,
+                self.regroup_queries,
+            ).unwrap_or_else(|e| exit_on_error(e))
         });
 
         let test_set = self.test_file_path.map(|path| {
-            let file = File::open(path).unwrap_or_else(|_e| exit(1));
-            let dataset = DataSet::load(file).unwrap_or_else(|_e| exit(1));
-            dataset
+            DataSet::load_file_with(
+                path,
+                &csv_format,
+                self.regroup_queries,
+            ).unwrap_or_else(|e| exit_on_error(e))
         });
 
         // The param is valid.
-        let metric = metric::new(self.metric, self.metric_k).unwrap();
+        let metric = metric::parse(self.metric, self.metric_k).unwrap();
+        let objective = objective::new(
+            self.objective,
+            metric::parse(self.metric, self.metric_k).unwrap(),
+        ).unwrap();
 
         Config {
             train: train_set,
@@ -91,11 +198,28 @@ impl<'a> LambdaMARTParameter<'a> {
             learning_rate: self.shrinkage,
             max_leaves: self.leaves,
             min_leaf_samples: self.min_leaf_samples,
+            max_depth: self.max_depth,
+            min_split_gain: self.min_split_gain,
+            min_leaf_weight: self.min_leaf_weight,
+            l1: self.l1,
+            l2: self.l2,
             thresholds: self.thresholds_count,
+            binning: self.binning,
             print_metric: true,
             metric: metric,
+            stop_metric: self.stop_metric
+                .map(|spec| metric::parse(spec, self.metric_k).unwrap()),
+            report_metrics: self.report_metrics
+                .iter()
+                .map(|spec| metric::parse(spec, self.metric_k).unwrap())
+                .collect(),
+            objective: objective,
             validate: validate_set,
             early_stop: self.early_stop,
+            subsample: self.subsample,
+            feature_fraction_per_tree: self.feature_fraction_per_tree,
+            feature_fraction_per_node: self.feature_fraction_per_node,
+            seed: self.seed,
         }
     }
 
@@ -119,16 +243,54 @@ impl<'a> LambdaMARTParameter<'a> {
                 None => "None",
             },
         );
+        print_param(
+            "Model file",
+            match self.model_file_path {
+                Some(path) => path,
+                None => "None",
+            },
+        );
+        print_param(
+            "Feature names file",
+            match self.feature_names_file_path {
+                Some(path) => path,
+                None => "None",
+            },
+        );
         print_param(
             "Metric",
-            self.metric.to_owned() + "@" + &self.metric_k.to_string(),
+            metric::parse(self.metric, self.metric_k).unwrap().name(),
+        );
+        print_param("Stop metric", self.stop_metric.unwrap_or("Metric"));
+        print_param(
+            "Report metrics",
+            if self.report_metrics.is_empty() {
+                "None".to_string()
+            } else {
+                self.report_metrics.join(",")
+            },
         );
+        print_param("Objective", self.objective);
         print_param("Trees", self.trees);
         print_param("Leaves", self.leaves);
         print_param("Shrinkage", self.shrinkage);
         print_param("Thresholds count", self.thresholds_count);
+        print_param("Binning", self.binning.name());
         print_param("Min leaf samples", self.min_leaf_samples);
+        print_param(
+            "Max depth",
+            self.max_depth
+                .map_or("None".to_string(), |depth| depth.to_string()),
+        );
+        print_param("Min split gain", self.min_split_gain);
+        print_param("Min leaf weight", self.min_leaf_weight);
+        print_param("L1", self.l1);
+        print_param("L2", self.l2);
         print_param("Early stop", self.early_stop);
+        print_param("Subsample", self.subsample);
+        print_param("Features per tree", self.feature_fraction_per_tree);
+        print_param("Features per node", self.feature_fraction_per_node);
+        print_param("Seed", self.seed);
     }
 }
 
@@ -137,8 +299,16 @@ pub fn main<'a>(matches: &ArgMatches<'a>) {
     param.print();
 
     let mut lambdamart = LambdaMART::new(param.config());
-    lambdamart.init().unwrap();
-    lambdamart.learn().unwrap();
+    lambdamart.init().unwrap_or_else(|e| exit_on_error(e));
+    lambdamart.learn().unwrap_or_else(|e| exit_on_error(e));
+
+    if let Some(path) = param.model_file_path {
+        let file = File::create(path)
+            .map_err(|e| Error::from(e).in_file(path))
+            .unwrap_or_else(|e| exit_on_error(e));
+        lambdamart.ensemble().save(file).unwrap_or_else(|e| exit_on_error(e));
+        println!("Model saved to {}", path);
+    }
 }
 
 pub fn clap_command<'a, 'b>() -> App<'a, 'b> {
@@ -198,14 +368,100 @@ pub fn clap_command<'a, 'b>() -> App<'a, 'b> {
                 .help("Min leaf support -- minimum #samples each leaf has to contain"),
         )
         .arg(
-            Arg::with_name("early-stop")
-                .required_if("type", "lambdamart")
-                .long("early-stop")
+            Arg::with_name("objective")
+                .long("objective")
+                .possible_values(&["lambda", "mart", "logistic"])
+                .default_value("lambda")
+                .display_order(106)
+                .help("Objective to minimize: LambdaMART, squared error (MART) or logistic loss"),
+        )
+        .arg(
+            Arg::with_name("subsample")
+                .long("subsample")
+                .takes_value(true)
+                .value_name("RATE")
+                .default_value("1.0")
+                .display_order(107)
+                .help("Fraction of queries sampled for each tree"),
+        )
+        .arg(
+            Arg::with_name("feature-fraction-per-tree")
+                .long("feature-fraction-per-tree")
+                .takes_value(true)
+                .value_name("RATE")
+                .default_value("1.0")
+                .display_order(108)
+                .help("Fraction of features sampled for each tree"),
+        )
+        .arg(
+            Arg::with_name("feature-fraction-per-node")
+                .long("feature-fraction-per-node")
+                .takes_value(true)
+                .value_name("RATE")
+                .default_value("1.0")
+                .display_order(109)
+                .help("Fraction of the features of the tree sampled for each split"),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
                 .takes_value(true)
                 .value_name("NUM")
-                .default_value("100")
-                .display_order(106)
-                .help("Stop early when no improvement is observed on validaton data in e consecutive rounds"),
+                .default_value("1")
+                .display_order(110)
+                .help("Seed for sampling the queries and features"),
+        )
+        .arg(
+            Arg::with_name("max-depth")
+                .long("max-depth")
+                .takes_value(true)
+                .value_name("NUM")
+                .display_order(111)
+                .help("Max depth of each tree, unlimited by default"),
+        )
+        .arg(
+            Arg::with_name("min-split-gain")
+                .long("min-split-gain")
+                .takes_value(true)
+                .value_name("GAIN")
+                .default_value("0")
+                .display_order(112)
+                .help("Minimum gain required to split a node"),
+        )
+        .arg(
+            Arg::with_name("min-leaf-weight")
+                .long("min-leaf-weight")
+                .takes_value(true)
+                .value_name("WEIGHT")
+                .default_value("0")
+                .display_order(113)
+                .help("Minimum sum of the hessians each leaf has to contain"),
+        )
+        .arg(
+            Arg::with_name("l1")
+                .long("l1")
+                .takes_value(true)
+                .value_name("FACTOR")
+                .default_value("0")
+                .display_order(114)
+                .help("L1 regularization of the leaf outputs"),
+        )
+        .arg(
+            Arg::with_name("l2")
+                .long("l2")
+                .takes_value(true)
+                .value_name("FACTOR")
+                .default_value("0")
+                .display_order(115)
+                .help("L2 regularization of the leaf outputs"),
+        )
+        .arg(
+            Arg::with_name("binning")
+                .long("binning")
+                .possible_values(&["uniform", "quantile"])
+                .default_value("uniform")
+                .display_order(116)
+                .help("Generate thresholds evenly between min and max, or at the quantiles"),
         );
     lambdamart_command
 }
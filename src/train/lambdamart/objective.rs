@@ -0,0 +1,152 @@
+use metric::Measure;
+use super::training_set::TrainSet;
+
+/// The loss function that the trees are boosted to minimize. Before
+/// fitting each tree, the objective sets the lambda (negative
+/// gradient) and weight (hessian) of each instance in the training
+/// set from the current model scores. The trees split on the lambdas,
+/// and the leaf outputs are the Newton steps sum(lambda) /
+/// sum(weight).
+pub trait Objective {
+    fn update(&self, training: &mut TrainSet);
+
+    /// Name of the objective. For display.
+    fn name(&self) -> String;
+}
+
+/// Squared error of the labels, that is MART for pointwise
+/// regression.
+pub struct SquaredError;
+
+impl Objective for SquaredError {
+    fn update(&self, training: &mut TrainSet) {
+        training.update_pointwise(|label, score| (label - score, 1.0));
+    }
+
+    fn name(&self) -> String {
+        "mart".to_string()
+    }
+}
+
+/// Logistic loss for binary classification. Instances with positive
+/// labels are the positive class, and the model scores are
+/// log-odds.
+pub struct Logistic;
+
+/// Lower bound of the hessians of the logistic loss. Without it, the
+/// Newton step of confidently classified instances stays about 1 as
+/// p(1 - p) vanishes, so separable data are pushed apart forever.
+const MIN_LOGISTIC_HESSIAN: f64 = 1e-16;
+
+impl Objective for Logistic {
+    fn update(&self, training: &mut TrainSet) {
+        training.update_pointwise(|label, score| {
+            let target = if label > 0.0 { 1.0 } else { 0.0 };
+            let p = 1.0 / (1.0 + (-score).exp());
+            (target - p, (p * (1.0 - p)).max(MIN_LOGISTIC_HESSIAN))
+        });
+    }
+
+    fn name(&self) -> String {
+        "logistic".to_string()
+    }
+}
+
+/// The LambdaMART objective. The lambdas of each pair of instances
+/// in a query are weighted by the change of the metric when swapping
+/// them.
+pub struct Lambda {
+    metric: Box<dyn Measure>,
+}
+
+impl Lambda {
+    pub fn new(metric: Box<dyn Measure>) -> Lambda {
+        Lambda { metric: metric }
+    }
+}
+
+impl Objective for Lambda {
+    fn update(&self, training: &mut TrainSet) {
+        training.update_lambdas_weights(&self.metric);
+    }
+
+    fn name(&self) -> String {
+        "lambda".to_string()
+    }
+}
+
+/// Creates an objective by name. The metric is only used by the
+/// lambda objective.
+pub fn new(
+    name: &str,
+    metric: Box<dyn Measure>,
+) -> Option<Box<dyn Objective>> {
+    match name {
+        "lambda" => Some(Box::new(Lambda::new(metric))),
+        "mart" => Some(Box::new(SquaredError)),
+        "logistic" => Some(Box::new(Logistic)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use train::dataset::DataSet;
+    use train::lambdamart::training_set::TrainSample;
+    use metric;
+    use util::Value;
+
+    /// Returns the Newton step of all the instances with the labels.
+    fn newton_output(objective: &Box<dyn Objective>, labels: &[Value]) -> f64 {
+        // (label, qid, feature_values)
+        let dataset: DataSet = labels
+            .iter()
+            .map(|&label| (label, 1, vec![label]))
+            .collect();
+        let mut training = TrainSet::new(&dataset, 3);
+        objective.update(&mut training);
+        let sample = TrainSample::from(&training);
+        sample.newton_output(0.0, 0.0)
+    }
+
+    #[test]
+    fn test_objectives() {
+        let metric = metric::new("NDCG", 10).unwrap();
+        let mart = new("mart", metric::new("NDCG", 10).unwrap()).unwrap();
+        assert_eq!(mart.name(), "mart");
+        assert_eq!(newton_output(&mart, &[1.0, 2.0, 3.0]), 2.0);
+
+        // p = 0.5 for all the instances, so the lambdas are 0.5 or
+        // -0.5 and the weights are 0.25.
+        let logistic = new("logistic", metric).unwrap();
+        assert_eq!(newton_output(&logistic, &[1.0, 1.0, 1.0, 0.0]), 1.0);
+
+        assert!(new("hinge", metric::new("NDCG", 10).unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_logistic_separable() {
+        // (label, qid, feature_values)
+        let dataset: DataSet = vec![
+            (1.0, 1, vec![1.0]),
+            (1.0, 1, vec![1.0]),
+            (0.0, 1, vec![0.0]),
+            (0.0, 1, vec![0.0]),
+        ].into_iter()
+            .collect();
+        let mut training = TrainSet::new(&dataset, 3);
+        // The classes are separated by the scores.
+        training.init_model_scores(&[40.0, 40.0, -40.0, -40.0]);
+        Logistic.update(&mut training);
+
+        // The steps vanish instead of pushing the scores further
+        // apart.
+        let positive = TrainSample::new(&training, vec![0, 1]);
+        assert!(positive.newton_output(0.0, 0.0).abs() < 0.1);
+        let negative = TrainSample::new(&training, vec![2, 3]);
+        assert!(negative.newton_output(0.0, 0.0).abs() < 0.1);
+        let sample = TrainSample::from(&training);
+        assert!(sample.newton_output(0.0, 0.0).is_finite());
+    }
+}
@@ -3,12 +3,19 @@ use train::dataset::*;
 use util::*;
 use std::collections::BinaryHeap;
 use std::cmp::Ordering;
+use std::io::prelude::*;
+use std::io::BufWriter;
 use train::lambdamart::training_set::*;
+use train::model::{self, ModelHeader, ModelLines};
+use rand::{self, Rng, XorShiftRng};
+use format::feature_names::FeatureNames;
 
 /// A node in the regression tree.
 struct Node {
     fid: Option<Id>,
     threshold: Option<Value>,
+    // Whether the instances missing the feature go to the left child.
+    missing_left: bool,
     output: Option<f64>,
     parent: Option<usize>,
     left: Option<usize>,
@@ -21,6 +28,7 @@ impl Node {
         Node {
             fid: None,
             threshold: None,
+            missing_left: false,
             parent: parent,
             left: None,
             right: None,
@@ -32,11 +40,13 @@ impl Node {
         &mut self,
         fid: Id,
         threshold: Value,
+        missing_left: bool,
         left: usize,
         right: usize,
     ) {
         self.fid = Some(fid);
         self.threshold = Some(threshold);
+        self.missing_left = missing_left;
         self.left = Some(left);
         self.right = Some(right);
     }
@@ -50,9 +60,10 @@ impl std::fmt::Debug for Node {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
             f,
-            "Node {{ fid: {:?}, threshold: {:?}, output: {:?}, left: {:?}, right: {:?} }}",
+            "Node {{ fid: {:?}, threshold: {:?}, missing_left: {:?}, output: {:?}, left: {:?}, right: {:?} }}",
             self.fid,
             self.threshold,
+            self.missing_left,
             self.output,
             self.left,
             self.right
@@ -74,21 +85,39 @@ pub struct RegressionTree {
     // Minimal count of samples per leaf.
     min_leaf_samples: usize,
     max_leaves: usize,
+    // Fraction of the features considered when splitting a node.
+    feature_fraction: f64,
+    // Max depth of the leaves. The root is of depth 0.
+    max_depth: Option<usize>,
+    // Minimal gain of the s value required to split a node.
+    min_split_gain: f64,
+    // Minimal sum of the weights (hessians) per leaf.
+    min_leaf_weight: f64,
+    // L1 and L2 regularization of the leaf outputs.
+    l1: f64,
+    l2: f64,
     nodes: Vec<Node>,
 }
 
 struct NodeData<'a> {
     index: usize,
+    depth: usize,
+    // The variance of the sample, which decides the order of
+    // splitting.
+    variance: f64,
     sample: TrainSample<'a>,
 }
 
 impl<'a> NodeData<'a> {
     pub fn new(
         index: usize,
+        depth: usize,
         sample: TrainSample<'a>,
     ) -> NodeData<'a> {
         NodeData {
             index: index,
+            depth: depth,
+            variance: sample.variance(),
             sample: sample,
         }
     }
@@ -96,13 +125,13 @@ impl<'a> NodeData<'a> {
 
 impl<'a> PartialEq for NodeData<'a> {
     fn eq(&self, other: &NodeData) -> bool {
-        self.sample.variance() == other.sample.variance()
+        self.variance == other.variance
     }
 }
 
 impl<'a> PartialOrd for NodeData<'a> {
     fn partial_cmp(&self, other: &NodeData) -> Option<Ordering> {
-        self.sample.variance().partial_cmp(&other.sample.variance())
+        self.variance.partial_cmp(&other.variance)
     }
 }
 
@@ -126,15 +155,64 @@ impl RegressionTree {
             learning_rate: learning_rate,
             min_leaf_samples: min_leaf_samples,
             max_leaves: max_leaves,
+            feature_fraction: 1.0,
+            max_depth: None,
+            min_split_gain: 0.0,
+            min_leaf_weight: 0.0,
+            l1: 0.0,
+            l2: 0.0,
             nodes: Vec::new(),
         }
     }
 
+    /// Returns the factor of the outputs of the leaves.
+    pub fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    /// Sets the factor of the outputs of the leaves, for example to
+    /// rescale a tree that is already fitted.
+    pub fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
+
+    /// Limits the depth of the leaves. The root is of depth 0.
+    pub fn set_max_depth(&mut self, max_depth: Option<usize>) {
+        self.max_depth = max_depth;
+    }
+
+    /// Only split a node if the split improves the s value by at
+    /// least `min_split_gain`.
+    pub fn set_min_split_gain(&mut self, min_split_gain: f64) {
+        self.min_split_gain = min_split_gain;
+    }
+
+    /// Only split a node if the weights of the instances on each
+    /// side sum to at least `min_leaf_weight`.
+    pub fn set_min_leaf_weight(&mut self, min_leaf_weight: f64) {
+        self.min_leaf_weight = min_leaf_weight;
+    }
+
+    /// Sets the L1 and L2 regularization of the leaf outputs. See
+    /// `TrainSample::newton_output`.
+    pub fn set_regularization(&mut self, l1: f64, l2: f64) {
+        self.l1 = l1;
+        self.l2 = l2;
+    }
+
+    /// Only consider a random fraction of the features when splitting
+    /// a node. The features are sampled again for each node.
+    pub fn set_feature_fraction(&mut self, feature_fraction: f64) {
+        assert!(feature_fraction > 0.0 && feature_fraction <= 1.0);
+        self.feature_fraction = feature_fraction;
+    }
+
     fn split_node(
         &mut self,
         index: usize,
         fid: usize,
         threshold: f64,
+        missing_left: bool,
     ) -> (usize, usize) {
         let left_index = self.nodes.len();
         let mut left = Node::new(Some(index));
@@ -146,7 +224,13 @@ impl RegressionTree {
         self.nodes.push(right);
 
         let node = &mut self.nodes[index];
-        node.set_non_leaf(fid, threshold, left_index, right_index);
+        node.set_non_leaf(
+            fid,
+            threshold,
+            missing_left,
+            left_index,
+            right_index,
+        );
 
         (left_index, right_index)
     }
@@ -158,6 +242,23 @@ impl RegressionTree {
     /// Fit to a training.
     pub fn fit(&mut self, training: &TrainSet) -> Vec<Value> {
         let sample = TrainSample::from(training);
+        let fids: Vec<Id> = training.fid_iter().collect();
+        let mut rng = XorShiftRng::new_unseeded();
+        self.fit_sample(training, sample, &fids, &mut rng)
+    }
+
+    /// Fit to a sample of the training, only splitting on the given
+    /// features. `rng` is used for sampling the features of each node
+    /// if the feature fraction is less than 1. The returned outputs
+    /// are indexed by the instances in the training, and are zero for
+    /// the instances not in the sample.
+    pub fn fit_sample<'a, R: Rng>(
+        &mut self,
+        training: &'a TrainSet<'a>,
+        sample: TrainSample<'a>,
+        fids: &[Id],
+        rng: &mut R,
+    ) -> Vec<Value> {
         let mut leaves = 0;
         let mut leaf_output: Vec<Value> = vec![0.0; training.len()];
 
@@ -166,13 +267,20 @@ impl RegressionTree {
 
         let mut queue: BinaryHeap<NodeData> =
             BinaryHeap::with_capacity(self.max_leaves);
-        queue.push(NodeData::new(0, sample));
+        queue.push(NodeData::new(0, 0, sample));
 
         while !queue.is_empty() {
-            let NodeData { index, sample } = queue.pop().unwrap();
-            // We have reached leaves count limitation.
-            if 1 + leaves + queue.len() >= self.max_leaves {
-                let value = sample.newton_output();
+            let NodeData {
+                index,
+                depth,
+                mut sample,
+                ..
+            } = queue.pop().unwrap();
+            // We have reached leaves count or depth limitation.
+            if 1 + leaves + queue.len() >= self.max_leaves ||
+                self.max_depth.map_or(false, |max_depth| depth >= max_depth)
+            {
+                let value = sample.newton_output(self.l1, self.l2);
                 let output = value * self.learning_rate;
                 self.set_leaf_node(index, value);
                 sample.update_output(&mut leaf_output, output);
@@ -180,9 +288,36 @@ impl RegressionTree {
                 continue;
             }
 
-            let split_result = sample.split(self.min_leaf_samples);
+            let split_result = if self.feature_fraction < 1.0 {
+                let amount = (fids.len() as f64 * self.feature_fraction)
+                    .round()
+                    .max(1.0) as usize;
+                let candidates = rand::seq::sample_slice(rng, fids, amount);
+                sample.split_on(
+                    &candidates,
+                    self.min_leaf_samples,
+                    self.min_leaf_weight,
+                )
+            } else {
+                sample.split_on(
+                    fids,
+                    self.min_leaf_samples,
+                    self.min_leaf_weight,
+                )
+            };
+            // The gain is checked only if required, because rounding
+            // errors may make it slightly negative.
+            let split_result = split_result.and_then(|split| {
+                if self.min_split_gain > 0.0 &&
+                    split.s - sample.s_value() < self.min_split_gain
+                {
+                    None
+                } else {
+                    Some(split)
+                }
+            });
             if split_result.is_none() {
-                let value = sample.newton_output();
+                let value = sample.newton_output(self.l1, self.l2);
                 let output = value * self.learning_rate;
                 self.set_leaf_node(index, value);
                 sample.update_output(&mut leaf_output, output);
@@ -190,21 +325,39 @@ impl RegressionTree {
                 continue;
             }
 
-            let split = split_result.unwrap();
+            let mut split = split_result.unwrap();
             let left_len = split.left.len();
             let right_len = split.right.len();
 
+            // Only the children that may be split further need
+            // histograms.
+            let deeper = self.max_depth
+                .map_or(true, |max_depth| depth + 1 < max_depth);
+            let splittable = |len: usize| {
+                leaves + queue.len() + 2 < self.max_leaves && deeper &&
+                    len >= 2 * self.min_leaf_samples
+            };
+            split.derive_histograms(
+                splittable(left_len),
+                splittable(right_len),
+            );
+
             // Split node at `index`.
-            let (left, right) =
-                self.split_node(index, split.fid, split.threshold);
+            let (left, right) = self.split_node(
+                index,
+                split.fid,
+                split.threshold,
+                split.missing_left,
+            );
 
-            queue.push(NodeData::new(left, split.left));
-            queue.push(NodeData::new(right, split.right));
+            queue.push(NodeData::new(left, depth + 1, split.left));
+            queue.push(NodeData::new(right, depth + 1, split.right));
 
             debug!(
-                "Split: fid:{} threshold:{} s:{}",
-                split.fid,
+                "Split: fid:{} threshold:{} missing_left:{} s:{}",
+                training.feature_names().describe(split.fid),
                 split.threshold,
+                split.missing_left,
                 split.s
             );
             debug!("Split: {} => {} + {}", sample.len(), left_len, right_len);
@@ -213,7 +366,132 @@ impl RegressionTree {
         leaf_output
     }
 
-    pub fn print(&self) {
+    /// Writes the tree in the model file format. The first line is
+    /// "tree <learning rate> <nodes count>", followed by one line for
+    /// each node in index order: "split <fid> <threshold> <left>
+    /// <right> <missing>" for a non-leaf node, where <missing> is
+    /// "left" or "right", the child of the instances missing the
+    /// feature, and "leaf <output>" for a leaf.
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writeln!(writer, "tree {} {}", self.learning_rate, self.nodes.len())?;
+        for node in self.nodes.iter() {
+            match node.output {
+                Some(output) => writeln!(writer, "leaf {}", output)?,
+                None => writeln!(
+                    writer,
+                    "split {} {} {} {} {}",
+                    node.fid.unwrap(),
+                    node.threshold.unwrap(),
+                    node.left.unwrap(),
+                    node.right.unwrap(),
+                    if node.missing_left { "left" } else { "right" }
+                )?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a tree written by `RegressionTree::write`. `lines` yields
+    /// the remaining lines of the model file.
+    ///
+    /// Models written before missing values were supported have no
+    /// <missing> field, and took the missing values as 0.0.
+    fn read(lines: &mut ModelLines) -> ParseResult<RegressionTree> {
+        fn parse_header(line: &str) -> ParseResult<(f64, usize)> {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 3 || fields[0] != "tree" {
+                return Err(Error::parse(line, "Invalid tree header"));
+            }
+            let learning_rate =
+                model::parse_field(fields[1], "Invalid learning rate")?;
+            let nnodes = model::parse_field(fields[2], "Invalid nodes count")?;
+            if nnodes == 0 {
+                return Err(Error::parse(fields[2], "A tree has no nodes"));
+            }
+            Ok((learning_rate, nnodes))
+        }
+
+        // The children of a node follow it, so that the nodes form a
+        // tree and a bad file can't make the evaluation loop.
+        fn parse_node(
+            line: &str,
+            index: usize,
+            nnodes: usize,
+        ) -> ParseResult<Node> {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let mut node = Node::new(None);
+            match (fields.get(0).cloned(), fields.len()) {
+                (Some("leaf"), 2) => {
+                    node.set_leaf(
+                        model::parse_field(fields[1], "Invalid leaf output")?,
+                    );
+                }
+                (Some("split"), 5) |
+                (Some("split"), 6) => {
+                    let fid =
+                        model::parse_field(fields[1], "Invalid feature id")?;
+                    let threshold =
+                        model::parse_field(fields[2], "Invalid threshold")?;
+                    let child = |field: &str| match field.parse::<usize>() {
+                        Ok(child) if index < child && child < nnodes => {
+                            Ok(child)
+                        }
+                        _ => Err(Error::parse(field, "Invalid child index")),
+                    };
+                    let left = child(fields[3])?;
+                    let right = child(fields[4])?;
+                    let missing_left = match fields.get(5).cloned() {
+                        Some("left") => true,
+                        Some("right") => false,
+                        None => 0.0 <= threshold,
+                        Some(field) => {
+                            let message = "Invalid missing direction";
+                            return Err(Error::parse(field, message));
+                        }
+                    };
+                    node.set_non_leaf(
+                        fid,
+                        threshold,
+                        missing_left,
+                        left,
+                        right,
+                    );
+                }
+                _ => return Err(Error::parse(line, "Invalid tree node")),
+            }
+            Ok(node)
+        }
+
+        let (line_number, header) = model::next_line(lines)?;
+        let (learning_rate, nnodes) =
+            parse_header(&header).map_err(|e| e.at_line(line_number))?;
+
+        let mut tree = RegressionTree::new(learning_rate, 0, 0);
+        for index in 0..nnodes {
+            let (line_number, line) = model::next_line(lines)?;
+            let node = parse_node(&line, index, nnodes)
+                .map_err(|e| e.at_line(line_number))?;
+            tree.nodes.push(node);
+        }
+        for index in 0..nnodes {
+            if let (Some(left), Some(right)) =
+                (tree.nodes[index].left, tree.nodes[index].right)
+            {
+                tree.nodes[left].parent = Some(index);
+                tree.nodes[right].parent = Some(index);
+            }
+        }
+
+        tree.max_leaves = tree.nodes
+            .iter()
+            .filter(|node| node.output.is_some())
+            .count();
+        Ok(tree)
+    }
+
+    /// Prints the tree, with the features described by their names
+    /// if known.
+    pub fn print(&self, feature_names: &FeatureNames) {
         if self.nodes.is_empty() {
             println!("Empty tree");
             return;
@@ -229,9 +507,10 @@ impl RegressionTree {
                 println!("{{ output: {:?} }}", output);
             } else {
                 println!(
-                    "{{ fid: {:?}, threshold: {:?} }}",
-                    option_to_string(&node.fid),
-                    option_to_string(&node.threshold)
+                    "{{ fid: {}, threshold: {}, missing_left: {:?} }}",
+                    feature_names.describe(node.fid.unwrap()),
+                    option_to_string(&node.threshold),
+                    node.missing_left
                 );
                 queue.push((node.left.unwrap(), indent + 2));
                 queue.push((node.right.unwrap(), indent + 2));
@@ -241,11 +520,16 @@ impl RegressionTree {
 }
 
 impl ::train::Evaluate for RegressionTree {
-    /// Evaluate an input.
+    /// Evaluate an input. The instances missing the feature of a node
+    /// go to the child learned in training.
     fn evaluate(&self, instance: &Instance) -> f64 {
         let mut node = &self.nodes[0];
         while node.output.is_none() {
-            if instance.value(node.fid.unwrap()) <= node.threshold.unwrap() {
+            let go_left = match instance.get(node.fid.unwrap()) {
+                Some(value) => value <= node.threshold.unwrap(),
+                None => node.missing_left,
+            };
+            if go_left {
                 node = &self.nodes[node.left.unwrap()];
             } else {
                 node = &self.nodes[node.right.unwrap()];
@@ -257,13 +541,138 @@ impl ::train::Evaluate for RegressionTree {
     }
 }
 
+/// A sequence of regression trees whose outputs are summed up.
 pub struct Ensemble {
     trees: Vec<RegressionTree>,
+    // Number of features of the training data.
+    nfeatures: usize,
+    // Training parameters as (name, value) pairs. Only used for
+    // recording how the model was trained.
+    params: Vec<(String, String)>,
+    // Names of the features of the training data.
+    feature_names: FeatureNames,
 }
 
 impl Ensemble {
     pub fn new() -> Ensemble {
-        Ensemble { trees: Vec::new() }
+        Ensemble {
+            trees: Vec::new(),
+            nfeatures: 0,
+            params: Vec::new(),
+            feature_names: FeatureNames::new(),
+        }
+    }
+
+    /// Returns the number of features of the training data.
+    pub fn nfeatures(&self) -> usize {
+        self.nfeatures
+    }
+
+    pub fn set_nfeatures(&mut self, nfeatures: usize) {
+        self.nfeatures = nfeatures;
+    }
+
+    /// Returns the names of the features of the training data.
+    pub fn feature_names(&self) -> &FeatureNames {
+        &self.feature_names
+    }
+
+    pub fn set_feature_names(&mut self, feature_names: FeatureNames) {
+        self.feature_names = feature_names;
+    }
+
+    /// Prints the trees, with the features described by their names
+    /// if known.
+    pub fn print(&self) {
+        for (index, tree) in self.trees.iter().enumerate() {
+            println!("Tree {}:", index);
+            tree.print(&self.feature_names);
+        }
+    }
+
+    /// Returns the training parameters recorded in the model.
+    pub fn params(&self) -> &[(String, String)] {
+        &self.params
+    }
+
+    /// Records a training parameter. The value should not contain
+    /// line breaks.
+    pub fn add_param<T: ToString>(&mut self, name: &str, value: T) {
+        self.params.push((name.to_string(), value.to_string()));
+    }
+
+    /// Writes the ensemble to a writer in the model file format.
+    ///
+    /// The body following the model header (see `ModelHeader`) is:
+    ///
+    /// ```text
+    /// trees <number of trees>
+    /// tree <learning rate> <number of nodes>
+    /// split <fid> <threshold> <left child> <right child> <left|right>
+    /// leaf <output>
+    /// ...
+    /// ```
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use rforests::util::Result;
+    /// # use rforests::train::lambdamart::regression_tree::Ensemble;
+    /// # fn save(ensemble: &Ensemble) -> Result<()> {
+    /// use std::fs::File;
+    ///
+    /// let file = File::create("model.txt")?;
+    /// ensemble.save(file)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn save<W: Write>(&self, writer: W) -> Result<()> {
+        let mut writer = BufWriter::new(writer);
+        ModelHeader::new("ensemble", self.nfeatures, &self.params)
+            .with_feature_names(&self.feature_names)
+            .write(&mut writer)?;
+        writeln!(writer, "trees {}", self.trees.len())?;
+        for tree in self.trees.iter() {
+            tree.write(&mut writer)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Loads an ensemble written by `Ensemble::save`.
+    pub fn load<R: Read>(reader: R) -> ParseResult<Ensemble> {
+        let mut lines = model::read_lines(reader)?;
+        let header = ModelHeader::read(&mut lines)?;
+        if header.kind != "ensemble" {
+            return Err(Error::parse(&header.kind, "Not an ensemble model"));
+        }
+        Ensemble::read(header, &mut lines)
+    }
+
+    /// Reads the body of an ensemble model file.
+    pub fn read(
+        header: ModelHeader,
+        lines: &mut ModelLines,
+    ) -> ParseResult<Ensemble> {
+        let (line_number, line) = model::next_line(lines)?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let ntrees = match (fields.get(0).cloned(), fields.len()) {
+            (Some("trees"), 2) => {
+                model::parse_field(fields[1], "Invalid trees count")
+            }
+            _ => Err(Error::parse(&line, "Invalid model line")),
+        }.map_err(|e| e.at_line(line_number))?;
+
+        let mut ensemble = Ensemble::new();
+        ensemble.nfeatures = header.nfeatures;
+        ensemble.params = header.params;
+        ensemble.feature_names = header.feature_names;
+        for _ in 0..ntrees {
+            let tree = RegressionTree::read(lines)?;
+            ensemble.push(tree);
+        }
+
+        Ok(ensemble)
     }
 }
 
@@ -276,6 +685,10 @@ impl ::train::Evaluate for Ensemble {
 
         result
     }
+
+    fn feature_names(&self) -> Option<&FeatureNames> {
+        Some(&self.feature_names)
+    }
 }
 
 impl std::ops::Deref for Ensemble {
@@ -335,4 +748,203 @@ mod test {
             // println!("-----------------------------------");
         }
     }
+
+    #[test]
+    fn test_tree_limits() {
+        // (label, qid, feature_values)
+        let data = vec![
+            (3.0, 1, vec![3.0, 0.0]),
+            (2.0, 1, vec![2.0, 1.0]),
+            (1.0, 1, vec![1.0, 0.0]),
+            (0.0, 1, vec![0.0, 1.0]),
+        ];
+        let dataset: DataSet = data.into_iter().collect();
+        let mut training = TrainSet::new(&dataset, 3);
+        training.update_pointwise(|label, _score| (label, 1.0));
+
+        let mut tree = RegressionTree::new(1.0, 10, 1);
+        tree.set_max_depth(Some(1));
+        tree.fit(&training);
+        assert_eq!(tree.nodes.len(), 3);
+
+        // Splitting [3, 2, 1, 0] into [1, 0] and [3, 2] gains
+        // 0.5 ^ 2 * 4 = 4.
+        let mut tree = RegressionTree::new(1.0, 10, 1);
+        tree.set_min_split_gain(4.5);
+        tree.fit(&training);
+        assert_eq!(tree.nodes.len(), 1);
+
+        // The output is (6 - 2) / (4 + 4).
+        let mut tree = RegressionTree::new(1.0, 10, 1);
+        tree.set_min_leaf_weight(3.0);
+        tree.set_regularization(2.0, 4.0);
+        let output = tree.fit(&training);
+        assert_eq!(output, vec![0.5; 4]);
+    }
+
+    #[test]
+    fn test_tree_sample() {
+        // (label, qid, feature_values)
+        let data = vec![
+            (3.0, 1, vec![3.0]),
+            (2.0, 1, vec![2.0]),
+            (1.0, 1, vec![1.0]),
+            (10.0, 2, vec![3.0]),
+            (20.0, 2, vec![2.0]),
+            (30.0, 2, vec![1.0]),
+        ];
+        let dataset: DataSet = data.into_iter().collect();
+        let mut training = TrainSet::new(&dataset, 3);
+        let fit = |training: &TrainSet| {
+            // Only the instances of query 1.
+            let sample = TrainSample::new(training, vec![0, 1, 2]);
+            let mut rng = XorShiftRng::new_unseeded();
+            let mut tree = RegressionTree::new(1.0, 10, 1);
+            tree.fit_sample(training, sample, &[1], &mut rng)
+        };
+
+        training.update_pointwise(|label, _score| (label, 1.0));
+        let output = fit(&training);
+        assert_eq!(output, vec![3.0, 2.0, 1.0, 0.0, 0.0, 0.0]);
+
+        // The instances out of the sample make no difference.
+        training.update_pointwise(|label, _score| if label < 10.0 {
+            (label, 1.0)
+        } else {
+            (-label, 100.0)
+        });
+        assert_eq!(fit(&training), output);
+    }
+
+    #[test]
+    fn test_tree_feature_fraction() {
+        use rand::SeedableRng;
+
+        // (label, qid, feature_values), each feature splits the labels.
+        let data = vec![
+            (3.0, 1, vec![3.0, 1.0, 1.0]),
+            (2.0, 1, vec![2.0, 1.0, 0.0]),
+            (1.0, 1, vec![1.0, 0.0, 1.0]),
+            (0.0, 1, vec![0.0, 0.0, 0.0]),
+        ];
+        let dataset: DataSet = data.into_iter().collect();
+        let mut training = TrainSet::new(&dataset, 4);
+        training.update_pointwise(|label, _score| (label, 1.0));
+
+        let fids = [1, 2, 3];
+        let mut root_fids = Vec::new();
+        for seed in 1..10 {
+            let rng = XorShiftRng::from_seed([seed, 1, 2, 3]);
+            // The root considers the first feature sampled by the rng.
+            let candidates =
+                rand::seq::sample_slice(&mut rng.clone(), &fids, 1);
+
+            let fit = |mut rng: XorShiftRng| {
+                let mut tree = RegressionTree::new(1.0, 10, 1);
+                tree.set_feature_fraction(0.4);
+                let sample = TrainSample::from(&training);
+                tree.fit_sample(&training, sample, &fids, &mut rng);
+                tree
+            };
+            let tree = fit(rng.clone());
+            assert_eq!(tree.nodes[0].fid, Some(candidates[0]));
+            root_fids.push(candidates[0]);
+
+            // The seed reproduces the tree.
+            assert_eq!(
+                format!("{:?}", tree.nodes),
+                format!("{:?}", fit(rng).nodes)
+            );
+        }
+        root_fids.sort();
+        root_fids.dedup();
+        assert!(root_fids.len() > 1);
+
+        // Only the given features are split on.
+        let mut tree = RegressionTree::new(1.0, 10, 1);
+        let sample = TrainSample::from(&training);
+        let mut rng = XorShiftRng::new_unseeded();
+        tree.fit_sample(&training, sample, &[2, 3], &mut rng);
+        assert!(tree.nodes.len() > 1);
+        assert!(tree.nodes.iter().all(|node| node.fid != Some(1)));
+    }
+
+    #[test]
+    fn test_tree_missing_values() {
+        use train::Evaluate;
+        let nan = std::f64::NAN;
+
+        // (label, qid, feature_values), the missing values are of
+        // high labels.
+        let data = vec![
+            (3.0, 1, vec![nan]),
+            (3.0, 1, vec![nan]),
+            (2.0, 1, vec![2.0]),
+            (0.0, 1, vec![1.0]),
+        ];
+        let dataset: DataSet = data.into_iter().collect();
+        let mut training = TrainSet::new(&dataset, 3);
+        training.update_pointwise(|label, _score| (label, 1.0));
+
+        let mut tree = RegressionTree::new(1.0, 2, 1);
+        tree.fit(&training);
+        assert_eq!(tree.nodes[0].threshold, Some(1.0));
+        assert!(!tree.nodes[0].missing_left);
+
+        let missing = Instance::sparse(0.0, 1, vec![]);
+        assert_eq!(tree.evaluate(&missing), 8.0 / 3.0);
+
+        // Models without the missing direction take missing values
+        // as 0.0.
+        let model = "tree 1 3\nsplit 1 1 1 2\nleaf 0\nleaf 1\n";
+        let mut lines = model::read_lines(model.as_bytes()).unwrap();
+        let tree = RegressionTree::read(&mut lines).unwrap();
+        assert!(tree.nodes[0].missing_left);
+        assert_eq!(tree.evaluate(&missing), 0.0);
+    }
+
+    #[test]
+    fn test_tree_read_errors() {
+        let read = |model: &str| {
+            let mut lines = model::read_lines(model.as_bytes()).unwrap();
+            RegressionTree::read(&mut lines)
+        };
+        assert!(read("tree 1 3\nsplit 1 1 1 2\nleaf 0\nleaf 1\n").is_ok());
+
+        // No nodes, out of range, a self loop and a cycle.
+        for &(model, line_number, token) in &[
+            ("tree 1 0\n", 1, "0"),
+            ("tree 1 3\nsplit 1 1 1 3\nleaf 0\nleaf 1\n", 2, "3"),
+            ("tree 1 3\nsplit 1 1 0 2\nleaf 0\nleaf 1\n", 2, "0"),
+            ("tree 1 3\nsplit 1 1 1 2\nsplit 1 1 2 0\nleaf 1\n", 3, "0"),
+        ] {
+            match read(model) {
+                Err(Error::Parse { line, token: t, .. }) => {
+                    assert_eq!(line, Some(line_number));
+                    assert_eq!(t, Some(token.to_string()));
+                }
+                _ => panic!("Expected a parse error"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_ensemble_feature_names() {
+        let mut feature_names = FeatureNames::new();
+        feature_names.insert(1, "bm25".to_string());
+        feature_names.insert(3, "page rank".to_string());
+
+        let model = "tree 1 3\nsplit 1 1 1 2 left\nleaf 0\nleaf 1\n";
+        let mut lines = model::read_lines(model.as_bytes()).unwrap();
+        let mut ensemble = Ensemble::new();
+        ensemble.set_nfeatures(3);
+        ensemble.set_feature_names(feature_names.clone());
+        ensemble.push(RegressionTree::read(&mut lines).unwrap());
+
+        let mut buf = Vec::new();
+        ensemble.save(&mut buf).unwrap();
+        let loaded = Ensemble::load(buf.as_slice()).unwrap();
+        assert_eq!(loaded.feature_names(), &feature_names);
+        assert_eq!(loaded.len(), 1);
+    }
 }
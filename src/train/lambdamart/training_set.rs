@@ -4,10 +4,116 @@ use util::{Id, Value};
 use std;
 use std::cmp::Ordering::*;
 use train::dataset::*;
+use format::feature_names::FeatureNames;
 use std::collections::{BinaryHeap, HashMap};
 use std::cmp::Ordering;
 use std::sync::{Arc, Mutex};
 
+/// The bin indices of the values of a feature, pre-computed for all
+/// the instances. The indices are stored in the smallest integer type
+/// that holds the count of bins, to keep the columns compact.
+///
+/// A feature that is missing for most of the instances only stores
+/// the bins of its given values, ordered by the instance index.
+#[derive(Debug)]
+enum BinIndices {
+    Byte(Vec<u8>),
+    Short(Vec<u16>),
+    Int(Vec<u32>),
+    Sparse {
+        len: usize,
+        missing_bin: usize,
+        indices: Vec<u32>,
+        bins: Box<BinIndices>,
+    },
+}
+
+impl BinIndices {
+    fn new(indices: Vec<usize>, nbins: usize) -> BinIndices {
+        if nbins <= std::u8::MAX as usize + 1 {
+            BinIndices::Byte(indices.into_iter().map(|i| i as u8).collect())
+        } else if nbins <= std::u16::MAX as usize + 1 {
+            BinIndices::Short(indices.into_iter().map(|i| i as u16).collect())
+        } else {
+            BinIndices::Int(indices.into_iter().map(|i| i as u32).collect())
+        }
+    }
+
+    /// Creates the bin indices of `len` instances, where the
+    /// instances not in `entries` fall into `missing_bin`. `entries`
+    /// are (instance index, bin index) sorted by the instance index.
+    fn sparse(
+        len: usize,
+        missing_bin: usize,
+        entries: Vec<(usize, usize)>,
+        nbins: usize,
+    ) -> BinIndices {
+        // Each stored entry takes at least 5 bytes, and a dense
+        // column takes 1 byte per instance.
+        if entries.len() * 8 >= len {
+            let mut map = vec![missing_bin; len];
+            for (index, bin) in entries {
+                map[index] = bin;
+            }
+            return BinIndices::new(map, nbins);
+        }
+
+        let indices = entries.iter().map(|&(index, _)| index as u32).collect();
+        let bins = entries.into_iter().map(|(_, bin)| bin).collect();
+        BinIndices::Sparse {
+            len: len,
+            missing_bin: missing_bin,
+            indices: indices,
+            bins: Box::new(BinIndices::new(bins, nbins)),
+        }
+    }
+
+    /// Returns the bin index of the instance at `index`.
+    #[inline]
+    fn get(&self, index: usize) -> usize {
+        match *self {
+            BinIndices::Byte(ref indices) => indices[index] as usize,
+            BinIndices::Short(ref indices) => indices[index] as usize,
+            BinIndices::Int(ref indices) => indices[index] as usize,
+            BinIndices::Sparse {
+                len,
+                missing_bin,
+                ref indices,
+                ref bins,
+            } => {
+                debug_assert!(index < len);
+                match indices.binary_search(&(index as u32)) {
+                    Ok(pos) => bins.get(pos),
+                    Err(_) => missing_bin,
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        match *self {
+            BinIndices::Byte(ref indices) => indices.len(),
+            BinIndices::Short(ref indices) => indices.len(),
+            BinIndices::Int(ref indices) => indices.len(),
+            BinIndices::Sparse { len, .. } => len,
+        }
+    }
+
+    #[cfg(test)]
+    fn is_sparse(&self) -> bool {
+        match *self {
+            BinIndices::Sparse { .. } => true,
+            _ => false,
+        }
+    }
+
+    #[cfg(test)]
+    fn to_vec(&self) -> Vec<usize> {
+        (0..self.len()).map(|index| self.get(index)).collect()
+    }
+}
+
 /// A Mapping from the index of a Instance in the DataSet into a
 /// threshold interval.
 struct ThresholdMap {
@@ -15,95 +121,191 @@ struct ThresholdMap {
     /// value falls into the threshold, use `if value <= threshold`.
     thresholds: Vec<Value>,
 
-    /// The index of the Vec is the index of the instances in the
-    /// DataSet, which also means `map.len() == instances.len()`.
+    /// The index is the index of the instances in the DataSet, which
+    /// also means `map.len() == instances.len()`.
     ///
-    /// The elements are the indices into the thresholds Vec.
+    /// The elements are the indices into the thresholds Vec, or
+    /// `thresholds.len()` for the missing values.
     ///
     /// For example, if we have 100,000 instances, and 256 thresholds,
     /// then
-    /// ```
+    ///
+    /// ```text
     /// assert_eq!(map.len(), 100,000);
-    /// assert!(map.iter().all(|&i| i <= 256));
+    /// assert!((0..map.len()).all(|i| map.get(i) <= 257));
     /// ```
-    map: Vec<usize>,
+    map: BinIndices,
+}
+
+/// How the thresholds are generated when a feature has more distinct
+/// values than the thresholds count.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binning {
+    /// Thresholds evenly spaced between the min and max values.
+    Uniform,
+    /// Thresholds at the quantiles of the values, so that each bin
+    /// holds roughly the same number of instances.
+    Quantile,
+}
+
+impl Binning {
+    pub fn new(name: &str) -> Option<Binning> {
+        match name {
+            "uniform" => Some(Binning::Uniform),
+            "quantile" => Some(Binning::Quantile),
+            _ => None,
+        }
+    }
+
+    /// Name of the binning. For display.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Binning::Uniform => "uniform",
+            Binning::Quantile => "quantile",
+        }
+    }
 }
 
 impl ThresholdMap {
     /// Generate thresholds according to the given values and max
-    /// bins. If the count of values exceeds max bins, thresholds are
-    /// generated by averaging the difference of max and min of the
-    /// values by max bins.
+    /// bins. `counted_values` are the distinct values in ascending
+    /// order, each with the count of instances having it. If the
+    /// count of values exceeds max bins, thresholds are generated by
+    /// averaging the difference of max and min of the values by max
+    /// bins, or at the quantiles of the values.
     fn thresholds(
-        sorted_values: Vec<Value>,
+        counted_values: &[(Value, usize)],
         thresholds_count: usize,
+        binning: Binning,
     ) -> Vec<Value> {
-        let mut thresholds = sorted_values;
-
-        thresholds.dedup();
+        let mut thresholds: Vec<Value> =
+            counted_values.iter().map(|&(value, _)| value).collect();
 
         // If too many values, generate at most thresholds_count thresholds.
         if thresholds.len() > thresholds_count {
-            let max = *thresholds.last().unwrap();
-            let min = *thresholds.first().unwrap();
-            let step = (max - min) / thresholds_count as Value;
-            thresholds = (0..thresholds_count)
-                .map(|n| min + n as Value * step)
-                .collect();
+            match binning {
+                Binning::Uniform => {
+                    let max = *thresholds.last().unwrap();
+                    let min = *thresholds.first().unwrap();
+                    let step = (max - min) / thresholds_count as Value;
+                    thresholds = (0..thresholds_count)
+                        .map(|n| min + n as Value * step)
+                        .collect();
+                }
+                Binning::Quantile => {
+                    // The last value of each of the equal-frequency
+                    // bins. A value repeated across bins only makes
+                    // one threshold.
+                    let len: usize =
+                        counted_values.iter().map(|&(_, count)| count).sum();
+                    let mut iter = counted_values.iter();
+                    let mut seen = 0;
+                    let mut current = 0.0;
+                    thresholds = (1..thresholds_count + 1)
+                        .map(|n| {
+                            let pos = n * len / thresholds_count - 1;
+                            while seen <= pos {
+                                let &(value, count) = iter.next().unwrap();
+                                seen += count;
+                                current = value;
+                            }
+                            current
+                        })
+                        .collect();
+                    thresholds.dedup();
+                }
+            }
         }
         thresholds.push(std::f64::MAX);
         thresholds
     }
 
-    /// Create a map according to the given values and max bins.
-    pub fn new(values: Vec<Value>, thresholds_count: usize) -> ThresholdMap {
-        let nvalues = values.len();
-
-        let mut indexed_values: Vec<(usize, Value)> =
-            values.iter().cloned().enumerate().collect();
-        indexed_values.sort_by(|&(_, a), &(_, b)| {
-            a.partial_cmp(&b).unwrap_or(Less)
-        });
+    /// Create a map according to the given values and max bins. NaN
+    /// values are missing.
+    pub fn new(
+        values: Vec<Value>,
+        thresholds_count: usize,
+        binning: Binning,
+    ) -> ThresholdMap {
+        let len = values.len();
+        let entries = values
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, value)| !value.is_nan())
+            .collect();
+        ThresholdMap::sparse(len, entries, thresholds_count, binning)
+    }
 
-        let sorted_values = indexed_values
-            .iter()
-            .map(|&(_, value)| value)
-            .collect::<Vec<Value>>();
-        let thresholds =
-            ThresholdMap::thresholds(sorted_values, thresholds_count);
-        let mut map: Vec<usize> = Vec::new();
-        map.resize(nvalues, 0);
-
-        let mut value_pos = 0;
-        for (threshold_index, &threshold) in thresholds.iter().enumerate() {
-            for &(value_index, value) in indexed_values[value_pos..].iter() {
-                if value > threshold {
-                    break;
+    /// Create a map of `len` instances according to the given values
+    /// of a feature and max bins. `entries` are (instance index,
+    /// value); the instances not in `entries` are missing.
+    pub fn sparse(
+        len: usize,
+        mut entries: Vec<(usize, Value)>,
+        thresholds_count: usize,
+        binning: Binning,
+    ) -> ThresholdMap {
+        entries.sort_by(|&(_, a), &(_, b)| a.partial_cmp(&b).unwrap_or(Less));
+
+        // The distinct values and their counts.
+        let mut counted_values: Vec<(Value, usize)> = Vec::new();
+        for &(_, value) in entries.iter() {
+            match counted_values.last_mut() {
+                Some(&mut (last, ref mut count)) if last == value => {
+                    *count += 1;
+                    continue;
                 }
-                map[value_index] = threshold_index;
-                value_pos += 1;
+                _ => {}
             }
+            counted_values.push((value, 1));
         }
+
+        let thresholds = ThresholdMap::thresholds(
+            &counted_values,
+            thresholds_count,
+            binning,
+        );
+
+        // The first threshold that is not less than the value.
+        let bin = |value: Value| match thresholds.binary_search_by(
+            |threshold| threshold.partial_cmp(&value).unwrap_or(Less),
+        ) {
+            Ok(index) | Err(index) => index,
+        };
+
+        let mut entries: Vec<(usize, usize)> = entries
+            .into_iter()
+            .map(|(index, value)| (index, bin(value)))
+            .collect();
+        entries.sort_by_key(|&(index, _)| index);
+
+        // The last bin is of the missing values.
+        let nbins = thresholds.len() + 1;
         ThresholdMap {
+            map: BinIndices::sparse(len, nbins - 1, entries, nbins),
             thresholds: thresholds,
-            map: map,
         }
     }
 
-    /// Generate a histogram for a series of values.
+    /// Generate a histogram of the instances at `indices`, which are
+    /// in ascending order and may repeat. The labels and weights are
+    /// indexed by instance, and `totals` are the count of the
+    /// instances and the sums of their labels and weights.
     ///
-    /// The input is an iterator over (instance id, feature value,
-    /// label value).
+    /// A sparse map walks its given values along the indices, and the
+    /// other instances make up the missing bin, which is the rest of
+    /// the totals.
     ///
     /// There are two cases when we need to regenerate the
     /// histogram. First, after each iteration of learning, the label
-    /// values are different. But this is a situation that we can
-    /// update the histogram instead of constructing from
-    /// scratch. Second, after a tree node is splited, each sub-node
-    /// contains different part of data.
+    /// values are different. Second, after a tree node is splited,
+    /// each sub-node contains different part of data. In the second
+    /// case, only one of the sub-nodes needs generating, see
+    /// `Histogram::subtract`.
     ///
     /// # Examples
     ///
+    /// ```text
     /// let data = vec![
     ///     // target value, feature values
     ///     (3.0, 5.0),
@@ -117,33 +319,54 @@ impl ThresholdMap {
     ///     (0.0, 6.0),
     /// ];
     ///
-    /// let map = ThresholdMap::new(data.iter().map(|&(_, value)| value), 3);
-    /// let histogram = map.histogram(data.iter().map(|&(target, _)| target));
-    ///
-    /// assert_eq!(histogram.variance(), 15.555555555555557);
-    pub fn histogram<I: Iterator<Item = (Id, Value)>>(
+    /// let values = data.iter().map(|&(_, value)| value).collect();
+    /// let map = ThresholdMap::new(values, 3, Binning::Uniform);
+    /// let indices: Vec<usize> = (0..data.len()).collect();
+    /// let labels: Vec<f64> = data.iter().map(|&(target, _)| target).collect();
+    /// let weights = vec![1.0; data.len()];
+    /// let totals = (data.len(), labels.iter().sum(), data.len() as f64);
+    /// let histogram = map.histogram(&indices, &labels, &weights, totals);
+    /// ```
+    pub fn histogram(
         &self,
-        iter: I,
+        indices: &[Id],
+        labels: &[Value],
+        weights: &[Value],
+        totals: (usize, Value, Value),
     ) -> Histogram {
-        // (threshold value, count, sum, squared_sum)
-        let mut hist: Vec<(Value, usize, Value)> = self.thresholds
-            .iter()
-            .map(|&threshold| (threshold, 0, 0.0))
-            .collect();
-
-        for (id, label) in iter {
-            let threshold_index = self.map[id];
-
-            hist[threshold_index].1 += 1;
-            hist[threshold_index].2 += label;
-        }
-
-        for i in 1..hist.len() {
-            hist[i].1 += hist[i - 1].1;
-            hist[i].2 += hist[i - 1].2;
+        let mut histogram = Histogram::new(self.thresholds.len());
+        match self.map {
+            BinIndices::Sparse {
+                missing_bin,
+                indices: ref given,
+                ref bins,
+                ..
+            } => {
+                let (mut count, mut sum, mut weight) = totals;
+                let mut pos = 0;
+                for (entry, &id) in given.iter().enumerate() {
+                    let id = id as usize;
+                    while pos < indices.len() && indices[pos] < id {
+                        pos += 1;
+                    }
+                    while pos < indices.len() && indices[pos] == id {
+                        histogram.add(bins.get(entry), labels[id], weights[id]);
+                        count -= 1;
+                        sum -= labels[id];
+                        weight -= weights[id];
+                        pos += 1;
+                    }
+                    if pos == indices.len() {
+                        break;
+                    }
+                }
+                histogram.add_values(missing_bin, count, sum, weight);
+            }
+            _ => for &id in indices.iter() {
+                histogram.add(self.map.get(id), labels[id], weights[id]);
+            },
         }
-        let feature_histogram = hist.into_iter().collect();
-        feature_histogram
+        histogram
     }
 }
 
@@ -171,7 +394,7 @@ impl std::fmt::Debug for ThresholdMap {
 // Output: Vec of (higher index, lower index, lambda, weight)
 fn compute_lambda_weight(
     rank_list: &mut Vec<(usize, f64, f64)>,
-    metric: &Box<Measure>,
+    metric: &Box<dyn Measure>,
 ) -> Vec<(usize, usize, f64, f64)> {
     let mut query_values: Vec<(usize, usize, f64, f64)> = Vec::new();
     // Rank by the scores of our model.
@@ -218,9 +441,9 @@ pub struct TrainSet<'d> {
     // Fitting result of the model. We need to update the result at
     // each leaf node.
     model_scores: Vec<Value>,
-    // Gradients, or lambdas.
+    // Negative gradients of the objective, or lambdas.
     lambdas: Vec<Value>,
-    // Newton step weights
+    // Newton step weights, the hessians of the objective.
     weights: Vec<Value>,
 
     // Do not make assumptions on feature id values, so use a hash
@@ -230,20 +453,45 @@ pub struct TrainSet<'d> {
 
 impl<'d> TrainSet<'d> {
     /// Creates a new TrainSet from DataSet. Thresholds will be
-    /// generated.
+    /// generated with uniform binning.
     pub fn new(
         dataset: &'d DataSet,
         thresholds_count: usize,
+    ) -> TrainSet<'d> {
+        TrainSet::with_binning(dataset, thresholds_count, Binning::Uniform)
+    }
+
+    /// Creates a new TrainSet from DataSet. Thresholds will be
+    /// generated with the given binning.
+    pub fn with_binning(
+        dataset: &'d DataSet,
+        thresholds_count: usize,
+        binning: Binning,
     ) -> TrainSet<'d> {
         fn generate_thresholds(
             dataset: &DataSet,
             thresholds_count: usize,
+            binning: Binning,
         ) -> HashMap<usize, ThresholdMap> {
+            // Collect the given values of each feature in a single
+            // pass, so that the work is proportional to the given
+            // values rather than features times instances.
+            let mut columns: Vec<Vec<(usize, Value)>> =
+                dataset.fid_iter().map(|_| Vec::new()).collect();
+            for (index, instance) in dataset.iter().enumerate() {
+                for (fid, value) in instance.value_iter() {
+                    columns[fid - 1].push((index, value));
+                }
+            }
+
             let mut threshold_maps = HashMap::new();
-            for fid in dataset.fid_iter() {
-                let values: Vec<Value> =
-                    dataset.feature_value_iter(fid).collect();
-                let map = ThresholdMap::new(values, thresholds_count);
+            for (fid, column) in dataset.fid_iter().zip(columns.into_iter()) {
+                let map = ThresholdMap::sparse(
+                    dataset.len(),
+                    column,
+                    thresholds_count,
+                    binning,
+                );
 
                 threshold_maps.insert(fid, map);
             }
@@ -261,7 +509,11 @@ impl<'d> TrainSet<'d> {
             model_scores: model_scores,
             lambdas: lambdas,
             weights: weights,
-            threshold_maps: generate_thresholds(dataset, thresholds_count),
+            threshold_maps: generate_thresholds(
+                dataset,
+                thresholds_count,
+                binning,
+            ),
         }
     }
 
@@ -286,6 +538,11 @@ impl<'d> TrainSet<'d> {
         (self.lambdas[index], self.weights[index])
     }
 
+    /// Returns the names of the features.
+    pub fn feature_names(&self) -> &FeatureNames {
+        self.dataset.feature_names()
+    }
+
     /// Returns an iterator over the feature ids in the training set.
     pub fn fid_iter(&self) -> impl Iterator<Item = Id> {
         self.dataset.fid_iter()
@@ -320,23 +577,42 @@ impl<'d> TrainSet<'d> {
         }
     }
 
-    /// Generate histogram for the specified instances. The input
-    /// iterator specifies the indices of instance that we want to
-    /// generate histogram on. For a training data set, the histogram
-    /// is used to make statistics of the lambda values, which is
-    /// actually the target value that we aims to fit to in the
-    /// current iteration of learning.
-    fn feature_histogram<I: Iterator<Item = Id>>(
+    /// Generate histogram for the specified instances, in ascending
+    /// order, see `ThresholdMap::histogram`. For a training data set,
+    /// the histogram is used to make statistics of the lambda values,
+    /// which is actually the target value that we aims to fit to in
+    /// the current iteration of learning.
+    fn feature_histogram(
         &self,
         fid: Id,
-        iter: I,
+        indices: &[Id],
+        totals: (usize, Value, Value),
     ) -> Histogram {
-        // Get the map by feature id.
-        let iter = iter.map(|id| (id, self.lambdas[id]));
-
         // Get the map by feature id.
         let threshold_map = &self.threshold_maps[&fid];
-        threshold_map.histogram(iter)
+        threshold_map.histogram(indices, &self.lambdas, &self.weights, totals)
+    }
+
+    /// Returns the thresholds of the feature.
+    fn thresholds(&self, fid: Id) -> &[Value] {
+        &self.threshold_maps[&fid].thresholds
+    }
+
+    /// Updates the lambda and weight of each instance from its label
+    /// and model score, for objectives that treat the instances
+    /// independently.
+    pub fn update_pointwise<F>(&mut self, f: F)
+    where
+        F: Fn(Value, Value) -> (Value, Value),
+    {
+        for (index, (l, w)) in
+            self.lambdas.iter_mut().zip(self.weights.iter_mut()).enumerate()
+        {
+            let (lambda, weight) =
+                f(self.dataset[index].label(), self.model_scores[index]);
+            *l = lambda;
+            *w = weight;
+        }
     }
 
     /// Updates the lambda and weight for each instance.
@@ -348,7 +624,10 @@ impl<'d> TrainSet<'d> {
     /// another
     ///
     /// 3. Update lambda and weight according to the formulas
-    pub fn update_lambdas_weights<'a, 'b>(&'a mut self, metric: &Box<Measure>) {
+    pub fn update_lambdas_weights<'a, 'b>(
+        &'a mut self,
+        metric: &Box<dyn Measure>,
+    ) {
         for (l, w) in self.lambdas.iter_mut().zip(self.weights.iter_mut()) {
             *l = 0.0;
             *w = 0.0;
@@ -389,7 +668,7 @@ impl<'d> TrainSet<'d> {
         }
     }
 
-    pub fn measure(&self, metric: &Box<Measure>) -> f64 {
+    pub fn measure(&self, metric: &Box<dyn Measure>) -> f64 {
         let mut score = 0.0;
         let mut count = 0;
         for (_qid, mut indices) in self.dataset.query_iter() {
@@ -419,6 +698,8 @@ impl<'d> TrainSet<'d> {
 struct SplitPos {
     pub fid: usize,
     pub threshold: f64,
+    // Whether the missing values go to the left child.
+    pub missing_left: bool,
     pub s: f64,
 }
 
@@ -445,9 +726,51 @@ impl Ord for SplitPos {
 pub struct SampleSplit<'a> {
     pub fid: usize,
     pub threshold: f64,
+    pub missing_left: bool,
     pub s: f64,
     pub left: TrainSample<'a>,
     pub right: TrainSample<'a>,
+    // The histograms of the split sample, from which those of the
+    // children are derived.
+    histograms: HashMap<Id, Histogram>,
+}
+
+impl<'a> SampleSplit<'a> {
+    /// Derives the histograms of the children that may be split
+    /// further from the histograms of the split sample, which are
+    /// released. The smaller child generates its histograms from its
+    /// instances, and the larger child gets them by subtraction. A
+    /// child without histograms generates them if it is split anyway.
+    pub fn derive_histograms(&mut self, split_left: bool, split_right: bool) {
+        let histograms =
+            std::mem::replace(&mut self.histograms, HashMap::new());
+        let (smaller, larger, split_smaller, split_larger) =
+            if self.left.len() <= self.right.len() {
+                (&mut self.left, &mut self.right, split_left, split_right)
+            } else {
+                (&mut self.right, &mut self.left, split_right, split_left)
+            };
+        if !split_smaller && !split_larger {
+            return;
+        }
+
+        let fids: Vec<Id> = histograms.keys().cloned().collect();
+        smaller.histograms =
+            smaller.feature_histograms(&fids).into_iter().collect();
+        if split_larger {
+            larger.histograms = histograms
+                .into_iter()
+                .map(|(fid, histogram)| {
+                    let subtracted =
+                        histogram.subtract(&smaller.histograms[&fid]);
+                    (fid, subtracted)
+                })
+                .collect();
+        }
+        if !split_smaller {
+            smaller.histograms.clear();
+        }
+    }
 }
 
 /// A collection type containing part of a data set.
@@ -457,9 +780,30 @@ pub struct TrainSample<'a> {
 
     /// Indices into training
     indices: Vec<usize>,
+
+    /// Histograms of the features computed so far. They are passed
+    /// down to the children when splitting, so that only one child
+    /// generates its histograms from the instances.
+    histograms: HashMap<Id, Histogram>,
 }
 
 impl<'a> TrainSample<'a> {
+    /// Creates a sample of the instances at the given indices into the
+    /// training set. An index may appear more than once. The indices
+    /// are kept in ascending order, along which the sparse features
+    /// are walked.
+    pub fn new(
+        training: &'a TrainSet<'a>,
+        mut indices: Vec<usize>,
+    ) -> TrainSample<'a> {
+        indices.sort();
+        TrainSample {
+            training: training,
+            indices: indices,
+            histograms: HashMap::new(),
+        }
+    }
+
     /// Returns the number of instances in the data set sample, also
     /// referred to as its 'length'.
     pub fn len(&self) -> usize {
@@ -501,8 +845,10 @@ impl<'a> TrainSample<'a> {
         self.iter().map(move |(_index, _label, ins)| ins.value(fid))
     }
 
-    /// Returns the Newton step value.
-    pub fn newton_output(&self) -> f64 {
+    /// Returns the Newton step value, sum(lambdas) / sum(weights),
+    /// regularized by the L1 penalty `l1` on the sum of the lambdas
+    /// and the L2 penalty `l2` added to the sum of the weights.
+    pub fn newton_output(&self, l1: f64, l2: f64) -> f64 {
         let (lambda_sum, weight_sum) = self.indices.iter().fold(
             (0.0, 0.0),
             |(lambda_sum,
@@ -513,6 +859,15 @@ impl<'a> TrainSample<'a> {
             },
         );
 
+        let lambda_sum = if lambda_sum > l1 {
+            lambda_sum - l1
+        } else if lambda_sum < -l1 {
+            lambda_sum + l1
+        } else {
+            0.0
+        };
+        let weight_sum = weight_sum + l2;
+
         if weight_sum == 0.0 {
             0.0
         } else {
@@ -520,20 +875,63 @@ impl<'a> TrainSample<'a> {
         }
     }
 
+    /// Returns the s value of the sample without splitting, that is
+    /// sum(lambdas) ^ 2 / count. The gain of a split is its s value
+    /// minus this value.
+    pub fn s_value(&self) -> f64 {
+        let sum: f64 = self.indices
+            .iter()
+            .map(|&index| self.training.lambda(index))
+            .sum();
+        sum * sum / self.indices.len() as f64
+    }
+
     pub fn update_output(&self, leaf_output: &mut Vec<Value>, delta: Value) {
         for &i in self.indices.iter() {
             leaf_output[i] = delta;
         }
     }
 
-    /// Returns a histogram of the feature of the data set sample.
-    fn feature_histogram(&self, fid: Id) -> Histogram {
-        self.training.feature_histogram(
-            fid,
-            self.indices.iter().cloned(),
+    /// Returns the count of the instances in the sample, and the sums
+    /// of their lambdas and weights.
+    fn totals(&self) -> (usize, Value, Value) {
+        self.indices.iter().fold(
+            (0, 0.0, 0.0),
+            |(count, lambda_sum, weight_sum), &index| {
+                let (lambda, weight) = self.training.get_lambda_weight(index);
+                (count + 1, lambda_sum + lambda, weight_sum + weight)
+            },
         )
     }
 
+    /// Returns a histogram of the feature of the data set sample.
+    /// `totals` are those of the sample, see `totals`.
+    fn feature_histogram(
+        &self,
+        fid: Id,
+        totals: (usize, Value, Value),
+    ) -> Histogram {
+        self.training.feature_histogram(fid, &self.indices, totals)
+    }
+
+    /// Generates the histograms of the given features in parallel.
+    fn feature_histograms(&self, fids: &[Id]) -> Vec<(Id, Histogram)> {
+        let histograms: Arc<Mutex<Vec<(Id, Histogram)>>> =
+            Arc::new(Mutex::new(Vec::with_capacity(fids.len())));
+        let totals = self.totals();
+        let mut pool = ::util::POOL.lock().unwrap();
+        pool.scoped(|scoped| for &fid in fids.iter() {
+            let histograms = histograms.clone();
+            scoped.execute(move || {
+                let histogram = self.feature_histogram(fid, totals);
+                histograms.lock().unwrap().push((fid, histogram));
+            })
+        });
+
+        let mut guard = histograms.lock().unwrap();
+        guard.drain(..).collect()
+    }
+
     /// To facilitate computing the variance. We made a little
     /// transformation.
     ///
@@ -557,33 +955,68 @@ impl<'a> TrainSample<'a> {
         variance
     }
 
-    /// Find the best split of this sample. For each feature, find the
-    /// best split point that gets the best squared error. And find
-    /// the best among all the features.
-    fn best_split(&self, min_leaf_samples: usize) -> Option<SplitPos> {
+    /// Find the best split of this sample. For each of the given
+    /// features, find the best split point that gets the best squared
+    /// error. And find the best among all these features.
+    ///
+    /// The histograms of the features that are not computed yet are
+    /// generated and kept in self.
+    fn best_split(
+        &mut self,
+        fids: &[Id],
+        min_leaf_samples: usize,
+        min_leaf_weight: f64,
+    ) -> Option<SplitPos> {
+        let missing: Vec<Id> = fids.iter()
+            .cloned()
+            .filter(|fid| !self.histograms.contains_key(fid))
+            .collect();
+        let histograms = self.feature_histograms(&missing);
+        self.histograms.extend(histograms);
+
         // (fid, threshold, s)
-        let splits: Arc<Mutex<BinaryHeap<SplitPos>>> =
-            Arc::new(Mutex::new(BinaryHeap::new()));
-        let mut pool = ::util::POOL.lock().unwrap();
-        pool.scoped(|scoped| for fid in self.fid_iter() {
-            let splits = splits.clone();
-            scoped.execute(move || {
-                let feature_histogram = self.feature_histogram(fid);
-                let split = feature_histogram.best_split(min_leaf_samples);
-                if let Some((threshold, s)) = split {
-                    splits.lock().unwrap().push(SplitPos { fid, threshold, s })
-                }
-            })
-        });
+        let mut splits: BinaryHeap<SplitPos> = BinaryHeap::new();
+        for &fid in fids.iter() {
+            let split = self.histograms[&fid].best_split(
+                self.training.thresholds(fid),
+                min_leaf_samples,
+                min_leaf_weight,
+            );
+            if let Some((threshold, missing_left, s)) = split {
+                splits.push(SplitPos {
+                    fid,
+                    threshold,
+                    missing_left,
+                    s,
+                })
+            }
+        }
 
-        let mut guard = splits.lock().unwrap();
-        guard.pop()
+        splits.pop()
     }
 
     /// Split self. Returns (split feature, threshold, s value, left
     /// child, right child). For each split, if its variance is zero,
     /// it's non-splitable.
-    pub fn split(&self, min_leaf_samples: usize) -> Option<SampleSplit<'a>> {
+    pub fn split(
+        &mut self,
+        min_leaf_samples: usize,
+    ) -> Option<SampleSplit<'a>> {
+        let fids: Vec<Id> = self.fid_iter().collect();
+        self.split_on(&fids, min_leaf_samples, 0.0)
+    }
+
+    /// Split self, only considering the given features. The sum of
+    /// the weights on each side should be at least `min_leaf_weight`.
+    ///
+    /// The histograms of self are moved to the split, see
+    /// `SampleSplit::derive_histograms`.
+    pub fn split_on(
+        &mut self,
+        fids: &[Id],
+        min_leaf_samples: usize,
+        min_leaf_weight: f64,
+    ) -> Option<SampleSplit<'a>> {
         assert!(min_leaf_samples > 0);
         if self.indices.len() < min_leaf_samples ||
             self.variance().abs() <= 0.000001
@@ -592,33 +1025,39 @@ impl<'a> TrainSample<'a> {
         }
 
         // Find the split with the best s value;
-        if let Some(SplitPos { fid, threshold, s }) =
-            self.best_split(min_leaf_samples)
+        if let Some(SplitPos {
+            fid,
+            threshold,
+            missing_left,
+            s,
+        }) = self.best_split(fids, min_leaf_samples, min_leaf_weight)
         {
             let mut left_indices = Vec::new();
             let mut right_indices = Vec::new();
             for (index, _label, instance) in self.iter() {
-                if instance.value(fid) <= threshold {
+                let go_left = instance
+                    .get(fid)
+                    .map_or(missing_left, |value| value <= threshold);
+                if go_left {
                     left_indices.push(index);
                 } else {
                     right_indices.push(index);
                 }
             }
 
-            let left = TrainSample {
-                training: self.training,
-                indices: left_indices,
-            };
-            let right = TrainSample {
-                training: self.training,
-                indices: right_indices,
-            };
+            let left = TrainSample::new(self.training, left_indices);
+            let right = TrainSample::new(self.training, right_indices);
+            let histograms =
+                std::mem::replace(&mut self.histograms, HashMap::new());
+
             Some(SampleSplit {
                 fid,
                 threshold,
+                missing_left,
                 s,
                 left,
                 right,
+                histograms,
             })
         } else {
             None
@@ -633,6 +1072,7 @@ impl<'a> From<&'a TrainSet<'a>> for TrainSample<'a> {
         TrainSample {
             training: training,
             indices: indices,
+            histograms: HashMap::new(),
         }
     }
 }
@@ -695,7 +1135,7 @@ mod tests {
     fn test_threshold_map() {
         let values = vec![5.0, 7.0, 3.0, 2.0, 1.0, 8.0, 9.0, 4.0, 6.0];
 
-        let map = ThresholdMap::new(values, 3);
+        let map = ThresholdMap::new(values, 3, Binning::Uniform);
 
         assert_eq!(
             map.thresholds,
@@ -707,7 +1147,58 @@ mod tests {
             ]
         );
 
-        assert_eq!(map.map, vec![2, 3, 1, 1, 0, 3, 3, 2, 2]);
+        assert_eq!(map.map.to_vec(), vec![2, 3, 1, 1, 0, 3, 3, 2, 2]);
+    }
+
+    #[test]
+    fn test_quantile_threshold_map() {
+        let values = vec![5.0, 7.0, 3.0, 2.0, 1.0, 8.0, 9.0, 4.0, 6.0];
+        let map = ThresholdMap::new(values, 3, Binning::Quantile);
+        assert_eq!(map.thresholds, vec![3.0, 6.0, 9.0, std::f64::MAX]);
+        assert_eq!(map.map.to_vec(), vec![1, 2, 0, 0, 0, 2, 2, 1, 1]);
+
+        // Most values are zeros, as with click counts.
+        let values = vec![0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 100.0];
+        let map = ThresholdMap::new(values, 2, Binning::Quantile);
+        assert_eq!(map.thresholds, vec![0.0, 100.0, std::f64::MAX]);
+    }
+
+    #[test]
+    fn test_sparse_threshold_map() {
+        // Feature values of 40 instances, only 3 of them given.
+        let entries = vec![(13, 4.0), (2, -1.0), (7, 0.0)];
+        let map = ThresholdMap::sparse(40, entries, 256, Binning::Quantile);
+        assert_eq!(map.thresholds, vec![-1.0, 0.0, 4.0, std::f64::MAX]);
+        assert!(map.map.is_sparse());
+
+        // The missing values are in the last bin.
+        let mut expected = vec![4; 40];
+        expected[2] = 0;
+        expected[7] = 1;
+        expected[13] = 2;
+        assert_eq!(map.map.to_vec(), expected);
+
+        // The same values given densely, with NaN as missing, make
+        // the same map.
+        let values = (0..40).map(|index| match index {
+            2 => -1.0,
+            7 => 0.0,
+            13 => 4.0,
+            _ => std::f64::NAN,
+        });
+        let dense = ThresholdMap::new(values.collect(), 256, Binning::Quantile);
+        assert_eq!(dense.thresholds, map.thresholds);
+        assert_eq!(dense.map.to_vec(), expected);
+
+        // Walking the given values makes the same histogram, also for
+        // repeated instances.
+        let indices = vec![2, 2, 5, 13, 30, 39];
+        let labels: Vec<Value> = (0..40).map(|index| index as Value).collect();
+        let weights = vec![1.0; 40];
+        let totals = (6, 91.0, 6.0);
+        let sparse = map.histogram(&indices, &labels, &weights, totals);
+        let dense = dense.histogram(&indices, &labels, &weights, totals);
+        assert_eq!(format!("{:?}", sparse), format!("{:?}", dense));
     }
 
     #[test]
@@ -782,7 +1273,7 @@ mod tests {
         let mut training = TrainSet::new(&dataset, 3);
         training.update_lambdas_weights(&metric::new("NDCG", 10).unwrap());
 
-        let sample = TrainSample::from(&training);
+        let mut sample = TrainSample::from(&training);
         let split = sample.split(1).unwrap();
         assert_eq!(split.fid, 1);
         assert_eq!(split.threshold, 1.0);
@@ -812,16 +1303,59 @@ mod tests {
         let mut training = TrainSet::new(&dataset, 3);
         training.update_lambdas_weights(&metric::new("NDCG", 10).unwrap());
 
-        let sample = TrainSample::from(&training);
+        let mut sample = TrainSample::from(&training);
         assert!(sample.split(9).is_none());
         assert!(sample.split(4).is_none());
-        let split = sample.split(3).unwrap();
+        let mut split = sample.split(3).unwrap();
         assert_eq!(split.fid, 1);
         assert_eq!(split.threshold, 3.0 + 2.0 / 3.0);
 
         assert!(split.left.split(2).is_none());
     }
 
+    #[test]
+    fn test_derive_histograms() {
+        // (label, qid, feature_values)
+        let data = vec![
+            (3.0, 1, vec![5.0, 1.0]),
+            (2.0, 1, vec![7.0, 2.0]),
+            (3.0, 1, vec![3.0, 1.0]),
+            (1.0, 1, vec![2.0, 2.0]),
+            (0.0, 1, vec![1.0, 1.0]),
+            (2.0, 1, vec![8.0, 2.0]),
+        ];
+        let dataset: DataSet = data.into_iter().collect();
+        let mut training = TrainSet::new(&dataset, 3);
+        training.update_lambdas_weights(&metric::new("NDCG", 10).unwrap());
+
+        for &(split_left, split_right) in
+            &[(true, true), (true, false), (false, true), (false, false)]
+        {
+            let mut sample = TrainSample::from(&training);
+            let mut split = sample.split(1).unwrap();
+            assert!(sample.histograms.is_empty());
+            split.derive_histograms(split_left, split_right);
+            assert!(split.histograms.is_empty());
+            assert_eq!(split.left.histograms.len(), split_left as usize * 2);
+            assert_eq!(split.right.histograms.len(), split_right as usize * 2);
+
+            // The derived histograms split as those of the instances.
+            for child in [&split.left, &split.right].iter() {
+                for (&fid, histogram) in child.histograms.iter() {
+                    let thresholds = training.thresholds(fid);
+                    let expected =
+                        child.feature_histogram(fid, child.totals());
+                    let split = histogram.best_split(thresholds, 1, 0.0);
+                    let expected = expected.best_split(thresholds, 1, 0.0);
+                    assert_eq!(
+                        split.map(|(threshold, _, _)| threshold),
+                        expected.map(|(threshold, _, _)| threshold)
+                    );
+                }
+            }
+        }
+    }
+
     #[bench]
     fn bench_split(b: &mut Bencher) {
         let path = "./data/train-lite.txt";
@@ -831,7 +1365,9 @@ mod tests {
         let mut training = TrainSet::new(&dataset, 256);
         training.update_lambdas_weights(&metric::new("NDCG", 10).unwrap());
 
-        let sample = TrainSample::from(&training);
-        b.iter(|| sample.split(1).unwrap());
+        b.iter(|| {
+            let mut sample = TrainSample::from(&training);
+            sample.split(1).unwrap()
+        });
     }
 }
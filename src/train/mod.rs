@@ -1,15 +1,29 @@
 pub mod dataset;
 pub mod validate_set;
 pub mod lambdamart;
+pub mod ranknet;
+pub mod randomforest;
+pub mod best_score;
+pub mod model;
 
 use clap::{App, Arg, ArgMatches, SubCommand};
+use format::csv;
+use format::feature_names::FeatureNames;
+use metric;
 use train::dataset::Instance;
+use util::exit_on_error;
 
 pub fn main<'a>(matches: &ArgMatches<'a>) {
     match matches.subcommand_name() {
         Some("lambdamart") => lambdamart::main(
             matches.subcommand_matches("lambdamart").unwrap(),
         ),
+        Some("ranknet") => ranknet::main(
+            matches.subcommand_matches("ranknet").unwrap(),
+        ),
+        Some("randomforest") => randomforest::main(
+            matches.subcommand_matches("randomforest").unwrap(),
+        ),
         _ => (),
     }
 }
@@ -18,7 +32,9 @@ pub fn main<'a>(matches: &ArgMatches<'a>) {
 pub fn clap_command<'a, 'b>() -> App<'a, 'b> {
     let train_command = SubCommand::with_name("train")
         .about("Train an learning algorithm")
-        .subcommand(lambdamart::clap_command());
+        .subcommand(lambdamart::clap_command())
+        .subcommand(ranknet::clap_command())
+        .subcommand(randomforest::clap_command());
 
     train_command
 }
@@ -26,7 +42,7 @@ pub fn clap_command<'a, 'b>() -> App<'a, 'b> {
 /// Returns the common arguments for a learning algorithm. The display
 /// order of this type of arguments ranges from 1 to 100.
 fn common_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
-    let common_args = vec![
+    let mut common_args = vec![
         Arg::with_name("train-file")
             .short("t")
             .long("train")
@@ -55,10 +71,10 @@ fn common_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
         Arg::with_name("metric")
             .short("m")
             .long("metric")
-            .possible_values(&["NDCG", "DCG"])
+            .value_name("METRIC")
             .default_value("NDCG")
             .display_order(4)
-            .help("Metric to optimize on the training data"),
+            .help("Metric to optimize on the training data, as NAME[@k][:gain=exp|linear][:max=LABEL], where NAME is one of NDCG, DCG, MAP, ERR, RR and P, for example NDCG@10 or ERR@20:max=2"),
         Arg::with_name("metric-k")
             .short("k")
             .long("metric-k")
@@ -66,13 +82,90 @@ fn common_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
             .requires("metric")
             .default_value("10")
             .display_order(5)
-            .help("K value for metrics"),
+            .help("K value for the metrics without @k"),
+        Arg::with_name("early-stop")
+            .long("early-stop")
+            .takes_value(true)
+            .value_name("NUM")
+            .default_value("100")
+            .display_order(6)
+            .help("Stop early when no improvement is observed on validaton data in e consecutive rounds"),
+        Arg::with_name("model-file")
+            .long("model-out")
+            .takes_value(true)
+            .value_name("FILE")
+            .empty_values(false)
+            .display_order(7)
+            .help("File to save the trained model"),
+        Arg::with_name("feature-names-file")
+            .long("feature-names")
+            .takes_value(true)
+            .value_name("FILE")
+            .empty_values(false)
+            .display_order(8)
+            .help("File of feature names, with a line \"<id> <name>\" for each feature"),
     ];
+    common_args.extend(csv::clap_args(9));
+    common_args.push(regroup_queries_arg().display_order(13));
+    common_args.push(
+        Arg::with_name("stop-metric")
+            .long("stop-metric")
+            .value_name("METRIC")
+            .takes_value(true)
+            .empty_values(false)
+            .display_order(14)
+            .help("Metric deciding the best iteration and early stopping, in the form of --metric [default: the metric to optimize]"),
+    );
+    common_args.push(
+        Arg::with_name("report")
+            .long("report")
+            .value_name("METRICS")
+            .takes_value(true)
+            .multiple(true)
+            .require_delimiter(true)
+            .display_order(15)
+            .help("Comma separated metrics to report in each iteration, in the form of --metric, for example NDCG@1,NDCG@5,MAP"),
+    );
 
     common_args
 }
 
+/// Returns the argument to regroup the instances of a query that are
+/// not on consecutive lines, which is an error otherwise.
+pub fn regroup_queries_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("regroup-queries")
+        .long("regroup-queries")
+        .help("Regroup the instances of a query if they are not on consecutive lines, instead of reporting an error")
+}
+
+/// Returns the specs of the metric to optimize, the metric deciding
+/// the best iteration and the metrics to report. Exits if a spec is
+/// invalid.
+fn metric_specs<'a>(
+    matches: &'a ArgMatches<'a>,
+    metric_k: usize,
+) -> (&'a str, Option<&'a str>, Vec<&'a str>) {
+    let metric = matches.value_of("metric").unwrap();
+    let stop_metric = matches.value_of("stop-metric");
+    let report_metrics: Vec<&str> = matches
+        .values_of("report")
+        .map_or(Vec::new(), |values| values.collect());
+    let specs = Some(metric).into_iter().chain(stop_metric);
+    for spec in specs.chain(report_metrics.iter().cloned()) {
+        if let Err(e) = metric::parse(spec, metric_k) {
+            exit_on_error(e);
+        }
+    }
+    (metric, stop_metric, report_metrics)
+}
+
 /// Evaluate on an instance.
 pub trait Evaluate {
     fn evaluate(&self, instance: &Instance) -> f64;
+
+    /// Returns the names of the features of the training data, if
+    /// recorded.
+    fn feature_names(&self) -> Option<&FeatureNames> {
+        None
+    }
 }
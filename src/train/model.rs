@@ -0,0 +1,214 @@
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::iter::Peekable;
+use std::str::FromStr;
+use std::vec::IntoIter;
+use format::compression;
+use format::feature_names::FeatureNames;
+use train::Evaluate;
+use train::lambdamart::regression_tree::Ensemble;
+use train::ranknet::ranknet::Network;
+use util::{Error, ParseResult, Result};
+
+/// Version of the model file format.
+///
+/// Version 1 files have no "model" line and always contain an
+/// ensemble of regression trees. Version 2 files have no "feature"
+/// lines.
+pub const MODEL_VERSION: usize = 3;
+
+/// Lines of a model file with their line numbers, trimmed and
+/// without empty lines.
+pub type ModelLines = Peekable<IntoIter<(usize, String)>>;
+
+/// The header shared by all model files:
+///
+/// ```text
+/// rforests-model <version>
+/// model <kind>
+/// features <number of features>
+/// param <name> <value>
+/// ...
+/// feature <id> <name>
+/// ...
+/// ```
+///
+/// The body which follows the header depends on the kind of the model.
+pub struct ModelHeader {
+    pub kind: String,
+    // Number of features of the training data.
+    pub nfeatures: usize,
+    // Training parameters as (name, value) pairs. Only used for
+    // recording how the model was trained.
+    pub params: Vec<(String, String)>,
+    // Names of the features of the training data.
+    pub feature_names: FeatureNames,
+}
+
+impl ModelHeader {
+    pub fn new(
+        kind: &str,
+        nfeatures: usize,
+        params: &[(String, String)],
+    ) -> ModelHeader {
+        ModelHeader {
+            kind: kind.to_string(),
+            nfeatures: nfeatures,
+            params: params.to_vec(),
+            feature_names: FeatureNames::new(),
+        }
+    }
+
+    /// Sets the names of the features recorded in the header.
+    pub fn with_feature_names(
+        mut self,
+        feature_names: &FeatureNames,
+    ) -> ModelHeader {
+        self.feature_names = feature_names.clone();
+        self
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writeln!(writer, "rforests-model {}", MODEL_VERSION)?;
+        writeln!(writer, "model {}", self.kind)?;
+        writeln!(writer, "features {}", self.nfeatures)?;
+        for &(ref name, ref value) in self.params.iter() {
+            writeln!(writer, "param {} {}", name, value)?;
+        }
+        for (id, name) in self.feature_names.iter() {
+            writeln!(writer, "feature {} {}", id, name)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the header, leaving the body in `lines`.
+    pub fn read(lines: &mut ModelLines) -> ParseResult<ModelHeader> {
+        let (line_number, header) = next_line(lines)?;
+        let fields: Vec<&str> = header.split_whitespace().collect();
+        if fields.len() != 2 || fields[0] != "rforests-model" {
+            let e = Error::parse(&header, "Invalid model header");
+            return Err(e.at_line(line_number));
+        }
+        match fields[1].parse::<usize>() {
+            Ok(version) if version > 0 && version <= MODEL_VERSION => {}
+            _ => {
+                let e = Error::parse(fields[1], "Unsupported model version");
+                return Err(e.at_line(line_number));
+            }
+        }
+
+        let mut model_header = ModelHeader::new("ensemble", 0, &[]);
+        loop {
+            let (line_number, name, value) = match lines.peek() {
+                Some(&(line_number, ref line)) => {
+                    let mut fields = line.splitn(2, ' ');
+                    (
+                        line_number,
+                        fields.next().unwrap().to_string(),
+                        fields.next().unwrap_or("").to_string(),
+                    )
+                }
+                None => break,
+            };
+
+            match name.as_str() {
+                "model" => model_header.kind = value,
+                "features" => {
+                    model_header.nfeatures =
+                        parse_field(&value, "Invalid features count")
+                            .map_err(|e| e.at_line(line_number))?;
+                }
+                "param" => {
+                    let mut fields = value.splitn(2, ' ');
+                    let name = fields.next().unwrap().to_string();
+                    let value = fields.next().unwrap_or("").to_string();
+                    model_header.params.push((name, value));
+                }
+                "feature" => {
+                    let mut fields = value.splitn(2, ' ');
+                    let id = parse_field(fields.next().unwrap(), "Invalid id")
+                        .map_err(|e| e.at_line(line_number))?;
+                    let name = fields.next().unwrap_or("").to_string();
+                    model_header.feature_names.insert(id, name);
+                }
+                _ => break,
+            }
+            lines.next();
+        }
+
+        Ok(model_header)
+    }
+}
+
+/// Reads the lines of a model file.
+pub fn read_lines<R: Read>(reader: R) -> ParseResult<ModelLines> {
+    let lines = BufReader::new(reader)
+        .lines()
+        .collect::<::std::io::Result<Vec<String>>>()?;
+    Ok(
+        lines
+            .into_iter()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim().to_string()))
+            .filter(|&(_, ref line)| !line.is_empty())
+            .collect::<Vec<(usize, String)>>()
+            .into_iter()
+            .peekable(),
+    )
+}
+
+/// Returns the next line of a model file with its line number.
+pub fn next_line(lines: &mut ModelLines) -> ParseResult<(usize, String)> {
+    lines.next().ok_or_else(|| Error::Parse {
+        file: None,
+        line: None,
+        token: None,
+        message: "Unexpected end of model file".to_string(),
+    })
+}
+
+/// Parses a field of a line of a model file, with `message` telling
+/// what is invalid otherwise.
+pub fn parse_field<T: FromStr>(field: &str, message: &str) -> ParseResult<T> {
+    field.parse::<T>().map_err(|_| Error::parse(field, message))
+}
+
+/// Loads any kind of model saved by rforests.
+///
+/// # Examples
+///
+/// ```
+/// use rforests::train::model;
+/// use rforests::util::Error;
+///
+/// let s = "rforests-model 3
+/// model ensemble
+/// features 1
+/// trees 1
+/// tree 0.1 x";
+///
+/// match model::load(s.as_bytes()) {
+///     Err(Error::Parse { line, token, .. }) => {
+///         assert_eq!(line, Some(5));
+///         assert_eq!(token, Some("x".to_string()));
+///     }
+///     _ => panic!("Expected a parse error"),
+/// }
+/// ```
+pub fn load<R: Read>(reader: R) -> ParseResult<Box<dyn Evaluate>> {
+    let mut lines = read_lines(reader)?;
+    let header = ModelHeader::read(&mut lines)?;
+    let model: Box<dyn Evaluate> = match header.kind.as_str() {
+        "ensemble" => Box::new(Ensemble::read(header, &mut lines)?),
+        "ranknet" => Box::new(Network::read(header, &mut lines)?),
+        kind => return Err(Error::parse(kind, "Unknown model")),
+    };
+    Ok(model)
+}
+
+/// Loads a model file. Errors tell the file name.
+pub fn load_file(path: &str) -> ParseResult<Box<dyn Evaluate>> {
+    compression::open(path)
+        .and_then(load)
+        .map_err(|e| e.in_file(path))
+}
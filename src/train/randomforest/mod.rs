@@ -0,0 +1,324 @@
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::fs::File;
+use self::randomforest::*;
+use std;
+use util::{exit_on_error, Error};
+use metric;
+use train::dataset::*;
+use format::csv::CsvFormat;
+use format::feature_names::FeatureNames;
+use train::lambdamart::training_set::Binning;
+
+pub mod randomforest;
+
+struct RandomForestParameter<'a> {
+    train_file_path: &'a str,
+    validate_file_path: Option<&'a str>,
+    test_file_path: Option<&'a str>,
+    model_file_path: Option<&'a str>,
+    feature_names_file_path: Option<&'a str>,
+    csv_format: CsvFormat,
+    regroup_queries: bool,
+    metric: &'a str,
+    metric_k: usize,
+    stop_metric: Option<&'a str>,
+    report_metrics: Vec<&'a str>,
+    trees: usize,
+    leaves: usize,
+    thresholds_count: usize,
+    binning: Binning,
+    min_leaf_samples: usize,
+    sampling_rate: f64,
+    feature_fraction: f64,
+    early_stop: usize,
+    seed: usize,
+}
+
+impl<'a> RandomForestParameter<'a> {
+    pub fn parse(matches: &'a ArgMatches<'a>) -> RandomForestParameter<'a> {
+        let train_file_path = matches.value_of("train-file").unwrap();
+        let validate_file_path = matches.value_of("validate-file");
+        let test_file_path = matches.value_of("test-file");
+        let model_file_path = matches.value_of("model-file");
+        let feature_names_file_path = matches.value_of("feature-names-file");
+        let csv_format = CsvFormat::from_matches(matches);
+        let regroup_queries = matches.is_present("regroup-queries");
+        let metric_k = value_t!(matches.value_of("metric-k"), usize)
+            .unwrap_or_else(|e| e.exit());
+        let (metric, stop_metric, report_metrics) =
+            super::metric_specs(matches, metric_k);
+        let trees = value_t!(matches.value_of("trees"), usize).unwrap_or_else(
+            |e| e.exit(),
+        );
+        let leaves = value_t!(matches.value_of("leaves"), usize)
+            .unwrap_or_else(|e| e.exit());
+        let thresholds_count = value_t!(matches.value_of("thresholds"), usize)
+            .unwrap_or_else(|e| e.exit());
+        // The value is valid.
+        let binning = Binning::new(matches.value_of("binning").unwrap())
+            .unwrap();
+        let min_leaf_samples =
+            value_t!(matches.value_of("min-leaf-support"), usize)
+                .unwrap_or_else(|e| e.exit());
+        let sampling_rate = value_t!(matches.value_of("sampling-rate"), f64)
+            .unwrap_or_else(|e| e.exit());
+        let feature_fraction =
+            value_t!(matches.value_of("feature-fraction"), f64)
+                .unwrap_or_else(|e| e.exit());
+        let early_stop = value_t!(matches.value_of("early-stop"), usize)
+            .unwrap_or_else(|e| e.exit());
+        let seed = value_t!(matches.value_of("seed"), usize)
+            .unwrap_or_else(|e| e.exit());
+
+        if sampling_rate <= 0.0 {
+            exit_on_error(Error::Config(format!(
+                "Sampling rate should be positive: {}",
+                sampling_rate
+            )));
+        }
+        if feature_fraction <= 0.0 || feature_fraction > 1.0 {
+            exit_on_error(Error::Config(format!(
+                "Feature fraction should be in (0, 1]: {}",
+                feature_fraction
+            )));
+        }
+
+        RandomForestParameter {
+            train_file_path: train_file_path,
+            validate_file_path: validate_file_path,
+            test_file_path: test_file_path,
+            model_file_path: model_file_path,
+            feature_names_file_path: feature_names_file_path,
+            csv_format: csv_format,
+            regroup_queries: regroup_queries,
+            metric: metric,
+            metric_k: metric_k,
+            stop_metric: stop_metric,
+            report_metrics: report_metrics,
+            trees: trees,
+            leaves: leaves,
+            thresholds_count: thresholds_count,
+            binning: binning,
+            min_leaf_samples: min_leaf_samples,
+            sampling_rate: sampling_rate,
+            feature_fraction: feature_fraction,
+            early_stop: early_stop,
+            seed: seed,
+        }
+    }
+
+    pub fn config(&self) -> Config {
+        let mut train_set = DataSet::load_file_with(
+            self.train_file_path,
+            &self.csv_format,
+            self.regroup_queries,
+        ).unwrap_or_else(|e| exit_on_error(e));
+
+        if let Some(path) = self.feature_names_file_path {
+            let feature_names = FeatureNames::load_file(path)
+                .unwrap_or_else(|e| exit_on_error(e));
+            train_set.add_feature_names(feature_names);
+        }
+
+        // The columns of CSV files are mapped to the features of the
+        // training data by name.
+        let csv_format = self.csv_format
+            .clone()
+            .with_feature_names(train_set.feature_names().clone());
+        let validate_set = self.validate_file_path.map(|path| {
+            DataSet::load_file_with(
+                path,
+                &csv_format,
+                self.regroup_queries,
+            ).unwrap_or_else(|e| exit_on_error(e))
+        });
+
+        let test_set = self.test_file_path.map(|path| {
+            DataSet::load_file_with(
+                path,
+                &csv_format,
+                self.regroup_queries,
+            ).unwrap_or_else(|e| exit_on_error(e))
+        });
+
+        // The param is valid.
+        let metric = metric::parse(self.metric, self.metric_k).unwrap();
+
+        Config {
+            train: train_set,
+            validate: validate_set,
+            test: test_set,
+            metric: metric,
+            stop_metric: self.stop_metric
+                .map(|spec| metric::parse(spec, self.metric_k).unwrap()),
+            report_metrics: self.report_metrics
+                .iter()
+                .map(|spec| metric::parse(spec, self.metric_k).unwrap())
+                .collect(),
+            trees: self.trees,
+            max_leaves: self.leaves,
+            thresholds: self.thresholds_count,
+            binning: self.binning,
+            min_leaf_samples: self.min_leaf_samples,
+            sampling_rate: self.sampling_rate,
+            feature_fraction: self.feature_fraction,
+            early_stop: self.early_stop,
+            seed: self.seed,
+            print_metric: true,
+        }
+    }
+
+    pub fn print(&self) {
+        fn print_param<T: std::fmt::Display>(name: &str, value: T) {
+            println!("{:<20}: {}", name, value);
+        }
+
+        print_param("Training file", self.train_file_path);
+        print_param(
+            "Validating file",
+            match self.validate_file_path {
+                Some(path) => path,
+                None => "None",
+            },
+        );
+        print_param(
+            "Testing file",
+            match self.test_file_path {
+                Some(path) => path,
+                None => "None",
+            },
+        );
+        print_param(
+            "Model file",
+            match self.model_file_path {
+                Some(path) => path,
+                None => "None",
+            },
+        );
+        print_param(
+            "Feature names file",
+            match self.feature_names_file_path {
+                Some(path) => path,
+                None => "None",
+            },
+        );
+        print_param(
+            "Metric",
+            metric::parse(self.metric, self.metric_k).unwrap().name(),
+        );
+        print_param("Stop metric", self.stop_metric.unwrap_or("Metric"));
+        print_param(
+            "Report metrics",
+            if self.report_metrics.is_empty() {
+                "None".to_string()
+            } else {
+                self.report_metrics.join(",")
+            },
+        );
+        print_param("Trees", self.trees);
+        print_param("Leaves", self.leaves);
+        print_param("Thresholds count", self.thresholds_count);
+        print_param("Binning", self.binning.name());
+        print_param("Min leaf samples", self.min_leaf_samples);
+        print_param("Sampling rate", self.sampling_rate);
+        print_param("Feature fraction", self.feature_fraction);
+        print_param("Early stop", self.early_stop);
+        print_param("Seed", self.seed);
+    }
+}
+
+pub fn main<'a>(matches: &ArgMatches<'a>) {
+    let param = RandomForestParameter::parse(matches);
+    param.print();
+
+    let mut forest = RandomForest::new(param.config());
+    forest.learn().unwrap_or_else(|e| exit_on_error(e));
+
+    if let Some(path) = param.model_file_path {
+        let file = File::create(path)
+            .map_err(|e| Error::from(e).in_file(path))
+            .unwrap_or_else(|e| exit_on_error(e));
+        forest.ensemble().save(file).unwrap_or_else(|e| exit_on_error(e));
+        println!("Model saved to {}", path);
+    }
+}
+
+pub fn clap_command<'a, 'b>() -> App<'a, 'b> {
+    let train_common_args = super::common_args();
+    // Random forest args
+    let randomforest_command = SubCommand::with_name("randomforest")
+        .about("Train random forest")
+        .args(&train_common_args)
+        .arg(
+            Arg::with_name("trees")
+                .long("trees")
+                .takes_value(true)
+                .value_name("NUM")
+                .default_value("300")
+                .display_order(101)
+                .help("Number of trees"),
+        )
+        .arg(
+            Arg::with_name("leaves")
+                .long("leaves")
+                .takes_value(true)
+                .value_name("NUM")
+                .default_value("100")
+                .display_order(102)
+                .help("Number of leaves for each tree"),
+        )
+        .arg(
+            Arg::with_name("thresholds")
+                .long("thresholds")
+                .takes_value(true)
+                .value_name("NUM")
+                .default_value("256")
+                .display_order(103)
+                .help("Number of threshold candidates for tree spliting"),
+        )
+        .arg(
+            Arg::with_name("min-leaf-support")
+                .long("min-leaf-support")
+                .takes_value(true)
+                .value_name("NUM")
+                .default_value("1")
+                .display_order(104)
+                .help("Min leaf support -- minimum #samples each leaf has to contain"),
+        )
+        .arg(
+            Arg::with_name("sampling-rate")
+                .long("sampling-rate")
+                .takes_value(true)
+                .value_name("RATE")
+                .default_value("1.0")
+                .display_order(105)
+                .help("Number of queries drawn with replacement for each tree, as a fraction of the training queries"),
+        )
+        .arg(
+            Arg::with_name("feature-fraction")
+                .long("feature-fraction")
+                .takes_value(true)
+                .value_name("RATE")
+                .default_value("0.3")
+                .display_order(106)
+                .help("Fraction of features considered for each split"),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .takes_value(true)
+                .value_name("NUM")
+                .default_value("1")
+                .display_order(107)
+                .help("Seed for sampling the queries and features"),
+        )
+        .arg(
+            Arg::with_name("binning")
+                .long("binning")
+                .possible_values(&["uniform", "quantile"])
+                .default_value("uniform")
+                .display_order(108)
+                .help("Generate thresholds evenly between min and max, or at the quantiles"),
+        );
+    randomforest_command
+}
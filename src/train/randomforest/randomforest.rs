@@ -0,0 +1,419 @@
+use rand::{Rng, SeedableRng, StdRng};
+use train::dataset::*;
+use train::lambdamart::regression_tree::*;
+use train::lambdamart::training_set::*;
+use train::lambdamart::objective::{Objective, SquaredError};
+use train::validate_set::*;
+use train::best_score::*;
+use train::Evaluate;
+use metric::*;
+use util::*;
+use std::cmp::Ordering;
+
+/// A instance of random forest algorithm.
+pub struct RandomForest {
+    config: Config,
+    ensemble: Ensemble,
+    rng: StdRng,
+}
+
+/// Configurable options for random forest.
+pub struct Config {
+    pub train: DataSet,
+    pub validate: Option<DataSet>,
+    pub test: Option<DataSet>,
+
+    pub metric: Box<dyn Measure>,
+    // The metric deciding the best iteration and early stopping, or
+    // `metric` if None.
+    pub stop_metric: Option<Box<dyn Measure>>,
+    // Metrics only reported in each iteration.
+    pub report_metrics: Vec<Box<dyn Measure>>,
+    pub trees: usize,
+    pub max_leaves: usize,
+    pub thresholds: usize,
+    // How the thresholds are generated from the feature values.
+    pub binning: Binning,
+    pub min_leaf_samples: usize,
+    // Number of queries drawn with replacement for each tree, as a
+    // fraction of the queries in the training data.
+    pub sampling_rate: f64,
+    // Fraction of the features considered when splitting a node.
+    pub feature_fraction: f64,
+    pub early_stop: usize,
+    pub seed: usize,
+    pub print_metric: bool,
+}
+
+/// The out-of-bag scores of the training data. The score of an
+/// instance is the average output of the trees which did not see the
+/// instance during training.
+struct OutOfBag {
+    sums: Vec<f64>,
+    counts: Vec<usize>,
+}
+
+impl OutOfBag {
+    fn new(len: usize) -> OutOfBag {
+        OutOfBag {
+            sums: vec![0.0; len],
+            counts: vec![0; len],
+        }
+    }
+
+    /// Adds the outputs of the tree to the instances not in the bag.
+    fn update<E: Evaluate>(
+        &mut self,
+        dataset: &DataSet,
+        evaluator: &E,
+        in_bag: &[bool],
+    ) {
+        for (index, instance) in dataset.iter().enumerate() {
+            if !in_bag[index] {
+                self.sums[index] += evaluator.evaluate(instance);
+                self.counts[index] += 1;
+            }
+        }
+    }
+
+    /// Measures the queries which have been out of the bag at least
+    /// once. Returns None if there is no such query.
+    fn measure(
+        &self,
+        dataset: &DataSet,
+        metric: &Box<dyn Measure>,
+    ) -> Option<f64> {
+        let mut score = 0.0;
+        let mut count: usize = 0;
+        for (_, query) in dataset.query_iter() {
+            if query.iter().any(|&id| self.counts[id] == 0) {
+                continue;
+            }
+
+            let mut model_scores: Vec<(Value, Value)> = query
+                .iter()
+                .map(|&id| {
+                    (
+                        self.sums[id] / self.counts[id] as f64,
+                        dataset[id].label(),
+                    )
+                })
+                .collect();
+            model_scores.sort_by(|&(score1, _), &(score2, _)| {
+                score2.partial_cmp(&score1).unwrap_or(Ordering::Equal)
+            });
+
+            let labels: Vec<f64> =
+                model_scores.iter().map(|&(_, label)| label).collect();
+            score += metric.measure(&labels);
+            count += 1;
+        }
+
+        if count == 0 {
+            None
+        } else {
+            Some(score / count as f64)
+        }
+    }
+}
+
+impl RandomForest {
+    /// Create a new random forest instance. The bootstrap samples and
+    /// the features of each split are drawn with the seed in the
+    /// config.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use rforests::util::Result;
+    /// # pub fn randomforest(train_path: &str) -> Result<()> {
+    ///     use std::fs::File;
+    ///     use rforests::train::dataset::*;
+    ///     use rforests::train::randomforest::randomforest::*;
+    ///     use rforests::metric;
+    ///     use rforests::train::lambdamart::training_set::Binning;
+    ///
+    ///     let f = File::open(train_path)?;
+    ///     let dataset = DataSet::load(f).unwrap();
+    ///
+    ///     let config = Config {
+    ///         train: dataset,
+    ///         trees: 300,
+    ///         max_leaves: 100,
+    ///         min_leaf_samples: 1,
+    ///         thresholds: 256,
+    ///         binning: Binning::Uniform,
+    ///         sampling_rate: 1.0,
+    ///         feature_fraction: 0.3,
+    ///         print_metric: true,
+    ///         metric: metric::new("NDCG", 10).unwrap(),
+    ///         stop_metric: None,
+    ///         report_metrics: Vec::new(),
+    ///         validate: None,
+    ///         test: None,
+    ///         early_stop: 100,
+    ///         seed: 1,
+    ///     };
+    ///     let mut forest = RandomForest::new(config);
+    ///     forest.learn()?;
+    /// #    Ok(())
+    /// # }
+    /// ```
+    pub fn new(config: Config) -> RandomForest {
+        let rng: StdRng = SeedableRng::from_seed(&[config.seed][..]);
+        let mut ensemble = Ensemble::new();
+        ensemble.set_nfeatures(config.train.nfeatures());
+        ensemble.set_feature_names(config.train.feature_names().clone());
+        ensemble.add_param("algorithm", "RandomForest");
+        ensemble.add_param("metric", config.metric.name());
+        ensemble.add_param("trees", config.trees);
+        ensemble.add_param("leaves", config.max_leaves);
+        ensemble.add_param("thresholds", config.thresholds);
+        ensemble.add_param("binning", config.binning.name());
+        ensemble.add_param("min-leaf-support", config.min_leaf_samples);
+        ensemble.add_param("sampling-rate", config.sampling_rate);
+        ensemble.add_param("feature-fraction", config.feature_fraction);
+        ensemble.add_param("early-stop", config.early_stop);
+        ensemble.add_param("seed", config.seed);
+
+        RandomForest {
+            config: config,
+            ensemble: ensemble,
+            rng: rng,
+        }
+    }
+
+    /// Learns from the given training data, using the configuration
+    /// specified when creating the random forest instance. Each tree
+    /// fits the labels of a bootstrap sample of the queries, and the
+    /// output of the forest is the average of the trees.
+    pub fn learn(&mut self) -> Result<()> {
+        let mut training = TrainSet::with_binning(
+            &self.config.train,
+            self.config.thresholds,
+            self.config.binning,
+        );
+        // All the trees fit the labels.
+        SquaredError.update(&mut training);
+
+        let queries: Vec<Vec<Id>> = self.config
+            .train
+            .query_iter()
+            .map(|(_qid, query)| query)
+            .collect();
+        let bag_size = ((queries.len() as f64 * self.config.sampling_rate)
+            .round() as usize)
+            .max(1);
+        let fids: Vec<Id> = training.fid_iter().collect();
+
+        let mut train = ValidateSet::from(&self.config.train);
+        let mut out_of_bag = OutOfBag::new(self.config.train.len());
+        let mut validate =
+            self.config.validate.as_ref().map(|v| ValidateSet::from(v));
+        let (metrics, stop) = training_metrics(
+            &self.config.metric,
+            self.config.stop_metric.as_ref(),
+            &self.config.report_metrics,
+        );
+        let names: Vec<String> =
+            metrics.iter().map(|metric| metric.name()).collect();
+        let mut best_score = BestScore::new(&names, stop);
+        let table = ScoreTable::new(&names, &["T", "OOB", "V"]);
+
+        self.print(&table.header());
+        for i in 0..self.config.trees {
+            // Draw the queries with replacement.
+            let mut in_bag = vec![false; self.config.train.len()];
+            let mut indices = Vec::new();
+            for _ in 0..bag_size {
+                let query = &queries[self.rng.gen_range(0, queries.len())];
+                for &index in query.iter() {
+                    in_bag[index] = true;
+                    indices.push(index);
+                }
+            }
+
+            let mut tree = RegressionTree::new(
+                1.0 / self.config.trees as f64,
+                self.config.max_leaves,
+                self.config.min_leaf_samples,
+            );
+            tree.set_feature_fraction(self.config.feature_fraction);
+            let sample = TrainSample::new(&training, indices);
+            tree.fit_sample(&training, sample, &fids, &mut self.rng);
+
+            train.update(&tree);
+            let train_scores: Vec<f64> =
+                metrics.iter().map(|metric| train.measure(metric)).collect();
+
+            out_of_bag.update(&self.config.train, &tree, &in_bag);
+            let oob_scores: Option<Vec<f64>> = metrics
+                .iter()
+                .map(|metric| out_of_bag.measure(&self.config.train, metric))
+                .collect();
+
+            validate.as_mut().map(|v| v.update(&tree));
+            let validate_scores: Option<Vec<f64>> = validate.as_ref().map(|v| {
+                metrics.iter().map(|metric| v.measure(metric)).collect()
+            });
+            let validate_scores = validate_scores.as_ref().map(|v| &v[..]);
+
+            self.ensemble.push(tree);
+
+            self.print(&table.row(
+                i,
+                &[
+                    Some(&train_scores),
+                    oob_scores.as_ref().map(|v| &v[..]),
+                    validate_scores,
+                ],
+            ));
+
+            best_score.update(i, &train_scores, validate_scores);
+            if best_score.should_stop(i, self.config.early_stop) {
+                self.ensemble.truncate(best_score.best_iter().unwrap() + 1);
+                break;
+            }
+        }
+
+        // Early stopping may keep fewer trees than configured.
+        let kept = self.ensemble.len() as f64;
+        for tree in self.ensemble.iter_mut() {
+            tree.set_learning_rate(1.0 / kept);
+        }
+
+        println!("{}", best_score);
+        Ok(())
+    }
+
+    /// Returns the trees learned so far.
+    pub fn ensemble(&self) -> &Ensemble {
+        &self.ensemble
+    }
+
+    pub fn evaluate(&self, dataset: &DataSet) -> f64 {
+        dataset.evaluate(&self.ensemble, &self.config.metric)
+    }
+
+    fn print(&self, msg: &str) {
+        if self.config.print_metric {
+            println!("{}", msg);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn test_random_forest() {
+        // CWD of cargo test is the root of the project.
+        let path = "./data/train-lite.txt";
+        let f = File::open(path).unwrap();
+        let dataset = DataSet::load(f).unwrap();
+        let validate_set = dataset.clone();
+
+        let config = Config {
+            train: dataset,
+            test: None,
+            trees: 10,
+            max_leaves: 10,
+            min_leaf_samples: 1,
+            thresholds: 256,
+            binning: Binning::Uniform,
+            sampling_rate: 1.0,
+            feature_fraction: 0.3,
+            early_stop: 100,
+            seed: 1,
+            print_metric: false,
+            metric: Box::new(NDCGScorer::new(10)),
+            stop_metric: None,
+            report_metrics: Vec::new(),
+            validate: None,
+        };
+        let mut forest = RandomForest::new(config);
+        forest.learn().unwrap();
+        assert_eq!(forest.ensemble().len(), 10);
+
+        let score = forest.evaluate(&validate_set);
+        assert!(score > 0.5);
+
+        // The saved model gives the same result.
+        let mut buf = Vec::new();
+        forest.ensemble().save(&mut buf).unwrap();
+        let ensemble = Ensemble::load(buf.as_slice()).unwrap();
+        assert_eq!(
+            validate_set.evaluate(&ensemble, &forest.config.metric),
+            score
+        );
+    }
+
+    #[test]
+    fn test_early_stop_average() {
+        let path = "./data/train-lite.txt";
+        let dataset = DataSet::load(File::open(path).unwrap()).unwrap();
+
+        let config = Config {
+            train: dataset.clone(),
+            test: None,
+            trees: 20,
+            max_leaves: 10,
+            min_leaf_samples: 1,
+            thresholds: 256,
+            binning: Binning::Uniform,
+            sampling_rate: 1.0,
+            feature_fraction: 0.3,
+            early_stop: 1,
+            seed: 1,
+            print_metric: false,
+            metric: Box::new(NDCGScorer::new(10)),
+            stop_metric: None,
+            report_metrics: Vec::new(),
+            validate: Some(dataset.clone()),
+        };
+        let mut forest = RandomForest::new(config);
+        forest.learn().unwrap();
+        let ensemble = forest.ensemble();
+        assert!(ensemble.len() < 20);
+
+        // The output is the average of the kept trees.
+        for tree in ensemble.iter() {
+            assert_eq!(tree.learning_rate(), 1.0 / ensemble.len() as f64);
+        }
+        let instance = &dataset[0];
+        let sum: f64 = ensemble
+            .iter()
+            .map(|tree| tree.evaluate(instance) / tree.learning_rate())
+            .sum();
+        let average = sum / ensemble.len() as f64;
+        assert!((ensemble.evaluate(instance) - average).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_out_of_bag() {
+        // (label, qid, feature_values)
+        let data = vec![
+            (3.0, 1, vec![3.0]),
+            (1.0, 1, vec![1.0]),
+            (1.0, 2, vec![1.0]),
+            (2.0, 2, vec![2.0]),
+        ];
+        let dataset: DataSet = data.into_iter().collect();
+        let metric: Box<dyn Measure> = Box::new(NDCGScorer::new(10));
+
+        struct Identity;
+        impl Evaluate for Identity {
+            fn evaluate(&self, instance: &Instance) -> f64 {
+                instance.value(1)
+            }
+        }
+
+        let mut out_of_bag = OutOfBag::new(dataset.len());
+        assert_eq!(out_of_bag.measure(&dataset, &metric), None);
+
+        out_of_bag.update(&dataset, &Identity, &[true, true, false, false]);
+        assert_eq!(out_of_bag.measure(&dataset, &metric), Some(1.0));
+    }
+}
@@ -0,0 +1,262 @@
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::fs::File;
+use self::ranknet::*;
+use std;
+use util::{exit_on_error, Error};
+use metric;
+use train::dataset::*;
+use format::csv::CsvFormat;
+use format::feature_names::FeatureNames;
+
+pub mod ranknet;
+
+struct RankNetParameter<'a> {
+    train_file_path: &'a str,
+    validate_file_path: Option<&'a str>,
+    test_file_path: Option<&'a str>,
+    model_file_path: Option<&'a str>,
+    feature_names_file_path: Option<&'a str>,
+    csv_format: CsvFormat,
+    regroup_queries: bool,
+    metric: &'a str,
+    metric_k: usize,
+    stop_metric: Option<&'a str>,
+    report_metrics: Vec<&'a str>,
+    epochs: usize,
+    layers: usize,
+    nodes: usize,
+    learning_rate: f64,
+    early_stop: usize,
+    seed: usize,
+}
+
+impl<'a> RankNetParameter<'a> {
+    pub fn parse(matches: &'a ArgMatches<'a>) -> RankNetParameter<'a> {
+        let train_file_path = matches.value_of("train-file").unwrap();
+        let validate_file_path = matches.value_of("validate-file");
+        let test_file_path = matches.value_of("test-file");
+        let model_file_path = matches.value_of("model-file");
+        let feature_names_file_path = matches.value_of("feature-names-file");
+        let csv_format = CsvFormat::from_matches(matches);
+        let regroup_queries = matches.is_present("regroup-queries");
+        let metric_k = value_t!(matches.value_of("metric-k"), usize)
+            .unwrap_or_else(|e| e.exit());
+        let (metric, stop_metric, report_metrics) =
+            super::metric_specs(matches, metric_k);
+        let epochs = value_t!(matches.value_of("epochs"), usize)
+            .unwrap_or_else(|e| e.exit());
+        let layers = value_t!(matches.value_of("layers"), usize)
+            .unwrap_or_else(|e| e.exit());
+        let nodes = value_t!(matches.value_of("nodes"), usize)
+            .unwrap_or_else(|e| e.exit());
+        let learning_rate = value_t!(matches.value_of("learning-rate"), f64)
+            .unwrap_or_else(|e| e.exit());
+        let early_stop = value_t!(matches.value_of("early-stop"), usize)
+            .unwrap_or_else(|e| e.exit());
+        let seed = value_t!(matches.value_of("seed"), usize)
+            .unwrap_or_else(|e| e.exit());
+
+        RankNetParameter {
+            train_file_path: train_file_path,
+            validate_file_path: validate_file_path,
+            test_file_path: test_file_path,
+            model_file_path: model_file_path,
+            feature_names_file_path: feature_names_file_path,
+            csv_format: csv_format,
+            regroup_queries: regroup_queries,
+            metric: metric,
+            metric_k: metric_k,
+            stop_metric: stop_metric,
+            report_metrics: report_metrics,
+            epochs: epochs,
+            layers: layers,
+            nodes: nodes,
+            learning_rate: learning_rate,
+            early_stop: early_stop,
+            seed: seed,
+        }
+    }
+
+    pub fn config(&self) -> Config {
+        let mut train_set = DataSet::load_file_with(
+            self.train_file_path,
+            &self.csv_format,
+            self.regroup_queries,
+        ).unwrap_or_else(|e| exit_on_error(e));
+
+        if let Some(path) = self.feature_names_file_path {
+            let feature_names = FeatureNames::load_file(path)
+                .unwrap_or_else(|e| exit_on_error(e));
+            train_set.add_feature_names(feature_names);
+        }
+
+        // The columns of CSV files are mapped to the features of the
+        // training data by name.
+        let csv_format = self.csv_format
+            .clone()
+            .with_feature_names(train_set.feature_names().clone());
+        let validate_set = self.validate_file_path.map(|path| {
+            DataSet::load_file_with(
+                path,
+                &csv_format,
+                self.regroup_queries,
+            ).unwrap_or_else(|e| exit_on_error(e))
+        });
+
+        let test_set = self.test_file_path.map(|path| {
+            DataSet::load_file_with(
+                path,
+                &csv_format,
+                self.regroup_queries,
+            ).unwrap_or_else(|e| exit_on_error(e))
+        });
+
+        // The param is valid.
+        let metric = metric::parse(self.metric, self.metric_k).unwrap();
+
+        Config {
+            train: train_set,
+            validate: validate_set,
+            test: test_set,
+            metric: metric,
+            stop_metric: self.stop_metric
+                .map(|spec| metric::parse(spec, self.metric_k).unwrap()),
+            report_metrics: self.report_metrics
+                .iter()
+                .map(|spec| metric::parse(spec, self.metric_k).unwrap())
+                .collect(),
+            epochs: self.epochs,
+            hidden_layers: self.layers,
+            hidden_nodes: self.nodes,
+            learning_rate: self.learning_rate,
+            early_stop: self.early_stop,
+            seed: self.seed,
+            print_metric: true,
+        }
+    }
+
+    pub fn print(&self) {
+        fn print_param<T: std::fmt::Display>(name: &str, value: T) {
+            println!("{:<20}: {}", name, value);
+        }
+
+        print_param("Training file", self.train_file_path);
+        print_param(
+            "Validating file",
+            match self.validate_file_path {
+                Some(path) => path,
+                None => "None",
+            },
+        );
+        print_param(
+            "Testing file",
+            match self.test_file_path {
+                Some(path) => path,
+                None => "None",
+            },
+        );
+        print_param(
+            "Model file",
+            match self.model_file_path {
+                Some(path) => path,
+                None => "None",
+            },
+        );
+        print_param(
+            "Feature names file",
+            match self.feature_names_file_path {
+                Some(path) => path,
+                None => "None",
+            },
+        );
+        print_param(
+            "Metric",
+            metric::parse(self.metric, self.metric_k).unwrap().name(),
+        );
+        print_param("Stop metric", self.stop_metric.unwrap_or("Metric"));
+        print_param(
+            "Report metrics",
+            if self.report_metrics.is_empty() {
+                "None".to_string()
+            } else {
+                self.report_metrics.join(",")
+            },
+        );
+        print_param("Epochs", self.epochs);
+        print_param("Hidden layers", self.layers);
+        print_param("Nodes per layer", self.nodes);
+        print_param("Learning rate", self.learning_rate);
+        print_param("Early stop", self.early_stop);
+        print_param("Seed", self.seed);
+    }
+}
+
+pub fn main<'a>(matches: &ArgMatches<'a>) {
+    let param = RankNetParameter::parse(matches);
+    param.print();
+
+    let mut ranknet = RankNet::new(param.config());
+    ranknet.learn().unwrap_or_else(|e| exit_on_error(e));
+
+    if let Some(path) = param.model_file_path {
+        let file = File::create(path)
+            .map_err(|e| Error::from(e).in_file(path))
+            .unwrap_or_else(|e| exit_on_error(e));
+        ranknet.network().save(file).unwrap_or_else(|e| exit_on_error(e));
+        println!("Model saved to {}", path);
+    }
+}
+
+pub fn clap_command<'a, 'b>() -> App<'a, 'b> {
+    let train_common_args = super::common_args();
+    // RankNet args
+    let ranknet_command = SubCommand::with_name("ranknet")
+        .about("Train RankNet")
+        .args(&train_common_args)
+        .arg(
+            Arg::with_name("epochs")
+                .long("epochs")
+                .takes_value(true)
+                .value_name("NUM")
+                .default_value("100")
+                .display_order(101)
+                .help("Number of epochs"),
+        )
+        .arg(
+            Arg::with_name("layers")
+                .long("layers")
+                .takes_value(true)
+                .value_name("NUM")
+                .default_value("1")
+                .display_order(102)
+                .help("Number of hidden layers"),
+        )
+        .arg(
+            Arg::with_name("nodes")
+                .long("nodes")
+                .takes_value(true)
+                .value_name("NUM")
+                .default_value("10")
+                .display_order(103)
+                .help("Number of nodes for each hidden layer"),
+        )
+        .arg(
+            Arg::with_name("learning-rate")
+                .long("learning-rate")
+                .takes_value(true)
+                .value_name("RATE")
+                .default_value("0.001")
+                .display_order(104)
+                .help("Learning rate"),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .takes_value(true)
+                .value_name("NUM")
+                .default_value("1")
+                .display_order(105)
+                .help("Seed for initializing the weights and shuffling"),
+        );
+    ranknet_command
+}
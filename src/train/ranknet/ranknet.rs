@@ -0,0 +1,571 @@
+use rand::{Rng, SeedableRng, StdRng};
+use std::io::prelude::*;
+use std::io::BufWriter;
+use metric::*;
+use train::Evaluate;
+use train::best_score::*;
+use train::dataset::*;
+use train::model::{self, ModelHeader, ModelLines};
+use format::feature_names::FeatureNames;
+use util::*;
+
+/// A instance of RankNet algorithm.
+pub struct RankNet {
+    config: Config,
+    network: Network,
+    rng: StdRng,
+}
+
+/// Configurable options for RankNet.
+pub struct Config {
+    pub train: DataSet,
+    pub validate: Option<DataSet>,
+    pub test: Option<DataSet>,
+
+    pub metric: Box<dyn Measure>,
+    // The metric deciding the best epoch and early stopping, or
+    // `metric` if None.
+    pub stop_metric: Option<Box<dyn Measure>>,
+    // Metrics only reported in each epoch.
+    pub report_metrics: Vec<Box<dyn Measure>>,
+    pub epochs: usize,
+    pub hidden_layers: usize,
+    pub hidden_nodes: usize,
+    pub learning_rate: f64,
+    pub early_stop: usize,
+    pub seed: usize,
+    pub print_metric: bool,
+}
+
+/// A fully connected layer of neurons.
+#[derive(Clone, Debug, PartialEq)]
+struct Layer {
+    inputs: usize,
+    outputs: usize,
+    // The weights of the synapses from the inputs to the o-th output
+    // are at [o * (inputs + 1), (o + 1) * (inputs + 1)). The last one
+    // of them is the bias.
+    weights: Vec<f64>,
+    // Hidden layers use the sigmoid activation. The output layer is
+    // linear.
+    sigmoid: bool,
+}
+
+impl Layer {
+    /// Creates a layer with weights drawn uniformly from [-r, r],
+    /// where r = 1 / sqrt(inputs + 1).
+    fn new<R: Rng>(
+        inputs: usize,
+        outputs: usize,
+        sigmoid: bool,
+        rng: &mut R,
+    ) -> Layer {
+        let range = 1.0 / ((inputs + 1) as f64).sqrt();
+        let weights = (0..outputs * (inputs + 1))
+            .map(|_| rng.gen_range(-range, range))
+            .collect();
+        Layer {
+            inputs: inputs,
+            outputs: outputs,
+            weights: weights,
+            sigmoid: sigmoid,
+        }
+    }
+
+    fn neuron_weights(&self, output: usize) -> &[f64] {
+        let width = self.inputs + 1;
+        &self.weights[output * width..(output + 1) * width]
+    }
+
+    fn forward(&self, input: &[f64]) -> Vec<f64> {
+        (0..self.outputs)
+            .map(|o| {
+                let weights = self.neuron_weights(o);
+                let sum = weights[self.inputs] +
+                    weights
+                        .iter()
+                        .zip(input.iter())
+                        .map(|(w, x)| w * x)
+                        .sum::<f64>();
+                if self.sigmoid {
+                    1.0 / (1.0 + (-sum).exp())
+                } else {
+                    sum
+                }
+            })
+            .collect()
+    }
+
+    /// Back propagates the gradients of the outputs. The gradients of
+    /// the weights are added to `weight_gradients`, and the gradients
+    /// of the inputs are returned.
+    fn backward(
+        &self,
+        input: &[f64],
+        output: &[f64],
+        output_gradients: &[f64],
+        weight_gradients: &mut [f64],
+    ) -> Vec<f64> {
+        let width = self.inputs + 1;
+        let mut input_gradients = vec![0.0; self.inputs];
+        for o in 0..self.outputs {
+            let delta = if self.sigmoid {
+                output_gradients[o] * output[o] * (1.0 - output[o])
+            } else {
+                output_gradients[o]
+            };
+            if delta == 0.0 {
+                continue;
+            }
+
+            let weights = self.neuron_weights(o);
+            let gradients = &mut weight_gradients[o * width..(o + 1) * width];
+            for i in 0..self.inputs {
+                gradients[i] += delta * input[i];
+                input_gradients[i] += delta * weights[i];
+            }
+            gradients[self.inputs] += delta;
+        }
+        input_gradients
+    }
+}
+
+/// A multi-layer perceptron which outputs the score of an instance.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Network {
+    nfeatures: usize,
+    params: Vec<(String, String)>,
+    // Names of the features of the training data.
+    feature_names: FeatureNames,
+    layers: Vec<Layer>,
+}
+
+impl Network {
+    /// Creates a network with randomly initialized weights. The output
+    /// layer has one neuron.
+    pub fn new<R: Rng>(
+        nfeatures: usize,
+        hidden_layers: usize,
+        hidden_nodes: usize,
+        rng: &mut R,
+    ) -> Network {
+        let mut layers = Vec::new();
+        let mut inputs = nfeatures;
+        for _ in 0..hidden_layers {
+            layers.push(Layer::new(inputs, hidden_nodes, true, rng));
+            inputs = hidden_nodes;
+        }
+        layers.push(Layer::new(inputs, 1, false, rng));
+
+        Network {
+            nfeatures: nfeatures,
+            params: Vec::new(),
+            feature_names: FeatureNames::new(),
+            layers: layers,
+        }
+    }
+
+    /// Returns the number of features of the training data.
+    pub fn nfeatures(&self) -> usize {
+        self.nfeatures
+    }
+
+    /// Returns the names of the features of the training data.
+    pub fn feature_names(&self) -> &FeatureNames {
+        &self.feature_names
+    }
+
+    pub fn set_feature_names(&mut self, feature_names: FeatureNames) {
+        self.feature_names = feature_names;
+    }
+
+    /// Records a training parameter. The value should not contain
+    /// line breaks.
+    pub fn add_param<T: ToString>(&mut self, name: &str, value: T) {
+        self.params.push((name.to_string(), value.to_string()));
+    }
+
+    /// Returns the outputs of each layer, with the input values as the
+    /// first element.
+    fn forward(&self, instance: &Instance) -> Vec<Vec<f64>> {
+        let input: Vec<f64> = (1..self.nfeatures + 1)
+            .map(|fid| instance.value(fid))
+            .collect();
+        let mut outputs = vec![input];
+        for layer in self.layers.iter() {
+            let output = layer.forward(outputs.last().unwrap());
+            outputs.push(output);
+        }
+        outputs
+    }
+
+    /// Performs one step of gradient descent on the pairwise cross
+    /// entropy of a query.
+    ///
+    /// For a pair of instances (i, j) where i has a higher label, the
+    /// cost is C = log(1 + exp(s_j - s_i)), where s is the score of
+    /// the network. So dC/ds_i = -1 / (1 + exp(s_i - s_j)) = -dC/ds_j.
+    /// The gradients of each instance are summed up before being back
+    /// propagated, which is much faster than back propagating each
+    /// pair.
+    fn train_query(
+        &mut self,
+        dataset: &DataSet,
+        query: &[Id],
+        learning_rate: f64,
+    ) {
+        let outputs: Vec<Vec<Vec<f64>>> = query
+            .iter()
+            .map(|&index| self.forward(&dataset[index]))
+            .collect();
+        let scores: Vec<f64> =
+            outputs.iter().map(|output| output.last().unwrap()[0]).collect();
+
+        let mut score_gradients = vec![0.0; query.len()];
+        for i in 0..query.len() {
+            for j in 0..query.len() {
+                if dataset[query[i]].label() <= dataset[query[j]].label() {
+                    continue;
+                }
+                let rho = 1.0 / (1.0 + (scores[i] - scores[j]).exp());
+                score_gradients[i] -= rho;
+                score_gradients[j] += rho;
+            }
+        }
+
+        let mut weight_gradients: Vec<Vec<f64>> = self.layers
+            .iter()
+            .map(|layer| vec![0.0; layer.weights.len()])
+            .collect();
+        for (output, &gradient) in outputs.iter().zip(score_gradients.iter()) {
+            if gradient == 0.0 {
+                continue;
+            }
+            let mut gradients = vec![gradient];
+            for (l, layer) in self.layers.iter().enumerate().rev() {
+                gradients = layer.backward(
+                    &output[l],
+                    &output[l + 1],
+                    &gradients,
+                    &mut weight_gradients[l],
+                );
+            }
+        }
+
+        for (layer, gradients) in
+            self.layers.iter_mut().zip(weight_gradients.iter())
+        {
+            for (weight, gradient) in
+                layer.weights.iter_mut().zip(gradients.iter())
+            {
+                *weight -= learning_rate * gradient;
+            }
+        }
+    }
+
+    /// Writes the network to a writer in the model file format.
+    ///
+    /// The body following the model header (see `ModelHeader`) is:
+    ///
+    /// ```text
+    /// layers <number of layers>
+    /// layer <inputs> <outputs> <sigmoid|linear>
+    /// <weight> ... <weight> <bias>
+    /// ...
+    /// ```
+    ///
+    /// where each layer has one line of weights for each output.
+    pub fn save<W: Write>(&self, writer: W) -> Result<()> {
+        let mut writer = BufWriter::new(writer);
+        ModelHeader::new("ranknet", self.nfeatures, &self.params)
+            .with_feature_names(&self.feature_names)
+            .write(&mut writer)?;
+        writeln!(writer, "layers {}", self.layers.len())?;
+        for layer in self.layers.iter() {
+            writeln!(
+                writer,
+                "layer {} {} {}",
+                layer.inputs,
+                layer.outputs,
+                if layer.sigmoid { "sigmoid" } else { "linear" }
+            )?;
+            for o in 0..layer.outputs {
+                let weights: Vec<String> = layer
+                    .neuron_weights(o)
+                    .iter()
+                    .map(|weight| weight.to_string())
+                    .collect();
+                writeln!(writer, "{}", weights.join(" "))?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Loads a network written by `Network::save`.
+    pub fn load<R: Read>(reader: R) -> ParseResult<Network> {
+        let mut lines = model::read_lines(reader)?;
+        let header = ModelHeader::read(&mut lines)?;
+        if header.kind != "ranknet" {
+            return Err(Error::parse(&header.kind, "Not a RankNet model"));
+        }
+        Network::read(header, &mut lines)
+    }
+
+    /// Reads the body of a RankNet model file.
+    pub fn read(
+        header: ModelHeader,
+        lines: &mut ModelLines,
+    ) -> ParseResult<Network> {
+        // Parses "layer <inputs> <outputs> <activation>" into (inputs,
+        // outputs, whether the activation is sigmoid).
+        fn parse_layer(line: &str) -> ParseResult<(usize, usize, bool)> {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 4 || fields[0] != "layer" {
+                return Err(Error::parse(line, "Invalid layer header"));
+            }
+            let inputs = model::parse_field(fields[1], "Invalid inputs count")?;
+            let outputs =
+                model::parse_field(fields[2], "Invalid outputs count")?;
+            let sigmoid = match fields[3] {
+                "sigmoid" => true,
+                "linear" => false,
+                field => return Err(Error::parse(field, "Invalid activation")),
+            };
+            Ok((inputs, outputs, sigmoid))
+        }
+
+        let (line_number, line) = model::next_line(lines)?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let nlayers = match (fields.get(0).cloned(), fields.len()) {
+            (Some("layers"), 2) => {
+                model::parse_field(fields[1], "Invalid layers count")
+            }
+            _ => Err(Error::parse(&line, "Invalid model line")),
+        }.map_err(|e| e.at_line(line_number))?;
+
+        let mut layers = Vec::new();
+        let mut inputs = header.nfeatures;
+        for _ in 0..nlayers {
+            let (line_number, line) = model::next_line(lines)?;
+            let (layer_inputs, outputs, sigmoid) =
+                parse_layer(&line).map_err(|e| e.at_line(line_number))?;
+            if layer_inputs != inputs {
+                let e = Error::parse(&line, "Layer inputs do not match");
+                return Err(e.at_line(line_number));
+            }
+
+            // The counts come from the file, so the weights are not
+            // allocated ahead.
+            let mut weights = Vec::new();
+            for _ in 0..outputs {
+                let (line_number, line) = model::next_line(lines)?;
+                let neuron = line.split_whitespace()
+                    .map(|weight| model::parse_field(weight, "Invalid weight"))
+                    .collect::<ParseResult<Vec<f64>>>()
+                    .and_then(|neuron| if neuron.len().checked_sub(1) ==
+                        Some(inputs)
+                    {
+                        Ok(neuron)
+                    } else {
+                        Err(Error::parse(&line, "Invalid count of weights"))
+                    })
+                    .map_err(|e| e.at_line(line_number))?;
+                weights.extend(neuron);
+            }
+
+            layers.push(Layer {
+                inputs: inputs,
+                outputs: outputs,
+                weights: weights,
+                sigmoid: sigmoid,
+            });
+            inputs = outputs;
+        }
+        if inputs != 1 {
+            return Err(Error::Parse {
+                file: None,
+                line: None,
+                token: None,
+                message: "The output layer should have one neuron".to_string(),
+            });
+        }
+
+        Ok(Network {
+            nfeatures: header.nfeatures,
+            params: header.params,
+            feature_names: header.feature_names,
+            layers: layers,
+        })
+    }
+}
+
+impl Evaluate for Network {
+    fn evaluate(&self, instance: &Instance) -> f64 {
+        self.forward(instance).last().unwrap()[0]
+    }
+
+    fn feature_names(&self) -> Option<&FeatureNames> {
+        Some(&self.feature_names)
+    }
+}
+
+impl RankNet {
+    /// Create a new RankNet instance. The weights of the network are
+    /// initialized with the seed in the config.
+    pub fn new(config: Config) -> RankNet {
+        let mut rng: StdRng = SeedableRng::from_seed(&[config.seed][..]);
+        let mut network = Network::new(
+            config.train.nfeatures(),
+            config.hidden_layers,
+            config.hidden_nodes,
+            &mut rng,
+        );
+        network.set_feature_names(config.train.feature_names().clone());
+        network.add_param("algorithm", "RankNet");
+        network.add_param("metric", config.metric.name());
+        network.add_param("epochs", config.epochs);
+        network.add_param("layers", config.hidden_layers);
+        network.add_param("nodes", config.hidden_nodes);
+        network.add_param("learning-rate", config.learning_rate);
+        network.add_param("early-stop", config.early_stop);
+        network.add_param("seed", config.seed);
+
+        RankNet {
+            config: config,
+            network: network,
+            rng: rng,
+        }
+    }
+
+    /// Learns from the given training data, using the configuration
+    /// specified when creating RankNet instance. Each epoch goes
+    /// through all the queries in random order. If there is
+    /// validation data, the network of the best epoch is kept.
+    pub fn learn(&mut self) -> Result<()> {
+        let mut queries: Vec<Vec<Id>> = self.config
+            .train
+            .query_iter()
+            .map(|(_qid, query)| query)
+            .collect();
+        let (metrics, stop) = training_metrics(
+            &self.config.metric,
+            self.config.stop_metric.as_ref(),
+            &self.config.report_metrics,
+        );
+        let names: Vec<String> =
+            metrics.iter().map(|metric| metric.name()).collect();
+        let mut best_score = BestScore::new(&names, stop);
+        let table = ScoreTable::new(&names, &["T", "V"]);
+        let mut best_network = self.network.clone();
+
+        self.print(&table.header());
+        for i in 0..self.config.epochs {
+            self.rng.shuffle(&mut queries);
+            for query in queries.iter() {
+                self.network.train_query(
+                    &self.config.train,
+                    query,
+                    self.config.learning_rate,
+                );
+            }
+
+            let train_scores = self.config
+                .train
+                .evaluate_metrics(&self.network, &metrics);
+            let validate_scores = self.config
+                .validate
+                .as_ref()
+                .map(|v| v.evaluate_metrics(&self.network, &metrics));
+            let validate_scores = validate_scores.as_ref().map(|v| &v[..]);
+
+            self.print(&table.row(i, &[Some(&train_scores), validate_scores]));
+
+            best_score.update(i, &train_scores, validate_scores);
+            if best_score.best_iter() == Some(i) {
+                best_network = self.network.clone();
+            }
+
+            if best_score.should_stop(i, self.config.early_stop) {
+                break;
+            }
+        }
+
+        if best_score.best_iter().is_some() {
+            self.network = best_network;
+        }
+
+        println!("{}", best_score);
+        Ok(())
+    }
+
+    /// Returns the trained network.
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+
+    pub fn evaluate(&self, dataset: &DataSet) -> f64 {
+        dataset.evaluate(&self.network, &self.config.metric)
+    }
+
+    fn print(&self, msg: &str) {
+        if self.config.print_metric {
+            println!("{}", msg);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn test_ranknet() {
+        let path = "./data/train-lite.txt";
+        let f = File::open(path).unwrap();
+        let dataset = DataSet::load(f).unwrap();
+        let validate_set = dataset.clone();
+
+        let config = Config {
+            train: dataset,
+            validate: None,
+            test: None,
+            metric: Box::new(NDCGScorer::new(10)),
+            stop_metric: None,
+            report_metrics: Vec::new(),
+            epochs: 10,
+            hidden_layers: 1,
+            hidden_nodes: 10,
+            learning_rate: 0.001,
+            early_stop: 100,
+            seed: 1,
+            print_metric: false,
+        };
+        let mut ranknet = RankNet::new(config);
+        let initial_score = ranknet.evaluate(&validate_set);
+        ranknet.learn().unwrap();
+        assert!(ranknet.evaluate(&validate_set) > initial_score);
+
+        // The saved model gives the same network.
+        let mut buf = Vec::new();
+        ranknet.network().save(&mut buf).unwrap();
+        let network = Network::load(buf.as_slice()).unwrap();
+        assert_eq!(&network, ranknet.network());
+    }
+
+    #[test]
+    fn test_network_read_errors() {
+        // Counts too large for the memory run out of lines instead.
+        let s = format!(
+            "rforests-model 1\nmodel ranknet\nfeatures 46\nlayers 1
+layer 46 {} linear\n{}\n",
+            ::std::usize::MAX,
+            vec!["0"; 47].join(" ")
+        );
+        match Network::load(s.as_bytes()) {
+            Err(Error::Parse { .. }) => {}
+            _ => panic!("Expected a parse error"),
+        }
+    }
+}
@@ -1,5 +1,8 @@
 pub type Result<T> = ::std::result::Result<T, Box<::std::error::Error>>;
 
+/// Result of loading a data file, see `Error`.
+pub type ParseResult<T> = ::std::result::Result<T, Error>;
+
 /// Type for feature id.
 pub type Id = usize;
 
@@ -8,7 +11,159 @@ pub type Value = f64;
 
 use scoped_threadpool::Pool;
 use std::sync::Mutex;
+use std::fmt;
+use std::io;
 use num_cpus;
 lazy_static! {
     pub static ref POOL: Mutex<Pool> = Mutex::new(Pool::new(num_cpus::get() as u32));
 }
+
+/// Errors of loading data files and configuring the learning.
+///
+/// The loading functions return this type, so that callers can tell
+/// a missing file from a malformed one. Functions returning `Result`
+/// box it, and it can be recovered with `downcast_ref`.
+#[derive(Debug)]
+pub enum Error {
+    /// Failed to read or write a file.
+    Io {
+        file: Option<String>,
+        error: io::Error,
+    },
+    /// Malformed content of a file. `line` is 1-based, and `token` is
+    /// the offending part of the line.
+    Parse {
+        file: Option<String>,
+        line: Option<usize>,
+        token: Option<String>,
+        message: String,
+    },
+    /// Invalid parameters.
+    Config(String),
+}
+
+impl Error {
+    /// Creates a parse error of the offending token.
+    pub fn parse<S: Into<String>>(token: &str, message: S) -> Error {
+        Error::Parse {
+            file: None,
+            line: None,
+            token: Some(token.to_string()),
+            message: message.into(),
+        }
+    }
+
+    /// Sets the line number of a parse error.
+    pub fn at_line(self, line_number: usize) -> Error {
+        match self {
+            Error::Parse {
+                file,
+                token,
+                message,
+                ..
+            } => Error::Parse {
+                file: file,
+                line: Some(line_number),
+                token: token,
+                message: message,
+            },
+            e => e,
+        }
+    }
+
+    /// Sets the file name of an IO or parse error.
+    pub fn in_file(self, path: &str) -> Error {
+        let path = Some(path.to_string());
+        match self {
+            Error::Io { error, .. } => Error::Io {
+                file: path,
+                error: error,
+            },
+            Error::Parse {
+                line,
+                token,
+                message,
+                ..
+            } => Error::Parse {
+                file: path,
+                line: line,
+                token: token,
+                message: message,
+            },
+            e => e,
+        }
+    }
+
+    /// Returns the exit code of the process failing with this error.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Error::Io { .. } => 2,
+            Error::Parse { .. } => 3,
+            Error::Config(_) => 4,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io {
+                ref file,
+                ref error,
+            } => match *file {
+                Some(ref file) => write!(f, "{}: {}", file, error),
+                None => write!(f, "{}", error),
+            },
+            Error::Parse {
+                ref file,
+                line,
+                ref token,
+                ref message,
+            } => {
+                match (file, line) {
+                    (&Some(ref file), Some(line)) => {
+                        write!(f, "{}:{}: ", file, line)?
+                    }
+                    (&Some(ref file), None) => write!(f, "{}: ", file)?,
+                    (&None, Some(line)) => write!(f, "line {}: ", line)?,
+                    (&None, None) => {}
+                }
+                write!(f, "{}", message)?;
+                if let Some(ref token) = *token {
+                    write!(f, ": \"{}\"", token)?;
+                }
+                Ok(())
+            }
+            Error::Config(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io { .. } => "IO error",
+            Error::Parse { .. } => "parse error",
+            Error::Config(_) => "invalid configuration",
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io {
+            file: None,
+            error: error,
+        }
+    }
+}
+
+/// Reports the error on stderr and exits the process, with the exit
+/// code of the error if it is an `Error`, or 1 otherwise. The error is
+/// printed whatever the log filter is.
+pub fn exit_on_error<E: Into<Box<::std::error::Error>>>(error: E) -> ! {
+    let error = error.into();
+    eprintln!("error: {}", error);
+    let code = error.downcast_ref::<Error>().map_or(1, |e| e.exit_code());
+    ::std::process::exit(code)
+}